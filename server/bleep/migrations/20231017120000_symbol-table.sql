@@ -0,0 +1,18 @@
+CREATE TABLE symbols (
+    file_hash TEXT NOT NULL,
+    repo_ref TEXT NOT NULL,
+    relative_path TEXT NOT NULL,
+    lang TEXT NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT,
+    role TEXT NOT NULL,
+    is_top_level BOOLEAN NOT NULL,
+    start_byte INTEGER NOT NULL,
+    end_byte INTEGER NOT NULL,
+    start_line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL
+);
+
+CREATE INDEX symbols_name ON symbols (name);
+CREATE INDEX symbols_file_hash ON symbols (file_hash);
+CREATE INDEX symbols_repo_ref ON symbols (repo_ref);
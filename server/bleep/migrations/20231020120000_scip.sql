@@ -0,0 +1,21 @@
+CREATE TABLE scip_indexes (
+    repo_ref TEXT PRIMARY KEY NOT NULL,
+    commit_hash TEXT,
+    tool TEXT NOT NULL,
+    source_mtime INTEGER
+);
+
+CREATE TABLE scip_occurrences (
+    repo_ref TEXT NOT NULL,
+    relative_path TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    is_definition BOOLEAN NOT NULL,
+    position_encoding INTEGER NOT NULL,
+    start_line INTEGER NOT NULL,
+    start_character INTEGER NOT NULL,
+    end_line INTEGER NOT NULL,
+    end_character INTEGER NOT NULL
+);
+
+CREATE INDEX scip_occurrences_path ON scip_occurrences (repo_ref, relative_path);
+CREATE INDEX scip_occurrences_symbol ON scip_occurrences (repo_ref, symbol);
@@ -1,10 +1,11 @@
 use either::Either;
 use tokio::sync::OwnedSemaphorePermit;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     cache::FileCache,
     indexes,
+    intelligence::scip,
     remotes::RemoteError,
     repo::{Backend, RepoError, RepoMetadata, RepoRef, Repository, SyncStatus},
     Application,
@@ -173,6 +174,10 @@ impl SyncHandle {
                     repo.sync_done_with(self.new_branch_filters.as_ref(), state)
                 });
 
+                if let Err(err) = scip::ingest_configured(&self.app, &self.reporef).await {
+                    warn!(?err, ?self.reporef, "failed to ingest SCIP index");
+                }
+
                 // technically `sync_done_with` does this, but we want to send notifications
                 self.set_status(|_| SyncStatus::Done)
             }
@@ -118,6 +118,8 @@ impl<'a> FileCache<'a> {
         let mut tx = self.db.begin().await?;
         self.delete_files(&mut tx).await?;
         self.delete_chunks(&mut tx).await?;
+        self.delete_symbols(&mut tx).await?;
+        self.delete_scip(&mut tx).await?;
         tx.commit().await?;
 
         Ok(())
@@ -149,6 +151,40 @@ impl<'a> FileCache<'a> {
         Ok(())
     }
 
+    async fn delete_symbols(&self, tx: &mut sqlx::Transaction<'_, Sqlite>) -> anyhow::Result<()> {
+        let repo_str = self.reporef.to_string();
+        sqlx::query! {
+            "DELETE FROM symbols \
+                 WHERE repo_ref = ?",
+            repo_str
+        }
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_scip(&self, tx: &mut sqlx::Transaction<'_, Sqlite>) -> anyhow::Result<()> {
+        let repo_str = self.reporef.to_string();
+        sqlx::query! {
+            "DELETE FROM scip_indexes \
+                 WHERE repo_ref = ?",
+            repo_str
+        }
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query! {
+            "DELETE FROM scip_occurrences \
+                 WHERE repo_ref = ?",
+            repo_str
+        }
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn chunks_for_file(&self, key: &'a str) -> ChunkCache<'a> {
         ChunkCache::for_file(self.db, self.reporef, key).await
     }
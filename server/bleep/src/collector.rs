@@ -1,5 +1,9 @@
 mod bytes_filter;
+mod facet;
+mod fields_filter;
 mod frequency;
 
 pub use bytes_filter::BytesFilterCollector;
+pub use facet::{FacetCollector, FacetField};
+pub use fields_filter::FieldsFilterCollector;
 pub use frequency::FrequencyCollector;
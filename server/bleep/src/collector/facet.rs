@@ -0,0 +1,159 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::{BytesFastFieldReader, Column},
+    schema::Field,
+    Score, SegmentReader,
+};
+
+/// The number of values that are kept for each facet, the most frequent first.
+const MAX_VALUES: usize = 100;
+
+/// How the values of a facet are read from a fast field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FacetField {
+    /// A single value per document
+    Bytes(Field),
+
+    /// A newline-separated list of values per document
+    Lines(Field),
+
+    /// The first component of a path, for documents nested in a directory
+    TopDirectory(Field),
+
+    /// A single number per document
+    U64(Field),
+}
+
+/// Count the matching documents for every value of a facet.
+///
+/// Like `FrequencyCollector`, but values are decoded into strings, and documents with
+/// several values are counted once for each. Only the `MAX_VALUES` most frequent values are
+/// returned.
+pub struct FacetCollector(pub FacetField);
+
+impl Collector for FacetCollector {
+    type Fruit = HashMap<String, usize>;
+
+    type Child = FacetSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _segment_local_id: u32,
+        segment_reader: &SegmentReader,
+    ) -> tantivy::Result<FacetSegmentCollector> {
+        let fast_fields = segment_reader.fast_fields();
+        let reader = match self.0 {
+            FacetField::Bytes(field) => FacetReader::Bytes(fast_fields.bytes(field)?),
+            FacetField::Lines(field) => FacetReader::Lines(fast_fields.bytes(field)?),
+            FacetField::TopDirectory(field) => FacetReader::TopDirectory(fast_fields.bytes(field)?),
+            FacetField::U64(field) => FacetReader::U64(fast_fields.u64(field)?),
+        };
+
+        Ok(FacetSegmentCollector {
+            reader,
+            counts: HashMap::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, segments: Vec<Self::Fruit>) -> tantivy::Result<Self::Fruit> {
+        let mut counts = HashMap::<String, usize>::new();
+        for segment in segments {
+            for (k, v) in segment {
+                *counts.entry(k).or_default() += v;
+            }
+        }
+
+        if counts.len() > MAX_VALUES {
+            let mut values = counts.into_iter().collect::<Vec<_>>();
+            values.sort_unstable_by(|(a, m), (b, n)| n.cmp(m).then_with(|| a.cmp(b)));
+            values.truncate(MAX_VALUES);
+            counts = values.into_iter().collect();
+        }
+
+        Ok(counts)
+    }
+}
+
+enum FacetReader {
+    Bytes(BytesFastFieldReader),
+    Lines(BytesFastFieldReader),
+    TopDirectory(BytesFastFieldReader),
+    U64(Arc<dyn Column<u64>>),
+}
+
+pub struct FacetSegmentCollector {
+    reader: FacetReader,
+    counts: HashMap<String, usize>,
+}
+
+impl SegmentCollector for FacetSegmentCollector {
+    type Fruit = HashMap<String, usize>;
+
+    fn collect(&mut self, doc: u32, _score: Score) {
+        let Self { reader, counts } = self;
+
+        match reader {
+            FacetReader::Bytes(reader) => count(counts, reader.get_bytes(doc)),
+            FacetReader::Lines(reader) => {
+                for value in reader.get_bytes(doc).split(|&b| b == b'\n') {
+                    count(counts, value);
+                }
+            }
+            FacetReader::TopDirectory(reader) => {
+                let path = reader.get_bytes(doc);
+                if let Some(i) = path.iter().position(|&b| b == b'/') {
+                    count(counts, &path[..i]);
+                }
+            }
+            FacetReader::U64(reader) => count(counts, reader.get_val(doc).to_string().as_bytes()),
+        }
+    }
+
+    fn harvest(self) -> <Self as SegmentCollector>::Fruit {
+        self.counts
+    }
+}
+
+fn count(counts: &mut HashMap<String, usize>, value: &[u8]) {
+    if value.is_empty() {
+        return;
+    }
+
+    let value = String::from_utf8_lossy(value);
+    match counts.get_mut(value.as_ref()) {
+        Some(count) => *count += 1,
+        None => {
+            counts.insert(value.into_owned(), 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_most_frequent() {
+        let collector = FacetCollector(FacetField::U64(Field::from_field_id(0)));
+        let segment = (0..MAX_VALUES * 2)
+            .map(|i| (i.to_string(), i))
+            .collect::<HashMap<_, _>>();
+
+        let counts = collector
+            .merge_fruits(vec![segment.clone(), segment])
+            .unwrap();
+
+        assert_eq!(counts.len(), MAX_VALUES);
+        assert_eq!(
+            counts[&(MAX_VALUES * 2 - 1).to_string()],
+            MAX_VALUES * 4 - 2
+        );
+        assert!(!counts.contains_key("0"));
+    }
+}
@@ -0,0 +1,119 @@
+// a version of BytesFilterCollector that reads several fields of each document, for filters that
+// depend on more than one field, such as structural searches that parse the content in the
+// language of the document
+
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::fastfield::BytesFastFieldReader;
+use tantivy::schema::Field;
+use tantivy::{Score, SegmentReader, TantivyError};
+
+pub struct FieldsFilterCollector<TCollector, TPredicate, const N: usize>
+where
+    TPredicate: 'static + Clone,
+{
+    fields: [Field; N],
+    collector: TCollector,
+    predicate: TPredicate,
+}
+
+impl<TCollector, TPredicate, const N: usize> FieldsFilterCollector<TCollector, TPredicate, N>
+where
+    TCollector: Collector + Send + Sync,
+    TPredicate: Fn([&[u8]; N]) -> bool + Send + Sync + Clone,
+{
+    /// Create a new FieldsFilterCollector.
+    ///
+    /// The predicate is called with the values of `fields` for each document, in the same order.
+    pub fn new(
+        fields: [Field; N],
+        predicate: TPredicate,
+        collector: TCollector,
+    ) -> FieldsFilterCollector<TCollector, TPredicate, N> {
+        FieldsFilterCollector {
+            fields,
+            predicate,
+            collector,
+        }
+    }
+}
+
+impl<TCollector, TPredicate, const N: usize> Collector
+    for FieldsFilterCollector<TCollector, TPredicate, N>
+where
+    TCollector: Collector + Send + Sync,
+    TPredicate: 'static + Fn([&[u8]; N]) -> bool + Send + Sync + Clone,
+{
+    type Fruit = TCollector::Fruit;
+
+    type Child = FieldsFilterSegmentCollector<TCollector::Child, TPredicate, N>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: u32,
+        segment_reader: &SegmentReader,
+    ) -> tantivy::Result<FieldsFilterSegmentCollector<TCollector::Child, TPredicate, N>> {
+        let schema = segment_reader.schema();
+        let mut readers = Vec::with_capacity(N);
+        for field in self.fields {
+            let field_entry = schema.get_field_entry(field);
+            if !field_entry.is_fast() {
+                return Err(TantivyError::SchemaError(format!(
+                    "Field {:?} is not a fast field.",
+                    field_entry.name()
+                )));
+            }
+
+            readers.push(segment_reader.fast_fields().bytes(field)?);
+        }
+
+        let segment_collector = self
+            .collector
+            .for_segment(segment_local_id, segment_reader)?;
+
+        Ok(FieldsFilterSegmentCollector {
+            readers: readers.try_into().ok().unwrap(),
+            segment_collector,
+            predicate: self.predicate.clone(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.collector.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<TCollector::Child as SegmentCollector>::Fruit>,
+    ) -> tantivy::Result<TCollector::Fruit> {
+        self.collector.merge_fruits(segment_fruits)
+    }
+}
+
+pub struct FieldsFilterSegmentCollector<TSegmentCollector, TPredicate, const N: usize>
+where
+    TPredicate: 'static,
+{
+    readers: [BytesFastFieldReader; N],
+    segment_collector: TSegmentCollector,
+    predicate: TPredicate,
+}
+
+impl<TSegmentCollector, TPredicate, const N: usize> SegmentCollector
+    for FieldsFilterSegmentCollector<TSegmentCollector, TPredicate, N>
+where
+    TSegmentCollector: SegmentCollector,
+    TPredicate: 'static + Fn([&[u8]; N]) -> bool + Send + Sync,
+{
+    type Fruit = TSegmentCollector::Fruit;
+
+    fn collect(&mut self, doc: u32, score: Score) {
+        let values = std::array::from_fn(|i| self.readers[i].get_bytes(doc));
+        if (self.predicate)(values) {
+            self.segment_collector.collect(doc, score)
+        }
+    }
+
+    fn harvest(self) -> <TSegmentCollector as SegmentCollector>::Fruit {
+        self.segment_collector.harvest()
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{semantic::chunk::OverlapStrategy, state::StateSource};
+use crate::{query::ranking::RankingConfig, semantic::chunk::OverlapStrategy, state::StateSource};
 use anyhow::{Context, Result};
 use clap::Parser;
 
@@ -26,11 +26,22 @@ pub struct Configuration {
     /// Directory to store all persistent state
     pub index_dir: PathBuf,
 
+    #[clap(long)]
+    /// Directory of SCIP indexes for precise code navigation, named after the repositories they
+    /// index, as in `<scip_dir>/github.com/org/repo.scip`
+    pub scip_dir: Option<PathBuf>,
+
     #[clap(long, default_value_t = false)]
     #[serde(skip)]
     /// Quit after indexing the specified repos
     pub index_only: bool,
 
+    #[clap(long, default_value_t = false)]
+    #[serde(skip)]
+    /// Serve code navigation over the Language Server Protocol on stdin and stdout, instead of
+    /// starting the webserver
+    pub lsp: bool,
+
     #[clap(long, default_value_t = false)]
     #[serde(default)]
     /// Disable periodic reindexing, and `git pull` on remote repositories.
@@ -126,6 +137,13 @@ pub struct Configuration {
     /// Chunking strategy
     pub overlap: Option<OverlapStrategy>,
 
+    //
+    // Search ranking
+    //
+    #[clap(flatten)]
+    #[serde(default)]
+    pub ranking: RankingConfig,
+
     //
     // Installation-specific values
     //
@@ -227,8 +245,12 @@ impl Configuration {
 
             index_dir: right_if_default!(b.index_dir, a.index_dir, default_index_dir()),
 
+            scip_dir: b.scip_dir.or(a.scip_dir),
+
             index_only: b.index_only | a.index_only,
 
+            lsp: b.lsp | a.lsp,
+
             disable_background: b.disable_background | a.disable_background,
 
             disable_fsevents: b.disable_fsevents | a.disable_fsevents,
@@ -259,6 +281,8 @@ impl Configuration {
 
             overlap: b.overlap.or(a.overlap),
 
+            ranking: right_if_default!(b.ranking, a.ranking, Default::default()),
+
             frontend_dist: b.frontend_dist.or(a.frontend_dist),
 
             qdrant_url: b.qdrant_url.or(a.qdrant_url),
@@ -7,7 +7,11 @@ use tracing::{debug, warn};
 use crate::Configuration;
 
 mod query_log;
+mod scip;
+mod symbols;
 pub use query_log::QueryLog;
+pub use scip::{ScipIndex, ScipOccurrence, ScipTable};
+pub use symbols::{SymbolEntry, SymbolMatch, SymbolRole, SymbolTable};
 
 pub type SqlDb = Arc<SqlitePool>;
 
@@ -0,0 +1,183 @@
+//! Occurrences from precise SCIP indexes, uploaded for a repository at a given commit.
+//!
+//! Each repository has at most one SCIP index, which is replaced wholesale on every upload.
+//! Positions are kept as the indexer wrote them, and converted to byte offsets when read, against
+//! the contents of the indexed files.
+
+use sqlx::{QueryBuilder, Sqlite};
+
+/// The number of occurrences inserted per statement, as SQLite limits the number of parameters
+/// bound to a single statement.
+const INSERT_BATCH: usize = 100;
+
+/// The SCIP index of a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScipIndex {
+    /// The commit that was indexed, if it was given when uploading the index
+    pub commit_hash: Option<String>,
+
+    /// The name of the indexer that produced the index, such as `rust-analyzer`
+    pub tool: String,
+
+    /// The modification time of the file the index was ingested from, in milliseconds since the
+    /// unix epoch, for indexes read from the configured SCIP directory
+    pub source_mtime: Option<i64>,
+}
+
+/// A single occurrence of a symbol in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScipOccurrence {
+    pub relative_path: String,
+
+    /// The SCIP symbol, such as `rust-analyzer cargo bleep 0.1.0 indexes/Indexes#`
+    pub symbol: String,
+    pub is_definition: bool,
+
+    /// The SCIP `PositionEncoding` that characters are counted in
+    pub position_encoding: i32,
+
+    /// The zero-based line and character where the occurrence starts
+    pub start: (usize, usize),
+
+    /// The zero-based line and character where the occurrence ends
+    pub end: (usize, usize),
+}
+
+pub struct ScipTable<'a> {
+    db: &'a super::SqlitePool,
+}
+
+impl<'a> ScipTable<'a> {
+    pub fn new(db: &'a super::SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Replace the SCIP index of a repository.
+    pub async fn insert_index(
+        &self,
+        repo_ref: &str,
+        index: &ScipIndex,
+        occurrences: &[ScipOccurrence],
+    ) -> anyhow::Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query!("DELETE FROM scip_indexes WHERE repo_ref = ?", repo_ref)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query!("DELETE FROM scip_occurrences WHERE repo_ref = ?", repo_ref)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query!(
+            "INSERT INTO scip_indexes (repo_ref, commit_hash, tool, source_mtime) \
+             VALUES (?, ?, ?, ?)",
+            repo_ref,
+            index.commit_hash,
+            index.tool,
+            index.source_mtime,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        for batch in occurrences.chunks(INSERT_BATCH) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "INSERT INTO scip_occurrences \
+                 (repo_ref, relative_path, symbol, is_definition, position_encoding, \
+                 start_line, start_character, end_line, end_character) ",
+            );
+
+            query.push_values(batch, |mut row, occurrence| {
+                row.push_bind(repo_ref)
+                    .push_bind(&occurrence.relative_path)
+                    .push_bind(&occurrence.symbol)
+                    .push_bind(occurrence.is_definition)
+                    .push_bind(occurrence.position_encoding)
+                    .push_bind(occurrence.start.0 as i64)
+                    .push_bind(occurrence.start.1 as i64)
+                    .push_bind(occurrence.end.0 as i64)
+                    .push_bind(occurrence.end.1 as i64);
+            });
+
+            query.build().execute(&mut tx).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The SCIP index of a repository, if one was uploaded.
+    pub async fn index(&self, repo_ref: &str) -> anyhow::Result<Option<ScipIndex>> {
+        let rec = sqlx::query!(
+            "SELECT commit_hash, tool, source_mtime FROM scip_indexes WHERE repo_ref = ?",
+            repo_ref,
+        )
+        .fetch_optional(self.db)
+        .await?;
+
+        Ok(rec.map(|r| ScipIndex {
+            commit_hash: r.commit_hash,
+            tool: r.tool,
+            source_mtime: r.source_mtime,
+        }))
+    }
+
+    /// All occurrences in a file.
+    pub async fn occurrences_in(
+        &self,
+        repo_ref: &str,
+        relative_path: &str,
+    ) -> anyhow::Result<Vec<ScipOccurrence>> {
+        let recs = sqlx::query!(
+            "SELECT relative_path, symbol, is_definition, position_encoding, \
+             start_line, start_character, end_line, end_character FROM scip_occurrences \
+             WHERE repo_ref = ? AND relative_path = ?",
+            repo_ref,
+            relative_path,
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        Ok(recs
+            .into_iter()
+            .map(|r| ScipOccurrence {
+                relative_path: r.relative_path,
+                symbol: r.symbol,
+                is_definition: r.is_definition,
+                position_encoding: r.position_encoding as i32,
+                start: (r.start_line as usize, r.start_character as usize),
+                end: (r.end_line as usize, r.end_character as usize),
+            })
+            .collect())
+    }
+
+    /// All occurrences of a symbol, across the files of a repository.
+    pub async fn occurrences_of(
+        &self,
+        repo_ref: &str,
+        symbol: &str,
+    ) -> anyhow::Result<Vec<ScipOccurrence>> {
+        let recs = sqlx::query!(
+            "SELECT relative_path, symbol, is_definition, position_encoding, \
+             start_line, start_character, end_line, end_character FROM scip_occurrences \
+             WHERE repo_ref = ? AND symbol = ?",
+            repo_ref,
+            symbol,
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        Ok(recs
+            .into_iter()
+            .map(|r| ScipOccurrence {
+                relative_path: r.relative_path,
+                symbol: r.symbol,
+                is_definition: r.is_definition,
+                position_encoding: r.position_encoding as i32,
+                start: (r.start_line as usize, r.start_character as usize),
+                end: (r.end_line as usize, r.end_character as usize),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,277 @@
+//! A persisted table of the symbols in every indexed file, across all repositories.
+//!
+//! Rows are written at index time from each file's scope graph, and keyed by the same hash as the
+//! file's tantivy document, so stale rows are dropped alongside stale documents.
+
+use std::ops::Range;
+
+use crate::intelligence::{NodeKind, ScopeGraph};
+
+/// The role a name plays where it appears in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolRole {
+    Definition,
+    Import,
+
+    /// A reference that could not be resolved within its own file.
+    Reference,
+}
+
+impl SymbolRole {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Definition => "definition",
+            Self::Import => "import",
+            Self::Reference => "reference",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "definition" => Some(Self::Definition),
+            "import" => Some(Self::Import),
+            "reference" => Some(Self::Reference),
+            _ => None,
+        }
+    }
+}
+
+/// A single symbol in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolEntry {
+    pub name: String,
+    pub kind: Option<String>,
+    pub role: SymbolRole,
+    pub is_top_level: bool,
+    pub bytes: Range<usize>,
+    pub lines: Range<usize>,
+}
+
+impl SymbolEntry {
+    /// Collect the entries of a file's scope graph.
+    ///
+    /// References that resolve to a definition or import in the same file are left out, as they
+    /// can never point to another file.
+    pub fn from_scope_graph(graph: &ScopeGraph, src: &[u8]) -> Vec<Self> {
+        graph
+            .graph
+            .node_indices()
+            .filter_map(|idx| {
+                let (role, name) = match &graph.graph[idx] {
+                    NodeKind::Def(d) => (SymbolRole::Definition, d.name(src)),
+                    NodeKind::Import(i) => (SymbolRole::Import, i.name(src)),
+                    NodeKind::Ref(r)
+                        if graph.definitions(idx).next().is_none()
+                            && graph.imports(idx).next().is_none() =>
+                    {
+                        (SymbolRole::Reference, r.name(src))
+                    }
+                    _ => return None,
+                };
+
+                let range = graph.graph[idx].range();
+                Some(Self {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    kind: graph.symbol_name_of(idx).map(ToOwned::to_owned),
+                    role,
+                    is_top_level: graph.is_top_level(idx),
+                    bytes: range.start.byte..range.end.byte,
+                    lines: range.start.line..range.end.line,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A symbol entry, along with the file it was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolMatch {
+    pub repo_ref: String,
+    pub relative_path: String,
+    pub lang: String,
+    pub entry: SymbolEntry,
+}
+
+pub struct SymbolTable<'a> {
+    db: &'a super::SqlitePool,
+}
+
+impl<'a> SymbolTable<'a> {
+    pub fn new(db: &'a super::SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Replace all entries for the file with the given hash.
+    pub async fn insert_file(
+        &self,
+        file_hash: &str,
+        repo_ref: &str,
+        relative_path: &str,
+        lang: &str,
+        entries: &[SymbolEntry],
+    ) -> anyhow::Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query!("DELETE FROM symbols WHERE file_hash = ?", file_hash)
+            .execute(&mut tx)
+            .await?;
+
+        for entry in entries {
+            let role = entry.role.as_str();
+            let start_byte = entry.bytes.start as i64;
+            let end_byte = entry.bytes.end as i64;
+            let start_line = entry.lines.start as i64;
+            let end_line = entry.lines.end as i64;
+
+            sqlx::query!(
+                "INSERT INTO symbols \
+                 (file_hash, repo_ref, relative_path, lang, name, kind, role, \
+                 is_top_level, start_byte, end_byte, start_line, end_line) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                file_hash,
+                repo_ref,
+                relative_path,
+                lang,
+                entry.name,
+                entry.kind,
+                role,
+                entry.is_top_level,
+                start_byte,
+                end_byte,
+                start_line,
+                end_line,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Delete the entries of files that are no longer indexed.
+    pub async fn delete_files(&self, file_hashes: &[String]) -> anyhow::Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        for file_hash in file_hashes {
+            sqlx::query!("DELETE FROM symbols WHERE file_hash = ?", file_hash)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Whether the given file has any entries in the table.
+    pub async fn contains_file(&self, repo_ref: &str, relative_path: &str) -> anyhow::Result<bool> {
+        let rec = sqlx::query!(
+            "SELECT file_hash FROM symbols \
+             WHERE repo_ref = ? AND relative_path = ? \
+             LIMIT 1",
+            repo_ref,
+            relative_path,
+        )
+        .fetch_optional(self.db)
+        .await?;
+
+        Ok(rec.is_some())
+    }
+
+    /// Paths of the files in a repository that define or import `name` at the top level.
+    ///
+    /// These are the only files that can take part in repo-wide navigation for that name.
+    pub async fn files_declaring(&self, repo_ref: &str, name: &str) -> anyhow::Result<Vec<String>> {
+        let recs = sqlx::query!(
+            "SELECT DISTINCT relative_path FROM symbols \
+             WHERE repo_ref = ? AND name = ? AND is_top_level AND role != 'reference'",
+            repo_ref,
+            name,
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        Ok(recs.into_iter().map(|r| r.relative_path).collect())
+    }
+
+    /// Top-level definitions of `name`, across all repositories.
+    pub async fn definitions(&self, name: &str) -> anyhow::Result<Vec<SymbolMatch>> {
+        let recs = sqlx::query!(
+            "SELECT repo_ref, relative_path, lang, name, kind, role, is_top_level, \
+             start_byte, end_byte, start_line, end_line FROM symbols \
+             WHERE name = ? AND is_top_level AND role = 'definition'",
+            name,
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        Ok(recs
+            .into_iter()
+            .filter_map(|r| {
+                Some(SymbolMatch {
+                    repo_ref: r.repo_ref,
+                    relative_path: r.relative_path,
+                    lang: r.lang,
+                    entry: SymbolEntry {
+                        name: r.name,
+                        kind: r.kind,
+                        role: SymbolRole::parse(&r.role)?,
+                        is_top_level: r.is_top_level,
+                        bytes: r.start_byte as usize..r.end_byte as usize,
+                        lines: r.start_line as usize..r.end_line as usize,
+                    },
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intelligence::TreeSitterFile;
+
+    #[test]
+    fn entries_from_scope_graph() {
+        let src = "use std::collections::HashMap;\n\
+                   fn parse() {}\n\
+                   fn main() {\n    \
+                       let m = HashMap::new();\n    \
+                       parse();\n    \
+                       helper();\n\
+                   }\n";
+
+        let graph = TreeSitterFile::try_build(src.as_bytes(), "Rust")
+            .and_then(TreeSitterFile::scope_graph)
+            .unwrap();
+
+        let entries = SymbolEntry::from_scope_graph(&graph, src.as_bytes());
+        let find = |name: &str| entries.iter().find(|e| e.name == name);
+
+        let import = find("HashMap").unwrap();
+        assert_eq!(import.role, SymbolRole::Import);
+        assert!(import.is_top_level);
+
+        let parse = find("parse").unwrap();
+        assert_eq!(parse.role, SymbolRole::Definition);
+        assert_eq!(parse.kind.as_deref(), Some("function"));
+        assert!(parse.is_top_level);
+        assert_eq!(parse.lines, 1..1);
+
+        let local = find("m").unwrap();
+        assert_eq!(local.role, SymbolRole::Definition);
+        assert!(!local.is_top_level);
+
+        // `parse` and `HashMap` resolve locally, but `helper` is left dangling
+        let references = entries
+            .iter()
+            .filter(|e| e.role == SymbolRole::Reference)
+            .map(|e| e.name.as_str())
+            .collect::<Vec<_>>();
+        assert!(references.contains(&"helper"));
+        assert!(!references.contains(&"parse"));
+        assert!(!references.contains(&"HashMap"));
+    }
+}
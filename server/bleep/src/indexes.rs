@@ -2,12 +2,12 @@ use std::{fs, ops::Deref, path::Path, sync::Arc};
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use smallvec::SmallVec;
 use tantivy::{
     collector::{Collector, MultiFruit},
+    query::EmptyQuery,
     schema::Schema,
     tokenizer::NgramTokenizer,
-    DocAddress, Document, IndexReader, IndexWriter, Score,
+    DocAddress, Document, IndexReader, IndexWriter, Score, Searcher,
 };
 use tokio::sync::RwLock;
 
@@ -24,7 +24,10 @@ use crate::{
     background::{SyncHandle, SyncPipes},
     cache::FileCache,
     db::SqlDb,
-    query::parser::Query,
+    query::{
+        cursor::{Cursor, CursorCollector, CursorError},
+        parser::{Query, QueryExpr},
+    },
     repo::{RepoError, RepoMetadata, RepoRef, Repository},
     semantic::Semantic,
     state::RepositoryPool,
@@ -121,7 +124,7 @@ impl Indexes {
                 config.max_threads,
             )?,
             file: Indexer::create(
-                File::new(sql, semantic),
+                File::new(sql, semantic, config.ranking.clone()),
                 config.index_path("content").as_ref(),
                 config.buffer_size,
                 config.max_threads,
@@ -166,18 +169,30 @@ pub trait DocumentRead: Send + Sync {
     type Schema;
     type Document;
 
+    /// Return whether this reader can process a single term of a query, which is a conjunction
+    /// of labels.
+    fn term_matches(&self, query: &Query<'_>) -> bool;
+
     /// Return whether this reader can process this query.
-    fn query_matches(&self, query: &Query<'_>) -> bool;
+    ///
+    /// Every branch of an `or` is considered on its own, so `symbol:foo or repo:bar` can be
+    /// processed by both the content and the repo readers.
+    fn query_matches(&self, expr: &QueryExpr<'_>) -> bool {
+        expr.matches(&|q| self.term_matches(q))
+    }
+
+    /// Drop the branches of a query that this reader cannot process.
+    fn select<'a>(&self, expr: &QueryExpr<'a>) -> QueryExpr<'a> {
+        expr.select(&|q| self.term_matches(q))
+    }
 
-    /// Compile a set of parsed queries into a single `tantivy` query.
-    fn compile<'a, I>(
+    /// Compile a parsed query into a single `tantivy` query.
+    fn compile<'a>(
         &self,
         schema: &Self::Schema,
-        queries: I,
+        expr: &'a QueryExpr<'a>,
         index: &tantivy::Index,
-    ) -> Result<Box<dyn tantivy::query::Query>>
-    where
-        I: Iterator<Item = &'a Query<'a>>;
+    ) -> Result<Box<dyn tantivy::query::Query>>;
 
     /// Read a tantivy document into the specified output type.
     fn read_document(&self, schema: &Self::Schema, doc: Document) -> Self::Document;
@@ -278,28 +293,58 @@ impl<T: Indexable> Indexer<T> {
         Ok(instance)
     }
 
-    pub async fn query<'a, R, I, C>(
+    /// Run a query with the given reader, which should already be narrowed down to the branches
+    /// the reader can process, see [`DocumentRead::select`].
+    ///
+    /// Queries that the reader cannot process at all match nothing.
+    pub async fn query<'a, R, C>(
         &'a self,
-        queries: I,
+        expr: &QueryExpr<'_>,
         doc_reader: &'a R,
         collector: C,
     ) -> Result<SearchResults<'_, R::Document>>
     where
-        I: Iterator<Item = &'a Query<'a>> + Send,
         C: Collector<Fruit = (Vec<(Score, DocAddress)>, MultiFruit)>,
         R: DocumentRead<Schema = T>,
     {
-        let searcher = self.reader.read().await.searcher();
-        let queries = queries
-            .filter(|q| doc_reader.query_matches(q))
-            .collect::<SmallVec<[_; 2]>>();
-        let compiled_query =
-            doc_reader.compile(&self.source, queries.iter().copied(), &self.index)?;
+        let searcher = self.searcher().await;
+        self.query_with_searcher(searcher, expr, doc_reader, collector)
+    }
+
+    /// A searcher over the latest generation of the index.
+    pub async fn searcher(&self) -> Searcher {
+        self.reader.read().await.searcher()
+    }
+
+    /// Run a query like [`Indexer::query`], on a searcher taken beforehand.
+    ///
+    /// Searches that resume after a cursor must run on the searcher the cursor was checked
+    /// against in [`Indexer::resume_after`], or the index may change in between.
+    pub fn query_with_searcher<'a, R, C>(
+        &'a self,
+        searcher: Searcher,
+        expr: &QueryExpr<'_>,
+        doc_reader: &'a R,
+        collector: C,
+    ) -> Result<SearchResults<'_, R::Document>>
+    where
+        C: Collector<Fruit = (Vec<(Score, DocAddress)>, MultiFruit)>,
+        R: DocumentRead<Schema = T>,
+    {
+        let compiled_query = if doc_reader.query_matches(expr) {
+            doc_reader.compile(&self.source, expr, &self.index)?
+        } else {
+            Box::new(EmptyQuery)
+        };
 
         let (top_k, metadata) = searcher
             .search(&compiled_query, &collector)
             .context("failed to execute search query")?;
 
+        let generation = searcher.generation().generation_id();
+        let last_hit = top_k.last().copied();
+        let hit_count = top_k.len();
+
         let iter = top_k.into_iter().map(move |(_score, addr)| {
             let doc = searcher.doc(addr).unwrap();
             doc_reader.read_document(&self.source, doc)
@@ -308,11 +353,42 @@ impl<T: Indexable> Indexer<T> {
         Ok(SearchResults {
             docs: Box::new(iter),
             metadata,
+            generation,
+            last_hit,
+            hit_count,
         })
     }
+
+    /// Wrap a collector so that a search resumes after `cursor`, scoring documents with
+    /// `tweaker`.
+    ///
+    /// This fails if `searcher` is not of the generation the cursor was handed out for, as
+    /// document addresses are no longer comparable.
+    pub fn resume_after<S, C>(
+        &self,
+        searcher: &Searcher,
+        cursor: Option<Cursor>,
+        tweaker: S,
+        collector: C,
+    ) -> Result<CursorCollector<S, C>> {
+        if let Some(cursor) = cursor {
+            anyhow::ensure!(
+                cursor.generation == searcher.generation().generation_id(),
+                CursorError::Stale
+            );
+        }
+
+        Ok(CursorCollector::new(tweaker, cursor, searcher, collector))
+    }
 }
 
 pub struct SearchResults<'a, T> {
     pub docs: Box<dyn Iterator<Item = T> + Sync + Send + 'a>,
     pub metadata: MultiFruit,
+    /// The generation of the index that was searched
+    pub generation: u64,
+    /// The score and address of the last document in `docs`, used to fetch the next page
+    pub last_hit: Option<(Score, DocAddress)>,
+    /// The number of documents in `docs`
+    pub hit_count: usize,
 }
@@ -34,8 +34,9 @@ use super::{
 use crate::{
     background::SyncPipes,
     cache::{FileCache, FileCacheSnapshot},
+    db::{SymbolEntry, SymbolTable},
     intelligence::TreeSitterFile,
-    query::compiler::{case_permutations, trigrams},
+    query::compiler::{case_permutations, kinded_gram, trigrams},
     repo::{iterator::*, RepoMetadata, RepoRef, Repository},
     symbol::SymbolLocations,
 };
@@ -115,7 +116,7 @@ impl Indexable for File {
         info!(?repo.disk_path, "repo file indexing finished, took {:?}", start.elapsed());
 
         // files that are no longer tracked by the git index are to be removed
-        // from the tantivy & qdrant indices
+        // from the tantivy & qdrant indices, and from the symbol table
         let mut qdrant_remove_list = vec![];
         cache_snapshot.retain(|k, v| {
             if !v.fresh {
@@ -126,6 +127,12 @@ impl Indexable for File {
             v.fresh
         });
 
+        if !qdrant_remove_list.is_empty() {
+            SymbolTable::new(&self.sql)
+                .delete_files(&qdrant_remove_list)
+                .await?;
+        }
+
         // batch-delete points from qdrant index
         if !qdrant_remove_list.is_empty() {
             if let Some(semantic) = &self.semantic {
@@ -443,16 +450,23 @@ impl File {
             hash.finalize().to_hex().to_string()
         };
 
+        let last_commit = dir_entry
+            .last_commit()
+            .cloned()
+            .unwrap_or_else(|| LastCommit {
+                unix_secs: repo_metadata.last_commit_unix_secs.unwrap_or(0),
+                ..Default::default()
+            });
+
         let tantivy_hash = {
             let branch_list = dir_entry.branches().unwrap_or_default();
             let mut hash = blake3::Hasher::new();
             hash.update(semantic_hash.as_ref());
             hash.update(branch_list.join("\n").as_bytes());
+            hash.update(last_commit.hash.as_bytes());
             hash.finalize().to_hex().to_string()
         };
 
-        let last_commit = repo_metadata.last_commit_unix_secs.unwrap_or(0);
-
         match dir_entry {
             _ if is_cache_fresh(cache_snapshot, &tantivy_hash, &entry_pathbuf) => {
                 info!("fresh; skipping");
@@ -532,7 +546,7 @@ impl RepoDir {
         relative_path: &Path,
         repo_disk_path: &Path,
         repo_ref: &str,
-        last_commit: u64,
+        last_commit: LastCommit,
         tantivy_cache_key: String,
     ) -> tantivy::schema::Document {
         let relative_path_str = format!("{}/", relative_path.to_string_lossy());
@@ -548,7 +562,11 @@ impl RepoDir {
                 schema.relative_path => relative_path_str,
                 schema.repo_ref => repo_ref,
                 schema.repo_name => repo_name,
-                schema.last_commit_unix_seconds => last_commit,
+                schema.last_commit_unix_seconds => last_commit.unix_secs,
+                schema.last_commit_hash => last_commit.hash,
+                schema.raw_last_commit_author => last_commit.author.as_bytes(),
+                schema.last_commit_author => last_commit.author,
+                schema.raw_branches => branches.as_bytes(),
                 schema.branches => branches,
                 schema.is_directory => true,
                 schema.unique_hash => tantivy_cache_key,
@@ -561,6 +579,7 @@ impl RepoDir {
                 schema.avg_line_length => f64::default(),
                 schema.symbol_locations => bincode::serialize(&SymbolLocations::default()).unwrap(),
                 schema.symbols => String::default(),
+                schema.raw_symbols => Vec::<u8>::default(),
         )
     }
 }
@@ -577,7 +596,7 @@ impl RepoFile {
         tantivy_cache_key: String,
         entry_pathbuf: &Path,
         repo_ref: &str,
-        last_commit: u64,
+        last_commit: LastCommit,
         repo_metadata: &RepoMetadata,
         file_cache: &FileCache,
     ) -> Option<tantivy::schema::Document> {
@@ -608,8 +627,8 @@ impl RepoFile {
         };
 
         // flatten the list of symbols into a string with just text
-        let symbols = symbol_locations
-            .list()
+        let symbol_list = symbol_locations.list();
+        let symbols = symbol_list
             .iter()
             .map(|sym| self.buffer[sym.range.start.byte..sym.range.end.byte].to_owned())
             .collect::<HashSet<_>>()
@@ -617,6 +636,47 @@ impl RepoFile {
             .collect::<Vec<_>>()
             .join("\n");
 
+        // persist the names defined, imported and used in this file to the symbol table
+        let symbol_entries = symbol_locations
+            .scope_graph()
+            .map(|graph| SymbolEntry::from_scope_graph(graph, self.buffer.as_bytes()))
+            .unwrap_or_default();
+
+        // the same list with the kind of every symbol, for post-filtering searches like
+        // `-symbol:function:parse`
+        let raw_symbols = symbol_list
+            .iter()
+            .map(|sym| {
+                let name = &self.buffer[sym.range.start.byte..sym.range.end.byte];
+                format!("{}:{name}", sym.kind)
+            })
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // and index the trigrams of each name along with its kind, so that searches like
+        // `symbol:function:parse` only match files defining a function named like `parse`
+        let kinded_symbols = symbol_list
+            .iter()
+            .flat_map(|sym| {
+                let name = &self.buffer[sym.range.start.byte..sym.range.end.byte];
+                trigrams(name)
+                    .filter(|gram| gram.chars().count() == 3)
+                    .map(|gram| kinded_gram(&sym.kind, &gram))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<HashSet<_>>();
+
+        // and collect the kinds of symbols, for searches like `kind:struct`
+        let symbol_kinds = symbol_list
+            .into_iter()
+            .map(|sym| sym.kind)
+            .collect::<HashSet<_>>();
+
+        // the size on disk, before we touch the buffer
+        let byte_size = self.buffer.len() as u64;
+
         // add an NL if this file is not NL-terminated
         if !self.buffer.ends_with('\n') {
             self.buffer += "\n";
@@ -634,7 +694,26 @@ impl RepoFile {
             return None;
         }
 
-        let lines_avg = self.buffer.len() as f64 / self.buffer.lines().count() as f64;
+        let line_count = self.buffer.lines().count();
+        let file_class =
+            FileClass::classify(relative_path, &self.buffer, &repo_metadata.attributes);
+        let lines_avg = self.buffer.len() as f64 / line_count as f64;
+
+        if !symbol_entries.is_empty() {
+            let result = tokio::task::block_in_place(|| {
+                Handle::current().block_on(SymbolTable::new(&schema.sql).insert_file(
+                    &tantivy_cache_key,
+                    repo_ref,
+                    &relative_path_str,
+                    lang_str,
+                    &symbol_entries,
+                ))
+            });
+
+            if let Err(err) = result {
+                warn!(%err, "failed to update symbol table");
+            }
+        }
 
         if let Some(semantic) = &schema.semantic {
             tokio::task::block_in_place(|| {
@@ -654,7 +733,7 @@ impl RepoFile {
             });
         }
 
-        Some(doc!(
+        let mut doc = doc!(
             schema.raw_content => self.buffer.as_bytes(),
             schema.raw_repo_name => repo_name.as_bytes(),
             schema.raw_relative_path => relative_path_str.as_bytes(),
@@ -667,12 +746,37 @@ impl RepoFile {
             schema.line_end_indices => line_end_indices,
             schema.lang => lang_str.to_ascii_lowercase().as_bytes(),
             schema.avg_line_length => lines_avg,
-            schema.last_commit_unix_seconds => last_commit,
+            schema.last_commit_unix_seconds => last_commit.unix_secs,
+            schema.last_commit_hash => last_commit.hash,
+            schema.raw_last_commit_author => last_commit.author.as_bytes(),
+            schema.last_commit_author => last_commit.author,
             schema.symbol_locations => bincode::serialize(&symbol_locations).unwrap(),
             schema.symbols => symbols,
+            schema.raw_symbols => raw_symbols.as_bytes(),
+            schema.raw_branches => branches.as_bytes(),
             schema.branches => branches,
             schema.is_directory => false,
-        ))
+            schema.byte_size => byte_size,
+            schema.line_count => line_count as u64,
+            schema.file_class => file_class.as_u64(),
+        );
+
+        for kind in symbol_kinds {
+            doc.add_bytes(schema.symbol_kinds, kind.into_bytes());
+        }
+
+        for gram in kinded_symbols {
+            doc.add_bytes(schema.kinded_symbols, gram);
+        }
+
+        if let Some(ext) = relative_path.extension() {
+            doc.add_bytes(
+                schema.extension,
+                ext.to_string_lossy().to_lowercase().into_bytes(),
+            );
+        }
+
+        Some(doc)
     }
 }
 
@@ -1,3 +1,5 @@
+use std::{borrow::Cow, collections::HashSet};
+
 use anyhow::Result;
 use async_trait::async_trait;
 use tantivy::{
@@ -9,9 +11,11 @@ use super::{file::File, repo::Repo, DocumentRead};
 use crate::{
     intelligence::TreeSitterFile,
     query::{
+        ast,
         compiler::Compiler,
-        parser::{self, Query, Target},
+        parser::{self, Literal, Query, QueryExpr, Target},
     },
+    repo::iterator::{FileClass, LastCommit},
     symbol::SymbolLocations,
     text_range::TextRange,
 };
@@ -26,6 +30,8 @@ pub struct ContentDocument {
     pub line_end_indices: Vec<u32>,
     pub symbol_locations: SymbolLocations,
     pub branches: Option<String>,
+    pub last_commit: Option<LastCommit>,
+    pub class: FileClass,
 }
 
 impl ContentDocument {
@@ -58,39 +64,54 @@ impl DocumentRead for ContentReader {
     type Schema = File;
     type Document = ContentDocument;
 
-    fn query_matches(&self, query: &Query<'_>) -> bool {
+    fn term_matches(&self, query: &Query<'_>) -> bool {
+        // Match content or symbol searches, including searches for kinds of symbols like
+        // `kind:struct`
         matches!(
             query,
             Query {
                 open: Some(false) | None,
-                target: Some(Target::Content(..) | Target::Symbol(..)),
                 ..
             }
-        )
+        ) && (query.target.is_some() || !query.symbol_kinds.is_empty())
     }
 
-    fn compile<'a, I>(
+    fn compile<'a>(
         &self,
         schema: &File,
-        queries: I,
+        expr: &'a QueryExpr<'a>,
         tantivy_index: &Index,
-    ) -> Result<Box<dyn tantivy::query::Query>>
-    where
-        I: Iterator<Item = &'a Query<'a>>,
-    {
+    ) -> Result<Box<dyn tantivy::query::Query>> {
         Compiler::new()
             .priority(&[schema.relative_path])
-            .literal(schema.relative_path, |q| q.path.clone())
+            .literals(schema.relative_path, |q| &q.paths)
             .literal(schema.repo_name, |q| q.repo.clone())
-            .literal(schema.branches, |q| q.branch.clone())
-            .byte_string(schema.lang, |q| q.lang.as_ref())
-            .literal(schema.symbols, |q| {
+            .literals(schema.branches, |q| &q.branches)
+            .byte_strings(schema.lang, |q| &q.langs)
+            .literal(schema.last_commit_author, |q| q.author.clone())
+            .range(schema.last_commit_unix_seconds, |q| q.modified.clone())
+            .range(schema.byte_size, |q| q.size.clone())
+            .range(schema.line_count, |q| q.lines.clone())
+            .boolean(schema.is_directory, |q| q.is_dir)
+            .byte_strings(schema.extension, |q| &q.extensions)
+            .values(schema.file_class, file_classes)
+            .fuzzy_literal(schema.symbols, |q| {
                 q.target.as_ref().and_then(Target::symbol).cloned()
             })
-            .literal(schema.content, |q| {
+            .byte_strings(schema.symbol_kinds, |q| &q.symbol_kinds)
+            .kinded_names(schema.kinded_symbols, kinded_name)
+            .fuzzy_literal(schema.content, |q| match q.target.as_ref()? {
+                Target::Content(lit) => Some(lit.clone()),
+                // Structural searches are narrowed down to documents containing the longest
+                // literal in the pattern, before matching the syntax tree.
+                Target::Ast(pattern) => ast::longest_literal(&pattern.clone().unwrap())
+                    .map(|l| Literal::Plain(l.into())),
+                Target::Symbol(_) => None,
+            })
+            .boost(schema.symbols, schema.ranking.symbol_boost, |q| {
                 q.target.as_ref().and_then(Target::content).cloned()
             })
-            .compile(queries, tantivy_index)
+            .compile(expr, tantivy_index)
     }
 
     fn read_document(&self, schema: &File, doc: tantivy::Document) -> Self::Document {
@@ -100,6 +121,12 @@ impl DocumentRead for ContentReader {
         let content = read_text_field(&doc, schema.content);
         let lang = read_lang_field(&doc, schema.lang);
         let branches = read_lang_field(&doc, schema.branches);
+        let last_commit = read_last_commit(&doc, schema);
+        let class = doc
+            .get_first(schema.file_class)
+            .and_then(|v| v.as_u64())
+            .map(FileClass::from_u64)
+            .unwrap_or_default();
 
         let line_end_indices = doc
             .get_first(schema.line_end_indices)
@@ -127,6 +154,8 @@ impl DocumentRead for ContentReader {
             line_end_indices,
             lang,
             branches,
+            last_commit,
+            class,
         }
     }
 }
@@ -138,42 +167,51 @@ impl DocumentRead for FileReader {
     type Document = FileDocument;
     type Schema = File;
 
-    fn query_matches(&self, query: &Query<'_>) -> bool {
+    fn term_matches(&self, query: &Query<'_>) -> bool {
+        // Match language, filename, commit or file metadata searches. Handles searches like:
+        //   lang:Rust
+        //   path:server
+        //   lang:Rust path:server
+        //   author:alice modified:>2024-01-01
+        //   is:dir, ext:rs size:>100k
         matches!(
             query,
-            // Match both language or filename searches. Handles searches like:
-            //   lang:Rust
-            //   path:server
-            //   lang:Rust path:server
             Query {
                 open: Some(false) | None,
                 target: None,
-                lang: Some(..),
-                ..
-            } | Query {
-                open: Some(false) | None,
-                target: None,
-                path: Some(..),
                 ..
             }
-        )
+        ) && query.symbol_kinds.is_empty()
+            && !(query.langs.is_empty()
+                && query.paths.is_empty()
+                && query.author.is_none()
+                && query.modified.is_none()
+                && query.size.is_none()
+                && query.lines.is_none()
+                && query.is_dir.is_none()
+                && query.extensions.is_empty()
+                && query.classes.is_empty())
     }
 
-    fn compile<'a, I>(
+    fn compile<'a>(
         &self,
         schema: &Self::Schema,
-        queries: I,
+        expr: &'a QueryExpr<'a>,
         tantivy_index: &Index,
-    ) -> Result<Box<dyn tantivy::query::Query>>
-    where
-        I: Iterator<Item = &'a Query<'a>>,
-    {
+    ) -> Result<Box<dyn tantivy::query::Query>> {
         Compiler::new()
-            .literal(schema.relative_path, |q| q.path.clone())
+            .literals(schema.relative_path, |q| &q.paths)
             .literal(schema.repo_name, |q| q.repo.clone())
-            .literal(schema.branches, |q| q.branch.clone())
-            .byte_string(schema.lang, |q| q.lang.as_ref())
-            .compile(queries, tantivy_index)
+            .literals(schema.branches, |q| &q.branches)
+            .byte_strings(schema.lang, |q| &q.langs)
+            .literal(schema.last_commit_author, |q| q.author.clone())
+            .range(schema.last_commit_unix_seconds, |q| q.modified.clone())
+            .range(schema.byte_size, |q| q.size.clone())
+            .range(schema.line_count, |q| q.lines.clone())
+            .boolean(schema.is_directory, |q| q.is_dir)
+            .byte_strings(schema.extension, |q| &q.extensions)
+            .values(schema.file_class, file_classes)
+            .compile(expr, tantivy_index)
     }
 
     fn read_document(&self, schema: &Self::Schema, doc: tantivy::Document) -> Self::Document {
@@ -200,31 +238,35 @@ impl DocumentRead for RepoReader {
     type Document = RepoDocument;
     type Schema = Repo;
 
-    fn query_matches(&self, query: &Query<'_>) -> bool {
+    fn term_matches(&self, query: &Query<'_>) -> bool {
         matches!(
             query,
             Query {
                 open: Some(false) | None,
                 repo: Some(..),
-                path: None,
                 target: None,
                 ..
             }
-        )
+        ) && query.paths.is_empty()
+            && query.author.is_none()
+            && query.modified.is_none()
+            && query.size.is_none()
+            && query.lines.is_none()
+            && query.is_dir.is_none()
+            && query.extensions.is_empty()
+            && query.classes.is_empty()
+            && query.symbol_kinds.is_empty()
     }
 
-    fn compile<'a, I>(
+    fn compile<'a>(
         &self,
         schema: &Repo,
-        queries: I,
+        expr: &'a QueryExpr<'a>,
         tantivy_index: &Index,
-    ) -> Result<Box<dyn tantivy::query::Query>>
-    where
-        I: Iterator<Item = &'a Query<'a>>,
-    {
+    ) -> Result<Box<dyn tantivy::query::Query>> {
         Compiler::new()
             .literal(schema.name, |q| q.repo.clone())
-            .compile(queries, tantivy_index)
+            .compile(expr, tantivy_index)
     }
 
     fn read_document(&self, schema: &Repo, doc: tantivy::Document) -> Self::Document {
@@ -250,6 +292,7 @@ pub struct OpenDocument {
     pub lang: Option<String>,
     pub content: String,
     pub line_end_indices: Vec<u32>,
+    pub last_commit: Option<LastCommit>,
 }
 
 #[async_trait]
@@ -257,7 +300,7 @@ impl DocumentRead for OpenReader {
     type Document = OpenDocument;
     type Schema = File;
 
-    fn query_matches(&self, query: &Query<'_>) -> bool {
+    fn term_matches(&self, query: &Query<'_>) -> bool {
         matches!(
             query,
             Query {
@@ -266,29 +309,29 @@ impl DocumentRead for OpenReader {
                 // All open queries must specify at least the repository name. We don't accept regex
                 // inputs for this type of query.
                 repo: Some(parser::Literal::Plain(..)),
-                path: None | Some(parser::Literal::Plain(..)),
 
                 // We want to make sure this query isn't a symbol or content search, which doesn't
                 // make sense for a file open.
                 target: None,
                 ..
             }
-        )
+        ) && query.paths.len() <= 1
+            && query
+                .paths
+                .iter()
+                .all(|p| matches!(p, parser::Literal::Plain(..)))
     }
 
-    fn compile<'a, I>(
+    fn compile<'a>(
         &self,
         schema: &File,
-        queries: I,
+        expr: &'a QueryExpr<'a>,
         tantivy_index: &Index,
-    ) -> Result<Box<dyn tantivy::query::Query>>
-    where
-        I: Iterator<Item = &'a Query<'a>>,
-    {
+    ) -> Result<Box<dyn tantivy::query::Query>> {
         Compiler::new()
             .literal(schema.repo_name, |q| q.repo.clone())
-            .literal(schema.branches, |q| q.branch.clone())
-            .literal(schema.relative_path, |q| match &q.path {
+            .literals(schema.branches, |q| &q.branches)
+            .literal(schema.relative_path, |q| match q.paths.iter().next() {
                 // We coerce path searches to always return sibling files. These are sorted later
                 // by users of this reader.
                 Some(parser::Literal::Plain(s)) => {
@@ -299,8 +342,8 @@ impl DocumentRead for OpenReader {
                 }
                 _ => None,
             })
-            .byte_string(schema.lang, |q| q.lang.as_ref())
-            .compile(queries, tantivy_index)
+            .byte_strings(schema.lang, |q| &q.langs)
+            .compile(expr, tantivy_index)
     }
 
     fn read_document(&self, schema: &File, doc: tantivy::Document) -> Self::Document {
@@ -318,6 +361,8 @@ impl DocumentRead for OpenReader {
             .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
             .collect();
 
+        let last_commit = read_last_commit(&doc, schema);
+
         Self::Document {
             relative_path,
             repo_name,
@@ -325,6 +370,7 @@ impl DocumentRead for OpenReader {
             lang,
             content,
             line_end_indices,
+            last_commit,
         }
     }
 }
@@ -361,6 +407,43 @@ fn read_lang_field(doc: &tantivy::Document, lang: Field) -> Option<String> {
     }
 }
 
+/// The `file_class` values of the classes a query is restricted to, as in `is:test`.
+fn file_classes(query: &Query<'_>) -> Vec<u64> {
+    query.classes.iter().map(|c| c.as_u64()).collect()
+}
+
+/// The kinds and name of the symbols a query searches for, as in `symbol:function:parse` or
+/// `kind:struct Error`.
+///
+/// Only names of at least 3 characters are matched against the kinded trigrams of symbols, and
+/// fuzzy and regex names are left to the `symbols` field, as their matches don't necessarily
+/// contain every trigram of the name.
+fn kinded_name<'a>(query: &'a Query<'a>) -> Option<(&'a HashSet<Cow<'a, str>>, &'a str)> {
+    let (Target::Symbol(Literal::Plain(name)) | Target::Content(Literal::Plain(name))) =
+        query.target.as_ref()?
+    else {
+        return None;
+    };
+
+    (!query.symbol_kinds.is_empty() && !query.is_fuzzy() && name.chars().count() >= 3)
+        .then_some((&query.symbol_kinds, &**name))
+}
+
+/// Read the last commit to modify a document, if it was indexed from a git repository.
+fn read_last_commit(doc: &tantivy::Document, schema: &File) -> Option<LastCommit> {
+    let hash = doc.get_first(schema.last_commit_hash)?.as_text()?;
+
+    if hash.is_empty() {
+        return None;
+    }
+
+    Some(LastCommit {
+        hash: hash.to_owned(),
+        author: read_text_field(doc, schema.last_commit_author),
+        unix_secs: doc.get_first(schema.last_commit_unix_seconds)?.as_u64()?,
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1,9 +1,9 @@
 use tantivy::schema::{
     BytesOptions, Field, IndexRecordOption, Schema, SchemaBuilder, TextFieldIndexing, TextOptions,
-    FAST, STORED, STRING,
+    FAST, INDEXED, STORED, STRING,
 };
 
-use crate::{db::SqlDb, semantic::Semantic};
+use crate::{db::SqlDb, query::ranking::RankingConfig, semantic::Semantic};
 
 #[cfg(feature = "debug")]
 use {
@@ -19,6 +19,9 @@ pub struct File {
     pub(super) semantic: Option<Semantic>,
     pub(super) sql: SqlDb,
 
+    /// Weights of the signals used to rank search results
+    pub ranking: RankingConfig,
+
     #[cfg(feature = "debug")]
     pub histogram: Arc<RwLock<Histogram>>,
 
@@ -48,16 +51,51 @@ pub struct File {
     pub symbols: Field,
     pub symbol_locations: Field,
 
+    /// every distinct kind of symbol defined in this file, e.g.:
+    /// ["function", "struct"]
+    pub symbol_kinds: Field,
+
+    /// every trigram of the name of each symbol, prefixed with the kind of the symbol, for
+    /// searches like `symbol:function:parse`, e.g.:
+    /// ["struct\0Fil", "struct\0ile", "function\0wor", ...]
+    pub kinded_symbols: Field,
+
     /// fast fields for scoring
     pub lang: Field,
     pub avg_line_length: Field,
+    /// time of the last commit that modified this entry, falling back to the
+    /// time of the last commit in the repository
     pub last_commit_unix_seconds: Field,
 
+    /// hash and author of the last commit that modified this entry, if known
+    pub last_commit_hash: Field,
+    pub last_commit_author: Field,
+
+    /// size of the file in bytes, and its number of lines
+    pub byte_size: Field,
+    pub line_count: Field,
+
+    /// lowercase file extension, without the leading dot
+    pub extension: Field,
+
+    /// whether this is a test, generated, vendored or documentation file, as a `FileClass`
+    pub file_class: Field,
+
     /// fast byte versions of certain fields for collector-level filtering
     pub raw_content: Field,
     pub raw_repo_name: Field,
     pub raw_relative_path: Field,
 
+    /// newline-separated list of branches, as in `branches`
+    pub raw_branches: Field,
+
+    /// author of the last commit, as in `last_commit_author`
+    pub raw_last_commit_author: Field,
+
+    /// newline-separated list of every symbol with its kind, e.g.:
+    /// "struct:File\nfunction:worker"
+    pub raw_symbols: Field,
+
     /// list of branches in which this file can be found
     pub branches: Field,
 
@@ -66,7 +104,7 @@ pub struct File {
 }
 
 impl File {
-    pub fn new(sql: SqlDb, semantic: Option<Semantic>) -> Self {
+    pub fn new(sql: SqlDb, semantic: Option<Semantic>, ranking: RankingConfig) -> Self {
         let mut builder = tantivy::schema::SchemaBuilder::new();
         let trigram = TextOptions::default().set_stored().set_indexing_options(
             TextFieldIndexing::default()
@@ -88,21 +126,37 @@ impl File {
         let symbols = builder.add_text_field("symbols", trigram.clone());
         let symbol_locations =
             builder.add_bytes_field("symbol_locations", BytesOptions::default().set_stored());
+        let symbol_kinds =
+            builder.add_bytes_field("symbol_kinds", BytesOptions::default().set_indexed());
+        let kinded_symbols =
+            builder.add_bytes_field("kinded_symbols", BytesOptions::default().set_indexed());
 
-        let branches = builder.add_text_field("branches", trigram);
+        let branches = builder.add_text_field("branches", trigram.clone());
+        let last_commit_author = builder.add_text_field("last_commit_author", trigram);
+        let last_commit_hash = builder.add_text_field("last_commit_hash", STRING | STORED);
 
         let lang = builder.add_bytes_field(
             "lang",
             BytesOptions::default().set_stored().set_indexed() | FAST,
         );
         let avg_line_length = builder.add_f64_field("line_length", FAST);
-        let last_commit_unix_seconds = builder.add_u64_field("last_commit_unix_seconds", FAST);
+        let last_commit_unix_seconds =
+            builder.add_u64_field("last_commit_unix_seconds", FAST | INDEXED | STORED);
+
+        let byte_size = builder.add_u64_field("byte_size", FAST | INDEXED);
+        let line_count = builder.add_u64_field("line_count", FAST | INDEXED);
+        let extension =
+            builder.add_bytes_field("extension", BytesOptions::default().set_indexed() | FAST);
+        let file_class = builder.add_u64_field("file_class", FAST | INDEXED | STORED);
 
         let raw_content = builder.add_bytes_field("raw_content", FAST);
         let raw_repo_name = builder.add_bytes_field("raw_repo_name", FAST);
         let raw_relative_path = builder.add_bytes_field("raw_relative_path", FAST);
+        let raw_branches = builder.add_bytes_field("raw_branches", FAST);
+        let raw_last_commit_author = builder.add_bytes_field("raw_last_commit_author", FAST);
+        let raw_symbols = builder.add_bytes_field("raw_symbols", FAST);
 
-        let is_directory = builder.add_bool_field("is_directory", FAST);
+        let is_directory = builder.add_bool_field("is_directory", FAST | INDEXED);
 
         Self {
             repo_disk_path,
@@ -114,17 +168,29 @@ impl File {
             line_end_indices,
             symbols,
             symbol_locations,
+            symbol_kinds,
+            kinded_symbols,
             lang,
             avg_line_length,
             last_commit_unix_seconds,
+            last_commit_hash,
+            last_commit_author,
+            byte_size,
+            line_count,
+            extension,
+            file_class,
             schema: builder.build(),
             semantic,
             raw_content,
             raw_repo_name,
             raw_relative_path,
+            raw_branches,
+            raw_last_commit_author,
+            raw_symbols,
             branches,
             is_directory,
             sql,
+            ranking,
 
             #[cfg(feature = "debug")]
             histogram: Arc::new(Histogram::builder().build().unwrap().into()),
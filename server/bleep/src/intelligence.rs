@@ -1,6 +1,7 @@
 pub mod code_navigation;
 mod language;
 mod namespace;
+pub mod scip;
 mod scope_resolution;
 
 pub use {
@@ -82,6 +83,25 @@ impl<'a> TreeSitterFile<'a> {
             .collect::<Vec<_>>())
     }
 
+    /// Run a tree-sitter query against this file, returning the byte ranges of all captured
+    /// nodes, sorted by their position in the file.
+    ///
+    /// The query must have been compiled for the grammar of this file.
+    pub fn structural_matches(self, query: &tree_sitter::Query) -> Vec<std::ops::Range<usize>> {
+        let root_node = self.tree.root_node();
+        let mut cursor = tree_sitter::QueryCursor::new();
+
+        let mut ranges = cursor
+            .matches(query, root_node, self.src)
+            .flat_map(|m| m.captures)
+            .map(|c| c.node.byte_range())
+            .collect::<Vec<_>>();
+
+        ranges.sort_by_key(|r| (r.start, r.end));
+        ranges.dedup();
+        ranges
+    }
+
     /// Produce a lexical scope-graph for this TreeSitterFile.
     pub fn scope_graph(self) -> Result<ScopeGraph, TreeSitterFileError> {
         let query = self
@@ -2,6 +2,8 @@
 //! - scope-graph based handler that operates only in the owning file
 //! - search based handler that operates on any file belonging to the repo
 
+mod imports;
+
 use std::ops::Not;
 
 use super::NodeKind;
@@ -187,37 +189,73 @@ impl<'a> CodeNavigationContext<'a> {
         })
     }
 
+    /// The files imported by the active token, if it is an import or refers to one.
+    fn imported_documents(&self) -> Option<Vec<&ContentDocument>> {
+        let scope_graph = self.source_document().symbol_locations.scope_graph()?;
+        let node_idx = scope_graph.node_by_range(self.token.start_byte, self.token.end_byte)?;
+        let import_idx = if self.is_import() {
+            node_idx
+        } else {
+            scope_graph.imports(node_idx).next()?
+        };
+
+        let range = scope_graph.graph[import_idx].range();
+        let imported =
+            imports::imported_files(self.source_document(), range.start.byte..range.end.byte)?;
+
+        Some(
+            self.non_source_documents()
+                .filter(|doc| imported.contains(&doc.relative_path))
+                .collect(),
+        )
+    }
+
     fn repo_wide_definitions(&self) -> Vec<FileSymbols> {
-        self.non_source_documents()
-            .filter_map(|doc| {
-                let scope_graph = doc.symbol_locations.scope_graph()?;
-                let content = doc.content.as_bytes();
-                let mut data = scope_graph
-                    .graph
-                    .node_indices()
-                    .filter(|idx| scope_graph.is_top_level(*idx))
-                    .filter(|idx| {
-                        if let Some(NodeKind::Def(d)) = scope_graph.get_node(*idx) {
-                            d.name(content) == self.active_token_text().as_bytes()
-                        } else {
-                            false
-                        }
-                    })
-                    .map(|idx| Occurrence {
-                        kind: OccurrenceKind::Definition,
-                        range: scope_graph.graph[idx].range(),
-                        snippet: to_occurrence(doc, scope_graph.graph[idx].range()),
-                    })
-                    .collect::<Vec<_>>();
+        // prefer definitions in the files that were actually imported, and only fall back to
+        // matching names across the repo if we can't resolve the import
+        if let Some(docs) = self.imported_documents() {
+            let definitions = self.definitions_in(docs.into_iter());
+            if !definitions.is_empty() {
+                return definitions;
+            }
+        }
 
-                data.sort_by_key(|occurrence| occurrence.range.start.byte);
+        self.definitions_in(self.non_source_documents())
+    }
 
-                data.is_empty().not().then(|| FileSymbols {
-                    file: doc.relative_path.to_owned(),
-                    data,
+    fn definitions_in<'b>(
+        &self,
+        docs: impl Iterator<Item = &'b ContentDocument>,
+    ) -> Vec<FileSymbols> {
+        docs.filter_map(|doc| {
+            let scope_graph = doc.symbol_locations.scope_graph()?;
+            let content = doc.content.as_bytes();
+            let mut data = scope_graph
+                .graph
+                .node_indices()
+                .filter(|idx| scope_graph.is_top_level(*idx))
+                .filter(|idx| {
+                    if let Some(NodeKind::Def(d)) = scope_graph.get_node(*idx) {
+                        d.name(content) == self.active_token_text().as_bytes()
+                    } else {
+                        false
+                    }
+                })
+                .map(|idx| Occurrence {
+                    kind: OccurrenceKind::Definition,
+                    range: scope_graph.graph[idx].range(),
+                    snippet: to_occurrence(doc, scope_graph.graph[idx].range()),
                 })
+                .collect::<Vec<_>>();
+
+            data.sort_by_key(|occurrence| occurrence.range.start.byte);
+
+            data.is_empty().not().then(|| FileSymbols {
+                file: doc.relative_path.to_owned(),
+                data,
             })
-            .collect()
+        })
+        .collect()
     }
 
     fn local_references(&self) -> Option<FileSymbols> {
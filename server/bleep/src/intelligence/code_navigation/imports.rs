@@ -0,0 +1,405 @@
+//! Resolution of imports to the files they refer to.
+//!
+//! Scope graphs only record the name introduced by an import, so we go back to the syntax tree
+//! of the importing file to find the module path, and apply language-specific rules to turn it
+//! into a set of candidate files.
+
+use std::ops::Range;
+
+use tree_sitter::Node;
+
+use crate::{indexes::reader::ContentDocument, intelligence::TreeSitterFile};
+
+/// The files that an import could refer to.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum ImportedFiles {
+    /// Paths relative to the root of the repository.
+    Exact(Vec<String>),
+
+    /// Paths that may be nested anywhere in the repository, used when we don't know where the
+    /// root of the imported package is.
+    Suffix(Vec<String>),
+
+    /// An import path that ends with the directory of the imported package, as in Go, where
+    /// every file in that directory is part of the package.
+    Directory(String),
+}
+
+impl ImportedFiles {
+    pub(super) fn contains(&self, path: &str) -> bool {
+        match self {
+            Self::Exact(paths) => paths.iter().any(|p| p == path),
+            Self::Suffix(suffixes) => suffixes
+                .iter()
+                .any(|s| path == s || path.ends_with(&format!("/{s}"))),
+            Self::Directory(import_path) => {
+                let dir = parent_dir(path);
+                !dir.is_empty() && (import_path == dir || import_path.ends_with(&format!("/{dir}")))
+            }
+        }
+    }
+}
+
+/// Find the files imported by the import statement containing `range` in `doc`.
+///
+/// Returns `None` if the language is unsupported, or the import refers to an external package.
+pub(super) fn imported_files(doc: &ContentDocument, range: Range<usize>) -> Option<ImportedFiles> {
+    let file = TreeSitterFile::try_build(doc.content.as_bytes(), doc.lang.as_ref()?).ok()?;
+    let node = file
+        .tree
+        .root_node()
+        .descendant_for_byte_range(range.start, range.end)?;
+
+    let src = doc.content.as_str();
+    let path = doc.relative_path.as_str();
+
+    match *file.language.language_ids.first()? {
+        "Rust" => rust(node, src, path),
+        "Python" => python(node, src, path),
+        "JavaScript" | "TypeScript" => javascript(node, src, path),
+        "Go" => go(node, src),
+        _ => None,
+    }
+}
+
+/// Rust imports are module paths, such as `crate::foo::Bar` or `super::Bar`.
+fn rust(node: Node<'_>, src: &str, path: &str) -> Option<ImportedFiles> {
+    // collect the module path leading up to the imported name, e.g. `Quux` in
+    // `use crate::foo::{bar, baz::Quux}` is in `crate::foo::baz`
+    let mut segments = Vec::new();
+    let mut child = node;
+
+    while let Some(parent) = child.parent() {
+        let prefix = parent
+            .child_by_field_name("path")
+            .filter(|p| p.id() != child.id())
+            .map(|p| text(p, src).split("::").map(str::trim).collect::<Vec<_>>());
+
+        match (parent.kind(), prefix) {
+            ("scoped_identifier" | "scoped_use_list", Some(prefix)) => {
+                segments.splice(0..0, prefix);
+            }
+
+            // `use foo::bar as baz` imports `bar` from `foo`
+            ("use_as_clause", Some(mut prefix)) => {
+                prefix.pop();
+                segments.splice(0..0, prefix);
+            }
+
+            ("use_declaration", _) => break,
+            _ => {}
+        }
+
+        child = parent;
+    }
+
+    let supers = segments.iter().take_while(|s| **s == "super").count();
+    let (base, module) = match segments.first().copied()? {
+        "crate" => (crate_root(path), &segments[1..]),
+        "self" => (Some(rust_module_dir(path)), &segments[1..]),
+        "super" => {
+            let mut dir = rust_module_dir(path);
+            for _ in 0..supers {
+                dir = parent_dir(dir);
+            }
+            (Some(dir), &segments[supers..])
+        }
+        _ => (None, &segments[..]),
+    };
+
+    let module = module.join("/");
+    let module = match base {
+        Some(base) => join(base, &module)?,
+        None => module,
+    };
+
+    let candidates = if module.is_empty() {
+        vec!["lib.rs".to_owned(), "main.rs".to_owned()]
+    } else {
+        vec![
+            format!("{module}.rs"),
+            format!("{module}/mod.rs"),
+            format!("{module}/lib.rs"),
+            format!("{module}/main.rs"),
+        ]
+    };
+
+    Some(match base {
+        Some(_) => ImportedFiles::Exact(candidates),
+        None => ImportedFiles::Suffix(candidates),
+    })
+}
+
+/// The directory holding the child modules of the module defined in `path`.
+fn rust_module_dir(path: &str) -> &str {
+    let (dir, file) = path.rsplit_once('/').unwrap_or(("", path));
+    match file {
+        "mod.rs" | "lib.rs" | "main.rs" => dir,
+        _ => path.strip_suffix(".rs").unwrap_or(path),
+    }
+}
+
+/// The `src` directory of the crate containing `path`, if any.
+fn crate_root(path: &str) -> Option<&str> {
+    let dir = parent_dir(path);
+    if dir == "src" {
+        return Some(dir);
+    }
+
+    dir.rfind("/src/")
+        .map(|i| &dir[..i + 4])
+        .or_else(|| dir.ends_with("/src").then_some(dir))
+        .or_else(|| dir.starts_with("src/").then_some("src"))
+}
+
+/// Python imports are dotted module names, which may be relative as in `from ..foo import bar`.
+fn python(node: Node<'_>, src: &str, path: &str) -> Option<ImportedFiles> {
+    let statement = ancestor(node, &["import_statement", "import_from_statement"])?;
+
+    let (dots, module) = if statement.kind() == "import_from_statement" {
+        let module = text(statement.child_by_field_name("module_name")?, src);
+        let name = module.trim_start_matches('.');
+        (module.len() - name.len(), name)
+    } else {
+        // `import foo.bar` or `import foo.bar as baz`, which imports the module itself
+        let parent = node.parent()?;
+        let dotted_name = match parent.kind() {
+            "aliased_import" => parent.child_by_field_name("name")?,
+            _ => parent,
+        };
+        (0, text(dotted_name, src))
+    };
+
+    let module = module.replace('.', "/");
+    let candidates = |module: &str| {
+        if module.is_empty() {
+            vec!["__init__.py".to_owned()]
+        } else {
+            vec![format!("{module}.py"), format!("{module}/__init__.py")]
+        }
+    };
+
+    Some(if dots == 0 {
+        ImportedFiles::Suffix(candidates(&module))
+    } else {
+        let mut base = parent_dir(path);
+        for _ in 1..dots {
+            base = parent_dir(base);
+        }
+
+        let candidates = candidates(&module)
+            .into_iter()
+            .filter_map(|c| join(base, &c))
+            .collect();
+        ImportedFiles::Exact(candidates)
+    })
+}
+
+/// JavaScript and TypeScript imports are relative paths, with an optional extension.
+fn javascript(node: Node<'_>, src: &str, path: &str) -> Option<ImportedFiles> {
+    const EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+    let source = match ancestor(node, &["import_statement", "variable_declarator"])? {
+        statement if statement.kind() == "import_statement" => {
+            statement.child_by_field_name("source")?
+        }
+
+        // `const x = require("./x")`
+        declarator => declarator
+            .child_by_field_name("value")
+            .filter(|v| v.kind() == "call_expression")?
+            .child_by_field_name("arguments")?
+            .named_child(0)
+            .filter(|a| a.kind() == "string")?,
+    };
+
+    let source = text(source, src).trim_matches(|c| c == '"' || c == '\'' || c == '`');
+
+    // anything else is a package
+    if !source.starts_with('.') {
+        return None;
+    }
+
+    let module = join(parent_dir(path), source)?;
+    let candidates = std::iter::once(module.clone())
+        .chain(EXTENSIONS.iter().map(|ext| format!("{module}.{ext}")))
+        .chain(EXTENSIONS.iter().map(|ext| format!("{module}/index.{ext}")))
+        .collect();
+
+    Some(ImportedFiles::Exact(candidates))
+}
+
+/// Go imports are package paths, which end with the directory of the package.
+fn go(node: Node<'_>, src: &str) -> Option<ImportedFiles> {
+    let spec = ancestor(node, &["import_spec"])?;
+    let import_path =
+        text(spec.child_by_field_name("path")?, src).trim_matches(|c| c == '"' || c == '`');
+
+    Some(ImportedFiles::Directory(import_path.to_owned()))
+}
+
+fn ancestor<'a>(node: Node<'a>, kinds: &[&str]) -> Option<Node<'a>> {
+    std::iter::successors(Some(node), Node::parent).find(|n| kinds.contains(&n.kind()))
+}
+
+fn text<'a>(node: Node<'_>, src: &'a str) -> &'a str {
+    &src[node.byte_range()]
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(dir, _)| dir)
+        .unwrap_or_default()
+}
+
+/// Join a relative path onto a directory, resolving `.` and `..` components.
+///
+/// Returns `None` if the path escapes the root of the repository.
+fn join(dir: &str, relative: &str) -> Option<String> {
+    let mut components = dir.split('/').filter(|c| !c.is_empty()).collect::<Vec<_>>();
+
+    for component in relative.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            c => components.push(c),
+        }
+    }
+
+    Some(components.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(path: &str, lang: &str, src: &str, import: &str) -> Option<ImportedFiles> {
+        let start = src.rfind(import).unwrap();
+        let doc = ContentDocument {
+            content: src.to_owned(),
+            lang: Some(lang.to_owned()),
+            relative_path: path.to_owned(),
+            ..Default::default()
+        };
+
+        imported_files(&doc, start..start + import.len())
+    }
+
+    fn exact(paths: &[&str]) -> Option<ImportedFiles> {
+        Some(ImportedFiles::Exact(
+            paths.iter().map(|p| p.to_string()).collect(),
+        ))
+    }
+
+    #[test]
+    fn rust_imports() {
+        assert_eq!(
+            resolve(
+                "server/src/webserver/mod.rs",
+                "Rust",
+                "use crate::indexes::reader::ContentDocument;",
+                "ContentDocument"
+            ),
+            exact(&[
+                "server/src/indexes/reader.rs",
+                "server/src/indexes/reader/mod.rs",
+                "server/src/indexes/reader/lib.rs",
+                "server/src/indexes/reader/main.rs",
+            ]),
+        );
+
+        assert_eq!(
+            resolve(
+                "src/query/parser.rs",
+                "Rust",
+                "use super::{ast, planner::{self, Fragment}};",
+                "Fragment"
+            ),
+            exact(&[
+                "src/query/planner.rs",
+                "src/query/planner/mod.rs",
+                "src/query/planner/lib.rs",
+                "src/query/planner/main.rs",
+            ]),
+        );
+
+        assert_eq!(
+            resolve("src/main.rs", "Rust", "use foo::Bar as Baz;", "Baz"),
+            Some(ImportedFiles::Suffix(vec![
+                "foo.rs".into(),
+                "foo/mod.rs".into(),
+                "foo/lib.rs".into(),
+                "foo/main.rs".into(),
+            ])),
+        );
+    }
+
+    #[test]
+    fn python_imports() {
+        assert_eq!(
+            resolve(
+                "pkg/sub/mod.py",
+                "Python",
+                "from ..utils import helper",
+                "helper"
+            ),
+            exact(&["pkg/utils.py", "pkg/utils/__init__.py"]),
+        );
+
+        assert_eq!(
+            resolve("pkg/sub/mod.py", "Python", "from . import helper", "helper"),
+            exact(&["pkg/sub/__init__.py"]),
+        );
+
+        assert_eq!(
+            resolve("main.py", "Python", "import pkg.utils", "utils"),
+            Some(ImportedFiles::Suffix(vec![
+                "pkg/utils.py".into(),
+                "pkg/utils/__init__.py".into(),
+            ])),
+        );
+    }
+
+    #[test]
+    fn javascript_imports() {
+        let files = resolve(
+            "client/src/App.tsx",
+            "TypeScript",
+            "import { Button } from '../components/Button';",
+            "Button",
+        )
+        .unwrap();
+
+        assert!(files.contains("client/components/Button.tsx"));
+        assert!(files.contains("client/components/Button/index.ts"));
+        assert!(!files.contains("client/src/components/Button.tsx"));
+
+        assert_eq!(
+            resolve(
+                "index.js",
+                "JavaScript",
+                "import React from 'react';",
+                "React"
+            ),
+            None,
+        );
+    }
+
+    #[test]
+    fn go_imports() {
+        let files = resolve(
+            "cmd/main.go",
+            "Go",
+            "package main\n\nimport util \"github.com/org/repo/internal/util\"\n",
+            "util",
+        )
+        .unwrap();
+
+        assert!(files.contains("internal/util/strings.go"));
+        assert!(files.contains("repo/internal/util/strings.go"));
+        assert!(!files.contains("internal/other/strings.go"));
+        assert!(!files.contains("main.go"));
+    }
+}
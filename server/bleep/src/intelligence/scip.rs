@@ -0,0 +1,887 @@
+//! Precise code navigation from SCIP indexes, as produced by `rust-analyzer`, `scip-typescript`
+//! or `scip-java`, and the export of scope graphs as SCIP indexes.
+//!
+//! A SCIP index is a protobuf file listing every occurrence of every symbol in a repository. Only
+//! the parts of the [schema](https://github.com/sourcegraph/scip/blob/main/scip.proto) that
+//! navigation needs are decoded; everything else is skipped.
+//!
+//! Indexes describe a single checkout, so they are only used for files at `HEAD`, and only when
+//! they were made for the commit that is currently checked out. Everything else falls back to
+//! scope graphs.
+//!
+//! Conversely, languages without a precise indexer can have their scope graphs exported, so that
+//! tools which consume SCIP can use them too.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use prost::Message;
+use tantivy::{
+    collector::DocSetCollector,
+    query::TermQuery,
+    schema::{IndexRecordOption, Term},
+};
+use tracing::{debug, info, warn};
+
+use super::{
+    code_navigation::{FileSymbols, Occurrence, OccurrenceKind},
+    Language, NodeKind, TSLanguage,
+};
+use crate::{
+    db::{ScipIndex, ScipOccurrence, ScipTable},
+    indexes::{
+        reader::{ContentDocument, ContentReader},
+        DocumentRead, File, Indexer,
+    },
+    repo::RepoRef,
+    snippet::Snipper,
+    text_range::TextRange,
+    Application,
+};
+
+/// The bit of `Occurrence.symbol_roles` that marks definitions.
+const DEFINITION_ROLE: i32 = 0x1;
+
+/// The bit of `Occurrence.symbol_roles` that marks imports.
+const IMPORT_ROLE: i32 = 0x2;
+
+/// The scheme of the global symbols in exported indexes.
+const SCHEME: &str = "bleep";
+
+/// The `UTF8CodeUnitOffsetFromLineStart` position encoding, which counts bytes.
+const UTF8_POSITIONS: i32 = 1;
+
+/// The `UTF8` text encoding.
+const UTF8_TEXT: i32 = 1;
+
+/// Symbols with this prefix are local to the document they occur in.
+const LOCAL_PREFIX: &str = "local ";
+
+/// The messages of the SCIP schema, with only the fields that are needed for navigation and
+/// export.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Index {
+        #[prost(message, optional, tag = "1")]
+        pub metadata: Option<Metadata>,
+        #[prost(message, repeated, tag = "2")]
+        pub documents: Vec<Document>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Metadata {
+        #[prost(message, optional, tag = "2")]
+        pub tool_info: Option<ToolInfo>,
+        #[prost(string, tag = "3")]
+        pub project_root: String,
+        #[prost(int32, tag = "4")]
+        pub text_document_encoding: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ToolInfo {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub version: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Document {
+        #[prost(string, tag = "1")]
+        pub relative_path: String,
+        #[prost(message, repeated, tag = "2")]
+        pub occurrences: Vec<Occurrence>,
+        #[prost(message, repeated, tag = "3")]
+        pub symbols: Vec<SymbolInformation>,
+        #[prost(string, tag = "4")]
+        pub language: String,
+        #[prost(int32, tag = "6")]
+        pub position_encoding: i32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SymbolInformation {
+        #[prost(string, tag = "1")]
+        pub symbol: String,
+        #[prost(int32, tag = "5")]
+        pub kind: i32,
+        #[prost(string, tag = "6")]
+        pub display_name: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Occurrence {
+        #[prost(int32, repeated, tag = "1")]
+        pub range: Vec<i32>,
+        #[prost(string, tag = "2")]
+        pub symbol: String,
+        #[prost(int32, tag = "3")]
+        pub symbol_roles: i32,
+    }
+}
+
+/// The unit that SCIP positions count characters in, within a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PositionEncoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    fn from_scip(encoding: i32) -> Self {
+        match encoding {
+            2 => Self::Utf16,
+            3 => Self::Utf32,
+            // older indexers leave the encoding unspecified, and mostly write byte offsets
+            _ => Self::Utf8,
+        }
+    }
+
+    /// The byte offset of a character within `line`, if it falls on a character boundary.
+    fn byte_offset(self, line: &str, character: usize) -> Option<usize> {
+        match self {
+            Self::Utf8 => line.is_char_boundary(character).then_some(character),
+            Self::Utf16 => {
+                let mut units = 0;
+                for (i, c) in line.char_indices().chain(Some((line.len(), '\0'))) {
+                    if units >= character {
+                        return (units == character).then_some(i);
+                    }
+                    units += c.len_utf16();
+                }
+                None
+            }
+            Self::Utf32 => line
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain(Some(line.len()))
+                .nth(character),
+        }
+    }
+}
+
+/// A decoded SCIP index.
+#[derive(Debug)]
+pub struct DecodedIndex {
+    /// The name of the indexer that produced the index
+    pub tool: String,
+    pub occurrences: Vec<ScipOccurrence>,
+}
+
+/// Decode a SCIP index from its protobuf encoding.
+pub fn decode(bytes: &[u8]) -> Result<DecodedIndex> {
+    let index = proto::Index::decode(bytes).context("invalid SCIP index")?;
+
+    let tool = index
+        .metadata
+        .and_then(|m| m.tool_info)
+        .map(|t| t.name)
+        .unwrap_or_default();
+
+    let occurrences = index
+        .documents
+        .into_iter()
+        .flat_map(|doc| {
+            let relative_path = doc.relative_path;
+            let position_encoding = doc.position_encoding;
+
+            doc.occurrences.into_iter().filter_map(move |occurrence| {
+                // ranges are `[startLine, startCharacter, endCharacter]` for occurrences on a
+                // single line, and `[startLine, startCharacter, endLine, endCharacter]` otherwise
+                let (start, end) = match occurrence.range[..] {
+                    [line, start, end] => ((line, start), (line, end)),
+                    [start_line, start, end_line, end] => ((start_line, start), (end_line, end)),
+                    _ => return None,
+                };
+
+                if occurrence.symbol.is_empty() {
+                    return None;
+                }
+
+                Some(ScipOccurrence {
+                    relative_path: relative_path.clone(),
+                    is_definition: occurrence.symbol_roles & DEFINITION_ROLE != 0,
+                    symbol: occurrence.symbol,
+                    position_encoding,
+                    start: to_usize(start)?,
+                    end: to_usize(end)?,
+                })
+            })
+        })
+        .collect();
+
+    Ok(DecodedIndex { tool, occurrences })
+}
+
+fn to_usize((line, character): (i32, i32)) -> Option<(usize, usize)> {
+    Some((
+        usize::try_from(line).ok()?,
+        usize::try_from(character).ok()?,
+    ))
+}
+
+impl DecodedIndex {
+    /// Store this as the index of a repository at `commit_hash`, replacing any previous index.
+    ///
+    /// Indexes ingested from the configured directory also record the modification time of their
+    /// file, see `ingest_configured`.
+    pub async fn store(
+        self,
+        app: &Application,
+        repo_ref: &RepoRef,
+        commit_hash: Option<String>,
+        source_mtime: Option<i64>,
+    ) -> Result<()> {
+        ScipTable::new(&app.sql)
+            .insert_index(
+                &repo_ref.to_string(),
+                &ScipIndex {
+                    commit_hash,
+                    tool: self.tool,
+                    source_mtime,
+                },
+                &self.occurrences,
+            )
+            .await?;
+
+        info!(%repo_ref, occurrences = self.occurrences.len(), "stored SCIP index");
+        Ok(())
+    }
+}
+
+/// Ingest the SCIP index of a repository from the configured directory, if there is one.
+///
+/// Indexes are looked up by the name of the repository, as in
+/// `<scip_dir>/github.com/BloopAI/bloop.scip`, and are assumed to be made for the commit that is
+/// checked out when they are ingested. They are only ingested again when either the file or the
+/// checked out commit changes.
+pub async fn ingest_configured(app: &Application, repo_ref: &RepoRef) -> Result<()> {
+    let Some(path) = configured_path(app, repo_ref) else {
+        return Ok(());
+    };
+
+    let source_mtime = tokio::fs::metadata(&path)
+        .await
+        .and_then(|meta| meta.modified())
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .and_then(|d| i64::try_from(d.as_millis()).ok());
+
+    let commit_hash = app
+        .repo_pool
+        .read(repo_ref, |_, repo| repo.disk_path.clone())
+        .and_then(|disk_path| head_commit(&disk_path));
+
+    let current = ScipTable::new(&app.sql)
+        .index(&repo_ref.to_string())
+        .await?;
+    if let Some(current) = current {
+        if source_mtime.is_some()
+            && current.source_mtime == source_mtime
+            && current.commit_hash == commit_hash
+        {
+            debug!(%repo_ref, "SCIP index is up to date");
+            return Ok(());
+        }
+    }
+
+    let bytes = tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    decode(&bytes)?
+        .store(app, repo_ref, commit_hash, source_mtime)
+        .await
+}
+
+fn configured_path(app: &Application, repo_ref: &RepoRef) -> Option<PathBuf> {
+    let path = app
+        .config
+        .scip_dir
+        .as_ref()?
+        .join(format!("{}.scip", repo_ref.indexed_name()));
+
+    path.is_file().then_some(path)
+}
+
+fn head_commit(disk_path: &Path) -> Option<String> {
+    Some(gix::open(disk_path).ok()?.head_id().ok()?.to_string())
+}
+
+/// The result of navigating from a token with a SCIP index.
+pub struct ScipTokenInfo {
+    /// The byte range of the occurrence at the token
+    pub range: Range<usize>,
+
+    /// Whether the token is the definition of its symbol
+    pub is_definition: bool,
+
+    /// Every other occurrence of the symbol, starting with the file the token is in
+    pub data: Vec<FileSymbols>,
+}
+
+/// Find the other occurrences of the symbol at `token`, with the SCIP index of its repository.
+///
+/// Returns `None` if there is no up to date index for the document, or the index has no
+/// occurrence at the token.
+pub async fn token_info(
+    app: &Application,
+    repo_ref: &RepoRef,
+    source_document: &ContentDocument,
+    token: Range<usize>,
+    branch: Option<&str>,
+) -> Option<ScipTokenInfo> {
+    let table = ScipTable::new(&app.sql);
+    let repo_str = repo_ref.to_string();
+
+    let index = table
+        .index(&repo_str)
+        .await
+        .map_err(|err| warn!(%err, "failed to read SCIP index"))
+        .ok()??;
+
+    let at_head = source_document
+        .branches
+        .as_deref()
+        .map_or(true, |branches| branches.lines().any(|b| b == "HEAD"));
+    if !at_head {
+        return None;
+    }
+
+    if let Some(commit_hash) = &index.commit_hash {
+        let disk_path = app
+            .repo_pool
+            .read(repo_ref, |_, repo| repo.disk_path.clone())?;
+        if head_commit(&disk_path).as_ref() != Some(commit_hash) {
+            debug!(%repo_ref, %commit_hash, "SCIP index is out of date");
+            return None;
+        }
+    }
+
+    let in_file = table
+        .occurrences_in(&repo_str, &source_document.relative_path)
+        .await
+        .map_err(|err| warn!(%err, "failed to read SCIP index"))
+        .ok()?;
+
+    // the innermost occurrence that covers the token
+    let (active_range, active) = in_file
+        .iter()
+        .filter_map(|o| Some((byte_range(source_document, o)?, o)))
+        .filter(|(range, _)| range.start <= token.start && token.end <= range.end)
+        .min_by_key(|(range, _)| range.len())?;
+
+    let occurrences = if active.symbol.starts_with(LOCAL_PREFIX) {
+        in_file
+            .iter()
+            .filter(|o| o.symbol == active.symbol)
+            .cloned()
+            .collect()
+    } else {
+        table
+            .occurrences_of(&repo_str, &active.symbol)
+            .await
+            .map_err(|err| warn!(%err, "failed to read SCIP index"))
+            .ok()?
+    };
+
+    let mut files = BTreeMap::<_, Vec<_>>::new();
+    for occurrence in occurrences {
+        files
+            .entry(occurrence.relative_path.clone())
+            .or_default()
+            .push(occurrence);
+    }
+
+    let source_occurrences = files
+        .remove(&source_document.relative_path)
+        .map(|o| (source_document.relative_path.clone(), o));
+
+    let mut data = Vec::new();
+    for (relative_path, occurrences) in source_occurrences.into_iter().chain(files) {
+        let is_source = relative_path == source_document.relative_path;
+        let loaded;
+        let doc = if is_source {
+            source_document
+        } else {
+            match app
+                .indexes
+                .file
+                .by_path(repo_ref, &relative_path, branch)
+                .await
+            {
+                Ok(Some(doc)) => {
+                    loaded = doc;
+                    &loaded
+                }
+                _ => continue,
+            }
+        };
+
+        let mut ranges = occurrences
+            .iter()
+            .filter_map(|o| Some((byte_range(doc, o)?, o.is_definition)))
+            .filter(|(range, _)| !(is_source && *range == active_range))
+            .collect::<Vec<_>>();
+
+        // a symbol can be both defined and referenced at the same place, as with a field in a
+        // struct literal shorthand
+        ranges.sort_by_key(|(range, is_definition)| (range.start, range.end, !is_definition));
+        ranges.dedup_by_key(|(range, _)| range.clone());
+
+        let data_in_file = ranges
+            .into_iter()
+            .map(|(range, is_definition)| Occurrence {
+                kind: if is_definition {
+                    OccurrenceKind::Definition
+                } else {
+                    OccurrenceKind::Reference
+                },
+                snippet: Snipper::default()
+                    .expand(range.clone(), &doc.content, &doc.line_end_indices)
+                    .reify(&doc.content, &[]),
+                range: TextRange::from_byte_range(range, &doc.line_end_indices),
+            })
+            .collect::<Vec<_>>();
+
+        if !data_in_file.is_empty() {
+            data.push(FileSymbols {
+                file: relative_path,
+                data: data_in_file,
+            });
+        }
+    }
+
+    Some(ScipTokenInfo {
+        range: active_range,
+        is_definition: active.is_definition,
+        data,
+    })
+}
+
+/// The byte range of an occurrence in `doc`, if it is within bounds.
+fn byte_range(doc: &ContentDocument, occurrence: &ScipOccurrence) -> Option<Range<usize>> {
+    let encoding = PositionEncoding::from_scip(occurrence.position_encoding);
+    let start = byte_offset(doc, occurrence.start, encoding)?;
+    let end = byte_offset(doc, occurrence.end, encoding)?;
+
+    (start <= end).then_some(start..end)
+}
+
+fn byte_offset(
+    doc: &ContentDocument,
+    (line, character): (usize, usize),
+    encoding: PositionEncoding,
+) -> Option<usize> {
+    let line_start = match line {
+        0 => 0,
+        n => *doc.line_end_indices.get(n - 1)? as usize + 1,
+    };
+    let line_end = doc
+        .line_end_indices
+        .get(line)
+        .map_or(doc.content.len(), |&end| end as usize);
+
+    let text = doc.content.get(line_start..line_end)?;
+    Some(line_start + encoding.byte_offset(text, character)?)
+}
+
+/// Export the scope graphs of every file in a repository as a SCIP index.
+///
+/// Only files on `branch` are exported, or files at `HEAD` if no branch is given. Definitions
+/// below the top level of a file get symbols that are local to their document. Top-level
+/// definitions, imports and unresolved references get global symbols named after them, and so are
+/// linked across files by name, just like repo-wide navigation.
+pub async fn export(
+    indexer: &Indexer<File>,
+    repo_ref: &RepoRef,
+    disk_path: &Path,
+    branch: Option<&str>,
+) -> Result<Vec<u8>> {
+    let searcher = indexer.reader.read().await.searcher();
+    let query = TermQuery::new(
+        Term::from_field_text(indexer.source.repo_ref, &repo_ref.to_string()),
+        IndexRecordOption::Basic,
+    );
+
+    let branch = branch.unwrap_or("HEAD");
+    // spaces separate the parts of a symbol, so they are doubled in package names
+    let package = repo_ref.display_name().replace(' ', "  ");
+
+    let mut documents = Vec::new();
+    for addr in searcher.search(&query, &DocSetCollector)? {
+        let doc = ContentReader.read_document(&indexer.source, searcher.doc(addr)?);
+
+        let on_branch = doc
+            .branches
+            .as_deref()
+            .map_or(false, |branches| branches.lines().any(|b| b == branch));
+
+        if on_branch {
+            documents.extend(export_document(&doc, &package));
+        }
+    }
+
+    documents.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+    let project_root = disk_path.to_string_lossy().replace('\\', "/");
+    let index = proto::Index {
+        metadata: Some(proto::Metadata {
+            tool_info: Some(proto::ToolInfo {
+                name: SCHEME.to_owned(),
+                version: env!("CARGO_PKG_VERSION").to_owned(),
+            }),
+            project_root: format!("file://{}", project_root.trim_end_matches('/')),
+            text_document_encoding: UTF8_TEXT,
+        }),
+        documents,
+    };
+
+    Ok(index.encode_to_vec())
+}
+
+/// Convert the scope graph of a file, if it has one, to a SCIP document.
+fn export_document(doc: &ContentDocument, package: &str) -> Option<proto::Document> {
+    let graph = doc.symbol_locations.scope_graph()?;
+    let language = match TSLanguage::from_id(doc.lang.as_deref()?) {
+        Language::Supported(config) => *config.language_ids.first()?,
+        _ => return None,
+    };
+
+    let src = doc.content.as_bytes();
+    let global = |name: &[u8]| {
+        format!(
+            "{SCHEME} . {package} . {}/{}.",
+            escape(language),
+            escape(&String::from_utf8_lossy(name))
+        )
+    };
+
+    let mut occurrences = Vec::new();
+    let mut symbols = Vec::new();
+    let mut described = HashSet::new();
+    let mut locals = HashMap::new();
+
+    // definitions go first, so that references can find the local symbols they resolve to
+    for idx in graph.graph.node_indices() {
+        let NodeKind::Def(def) = &graph.graph[idx] else {
+            continue;
+        };
+
+        let symbol = if graph.is_top_level(idx) {
+            global(def.name(src))
+        } else {
+            let symbol = format!("{LOCAL_PREFIX}{}", locals.len());
+            locals.insert(idx, symbol.clone());
+            symbol
+        };
+
+        occurrences.push(export_occurrence(doc, &def.range, &symbol, DEFINITION_ROLE));
+
+        // a name can be defined more than once at the top level, but is only described once
+        if described.insert(symbol.clone()) {
+            symbols.push(proto::SymbolInformation {
+                symbol,
+                kind: symbol_kind(graph.symbol_name_of(idx)),
+                display_name: String::from_utf8_lossy(def.name(src)).into_owned(),
+            });
+        }
+    }
+
+    for idx in graph.graph.node_indices() {
+        let occurrence = match &graph.graph[idx] {
+            NodeKind::Import(import) => {
+                export_occurrence(doc, &import.range, &global(import.name(src)), IMPORT_ROLE)
+            }
+            NodeKind::Ref(reference) => {
+                let symbol = graph
+                    .definitions(idx)
+                    .find_map(|def| locals.get(&def).cloned())
+                    .unwrap_or_else(|| global(reference.name(src)));
+
+                export_occurrence(doc, &reference.range, &symbol, 0)
+            }
+            _ => continue,
+        };
+
+        occurrences.push(occurrence);
+    }
+
+    occurrences.sort_by(|a, b| a.range.cmp(&b.range));
+
+    Some(proto::Document {
+        relative_path: doc.relative_path.clone(),
+        occurrences,
+        symbols,
+        language: language.to_owned(),
+        position_encoding: UTF8_POSITIONS,
+    })
+}
+
+fn export_occurrence(
+    doc: &ContentDocument,
+    range: &TextRange,
+    symbol: &str,
+    symbol_roles: i32,
+) -> proto::Occurrence {
+    let (start_line, start) = line_and_character(doc, range.start.byte);
+    let (end_line, end) = line_and_character(doc, range.end.byte);
+
+    let range = if start_line == end_line {
+        vec![start_line, start, end]
+    } else {
+        vec![start_line, start, end_line, end]
+    };
+
+    proto::Occurrence {
+        range,
+        symbol: symbol.to_owned(),
+        symbol_roles,
+    }
+}
+
+/// The zero-based line of a byte offset, and its offset in bytes from the start of that line.
+fn line_and_character(doc: &ContentDocument, byte: usize) -> (i32, i32) {
+    let line = doc
+        .line_end_indices
+        .partition_point(|&end| (end as usize) < byte);
+    let line_start = line
+        .checked_sub(1)
+        .map_or(0, |prev| doc.line_end_indices[prev] as usize + 1);
+
+    (line as i32, (byte - line_start) as i32)
+}
+
+/// Escape a name for use as a descriptor in a SCIP symbol, by quoting it with backticks unless
+/// it is a plain identifier.
+fn escape(name: &str) -> String {
+    let is_simple = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-' | '$'));
+
+    if is_simple {
+        name.to_owned()
+    } else {
+        format!("`{}`", name.replace('`', "``"))
+    }
+}
+
+/// The SCIP `SymbolInformation.Kind` of a bleep symbol kind, such as `function` or `struct`.
+fn symbol_kind(kind: Option<&str>) -> i32 {
+    match kind {
+        Some("class") => 7,
+        Some("constant" | "const") => 8,
+        Some("enum") => 11,
+        Some("enumerator") => 12,
+        Some("field" | "member") => 15,
+        Some("function" | "func" | "generator") => 17,
+        Some("interface") => 21,
+        Some("macro") => 25,
+        Some("method") => 26,
+        Some("module") => 29,
+        Some("namespace") => 30,
+        Some("package") => 35,
+        Some("parameter") => 37,
+        Some("property") => 41,
+        Some("struct") => 49,
+        Some("trait") => 53,
+        Some("typedef" | "type" | "alias") => 55,
+        Some("union") => 59,
+        Some("variable" | "var" | "local") => 61,
+        // `UnspecifiedKind`
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{intelligence::TreeSitterFile, symbol::SymbolLocations};
+
+    fn document(content: &str) -> ContentDocument {
+        ContentDocument {
+            content: content.to_owned(),
+            line_end_indices: content.match_indices('\n').map(|(i, _)| i as u32).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn position_encodings() {
+        let line = "let é = \"𝄞x\";";
+        let x = line.find('x').unwrap();
+
+        assert_eq!(PositionEncoding::Utf8.byte_offset(line, x), Some(x));
+        assert_eq!(PositionEncoding::Utf8.byte_offset(line, 5), None);
+        assert_eq!(PositionEncoding::Utf16.byte_offset(line, 11), Some(x));
+        assert_eq!(PositionEncoding::Utf16.byte_offset(line, 10), None);
+        assert_eq!(PositionEncoding::Utf32.byte_offset(line, 10), Some(x));
+        assert_eq!(
+            PositionEncoding::Utf32.byte_offset(line, line.chars().count()),
+            Some(line.len())
+        );
+        assert_eq!(PositionEncoding::Utf32.byte_offset(line, 100), None);
+    }
+
+    #[test]
+    fn decode_index() {
+        let occurrence = |range: Vec<i32>, symbol: &str, symbol_roles| proto::Occurrence {
+            range,
+            symbol: symbol.to_owned(),
+            symbol_roles,
+        };
+
+        let index = proto::Index {
+            metadata: Some(proto::Metadata {
+                tool_info: Some(proto::ToolInfo {
+                    name: "rust-analyzer".to_owned(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            documents: vec![proto::Document {
+                relative_path: "src/lib.rs".to_owned(),
+                position_encoding: 2,
+                occurrences: vec![
+                    occurrence(vec![0, 3, 8], "cargo bleep 0.1.0 parse().", 1),
+                    occurrence(vec![2, 4, 3, 1], "cargo bleep 0.1.0 Query#", 0),
+                    occurrence(vec![4], "cargo bleep 0.1.0 broken().", 0),
+                    occurrence(vec![5, 0, 2], "", 0),
+                ],
+                ..Default::default()
+            }],
+        };
+
+        let decoded = decode(&index.encode_to_vec()).unwrap();
+        assert_eq!(decoded.tool, "rust-analyzer");
+        assert_eq!(
+            decoded.occurrences,
+            [
+                ScipOccurrence {
+                    relative_path: "src/lib.rs".to_owned(),
+                    symbol: "cargo bleep 0.1.0 parse().".to_owned(),
+                    is_definition: true,
+                    position_encoding: 2,
+                    start: (0, 3),
+                    end: (0, 8),
+                },
+                ScipOccurrence {
+                    relative_path: "src/lib.rs".to_owned(),
+                    symbol: "cargo bleep 0.1.0 Query#".to_owned(),
+                    is_definition: false,
+                    position_encoding: 2,
+                    start: (2, 4),
+                    end: (3, 1),
+                },
+            ]
+        );
+
+        assert!(decode(b"not a protobuf message").is_err());
+    }
+
+    #[test]
+    fn occurrence_ranges() {
+        let doc = document("fn parse() {}\n\nfn main() {\n    parse();\n}\n");
+        let occurrence = |start, end| ScipOccurrence {
+            relative_path: "src/main.rs".to_owned(),
+            symbol: "local 1".to_owned(),
+            is_definition: false,
+            position_encoding: 0,
+            start,
+            end,
+        };
+
+        let call = doc.content.rfind("parse").unwrap();
+        assert_eq!(
+            byte_range(&doc, &occurrence((3, 4), (3, 9))),
+            Some(call..call + 5)
+        );
+
+        // the body of `main`, spanning several lines
+        let body = doc.content.find("{\n    parse").unwrap();
+        assert_eq!(
+            byte_range(&doc, &occurrence((2, 10), (4, 1))),
+            Some(body..doc.content.len() - 1)
+        );
+
+        assert_eq!(byte_range(&doc, &occurrence((9, 0), (9, 1))), None);
+        assert_eq!(byte_range(&doc, &occurrence((0, 5), (0, 2))), None);
+    }
+
+    #[test]
+    fn export_scope_graph() {
+        let src = "use std::collections::HashMap;\n\
+                   fn parse() {}\n\
+                   fn main() {\n    \
+                       let m = HashMap::new();\n    \
+                       parse();\n    \
+                       helper(m);\n\
+                   }\n";
+
+        let graph = TreeSitterFile::try_build(src.as_bytes(), "Rust")
+            .and_then(TreeSitterFile::scope_graph)
+            .unwrap();
+        let doc = ContentDocument {
+            relative_path: "src/main.rs".to_owned(),
+            lang: Some("rust".to_owned()),
+            symbol_locations: SymbolLocations::TreeSitter(graph),
+            ..document(src)
+        };
+
+        let exported = export_document(&doc, "bloop").unwrap();
+        assert_eq!(exported.language, "Rust");
+
+        let kinds = exported
+            .symbols
+            .iter()
+            .map(|s| (s.display_name.as_str(), s.kind))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(kinds["parse"], 17);
+        assert_eq!(kinds["m"], 61);
+
+        // exported indexes can be ingested again
+        let index = proto::Index {
+            metadata: None,
+            documents: vec![exported.clone()],
+        };
+        let decoded = decode(&index.encode_to_vec()).unwrap();
+        let find = |name: &str, is_definition| {
+            decoded
+                .occurrences
+                .iter()
+                .filter(|o| o.is_definition == is_definition)
+                .find(|o| byte_range(&doc, o).map(|range| &src[range]) == Some(name))
+                .unwrap()
+        };
+
+        let parse = find("parse", true);
+        assert_eq!(parse.symbol, "bleep . bloop . Rust/parse.");
+        assert_eq!(find("parse", false).symbol, parse.symbol);
+
+        let local = find("m", true);
+        assert!(local.symbol.starts_with(LOCAL_PREFIX));
+        assert_eq!(find("m", false).symbol, local.symbol);
+
+        // imports and unresolved references are linked by name
+        assert_eq!(
+            find("HashMap", false).symbol,
+            "bleep . bloop . Rust/HashMap."
+        );
+        assert_eq!(find("helper", false).symbol, "bleep . bloop . Rust/helper.");
+    }
+
+    #[test]
+    fn escape_descriptors() {
+        assert_eq!(escape("parse_nl"), "parse_nl");
+        assert_eq!(escape("C#"), "`C#`");
+        assert_eq!(escape("a`b"), "`a``b`");
+    }
+}
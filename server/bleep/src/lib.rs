@@ -36,7 +36,7 @@ use std::{path::Path, sync::Arc};
 use tracing::{debug, error, info, warn, Level};
 use tracing_subscriber::{
     filter::{LevelFilter, Targets},
-    fmt,
+    fmt::{self, writer::BoxMakeWriter},
     prelude::*,
     EnvFilter,
 };
@@ -47,6 +47,7 @@ mod collector;
 mod config;
 mod db;
 mod env;
+mod lsp;
 mod remotes;
 mod repo;
 mod webserver;
@@ -251,6 +252,8 @@ impl Application {
 
         if self.config.index_only {
             joins.spawn(self.write_index().startup_scan());
+        } else if self.config.lsp {
+            joins.spawn(lsp::serve(self));
         } else {
             if !self.config.disable_background {
                 tokio::spawn(periodic::sync_github_status(self.clone()));
@@ -345,7 +348,15 @@ impl FromRef<Application> for axum_extra::extract::cookie::Key {
 }
 
 fn tracing_subscribe(config: &Configuration) -> bool {
-    let env_filter_layer = fmt::layer().with_filter(EnvFilter::from_env(LOG_ENV_VAR));
+    // the language server speaks over stdout, so logs can't be written there
+    let writer = if config.lsp {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let env_filter_layer = fmt::layer()
+        .with_writer(writer)
+        .with_filter(EnvFilter::from_env(LOG_ENV_VAR));
     let sentry_layer = sentry_layer();
     let log_writer_layer = (!config.disable_log_write).then(|| {
         let log_dir = config.index_dir.join("logs");
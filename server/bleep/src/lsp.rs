@@ -0,0 +1,450 @@
+//! A Language Server Protocol front-end for code navigation, started with `bleep --lsp`.
+//!
+//! The server speaks over stdin and stdout. Requests are answered from the index rather than the
+//! editor buffer, so results reflect the last indexed version of each file, and files are mapped to
+//! indexed repositories by their location on disk.
+
+mod protocol;
+
+use std::{ops::Range, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tantivy::collector::{MultiCollector, TopDocs};
+use tokio::io::{self, BufReader};
+use tracing::{debug, warn};
+
+use self::protocol::{
+    DocumentSymbolParams, Hover, Incoming, Location, MarkupContent, Position, ReferenceParams,
+    Response, ResponseError, SymbolInformation, TextDocumentPositionParams, WorkspaceSymbolParams,
+};
+use crate::{
+    db::SymbolTable,
+    indexes::reader::{ContentDocument, ContentReader},
+    intelligence::{
+        code_navigation::{CodeNavigationContext, FileSymbols, Occurrence, Token},
+        scip, Language, NodeKind, TSLanguage,
+    },
+    query::parser::{Expression, Literal, Query, Target},
+    repo::RepoRef,
+    text_range::Point,
+    webserver::intelligence::symbol_table_docs,
+    Application,
+};
+
+/// Number of files whose symbols are searched by `workspace/symbol`
+const SYMBOL_FILES: usize = 50;
+
+/// Maximum number of symbols returned by `workspace/symbol`
+const MAX_WORKSPACE_SYMBOLS: usize = 100;
+
+/// Serve requests on stdin until the client sends `exit`, or closes the stream.
+pub(crate) async fn serve(app: Application) -> Result<()> {
+    let mut stdin = BufReader::new(io::stdin());
+    let mut stdout = io::stdout();
+    let server = Server { app };
+
+    while let Some(incoming) = protocol::read_message(&mut stdin).await? {
+        let message = match incoming {
+            Incoming::Message(message) => message,
+            // invalid requests are answered with an error, and the server keeps reading
+            Incoming::Invalid(id, err) => {
+                warn!(?err, "invalid message");
+                if let Some(id) = id {
+                    let error = ResponseError::new(protocol::PARSE_ERROR, err.to_string());
+                    protocol::write_message(&mut stdout, &Response::new(id, Err(error))).await?;
+                }
+                continue;
+            }
+        };
+
+        let Some(method) = message.method else {
+            continue;
+        };
+
+        match message.id {
+            _ if method == "exit" => break,
+            Some(id) => {
+                let result = server.handle(&method, message.params).await;
+                protocol::write_message(&mut stdout, &Response::new(id, result)).await?;
+            }
+            // notifications such as `textDocument/didChange` are ignored, as documents are read
+            // from the index
+            None => debug!(%method, "ignoring notification"),
+        }
+    }
+
+    Ok(())
+}
+
+struct Server {
+    app: Application,
+}
+
+/// An indexed file, located by its URI.
+struct IndexedFile {
+    repo_ref: RepoRef,
+    doc: ContentDocument,
+}
+
+/// The result of navigating from a token.
+struct Navigation {
+    /// The byte range of the token
+    token: Range<usize>,
+    is_definition: bool,
+    files: Vec<FileSymbols>,
+}
+
+impl Server {
+    async fn handle(&self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        let result = match method {
+            "initialize" => Ok(protocol::server_capabilities()),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/definition" => self.definition(parse(params)?).await,
+            "textDocument/references" => self.references(parse(params)?).await,
+            "textDocument/hover" => self.hover(parse(params)?).await,
+            "textDocument/documentSymbol" => self.document_symbol(parse(params)?).await,
+            "workspace/symbol" => self.workspace_symbol(parse(params)?).await,
+            _ => {
+                return Err(ResponseError::new(
+                    protocol::METHOD_NOT_FOUND,
+                    format!("unsupported method `{method}`"),
+                ))
+            }
+        };
+
+        result.map_err(|err| ResponseError::new(protocol::REQUEST_FAILED, err.to_string()))
+    }
+
+    async fn definition(&self, params: TextDocumentPositionParams) -> Result<Value> {
+        let file = self.file(&params.text_document.uri).await?;
+        let Some(nav) = self.navigate(&file, params.position).await? else {
+            return Ok(Value::Null);
+        };
+
+        let locations = self.locations(&file, &nav.files, Occurrence::is_definition);
+        Ok(serde_json::to_value(locations)?)
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Value> {
+        let uri = &params.position.text_document.uri;
+        let file = self.file(uri).await?;
+        let Some(nav) = self.navigate(&file, params.position.position).await? else {
+            return Ok(Value::Null);
+        };
+
+        let include_declaration = params.context.include_declaration;
+        let mut locations = self.locations(&file, &nav.files, |o| {
+            include_declaration || !o.is_definition()
+        });
+
+        // references of a definition don't include the definition itself
+        if include_declaration && nav.is_definition {
+            locations.insert(
+                0,
+                Location {
+                    uri: uri.clone(),
+                    range: protocol::range_at(&file.doc.content, nav.token),
+                },
+            );
+        }
+
+        Ok(serde_json::to_value(locations)?)
+    }
+
+    /// Show the definition of the hovered token, or the line it is defined on if it is a
+    /// definition itself.
+    async fn hover(&self, params: TextDocumentPositionParams) -> Result<Value> {
+        let file = self.file(&params.text_document.uri).await?;
+        let Some(nav) = self.navigate(&file, params.position).await? else {
+            return Ok(Value::Null);
+        };
+
+        let content = &file.doc.content;
+        let definition = if nav.is_definition {
+            let start = content[..nav.token.start].rfind('\n').map_or(0, |i| i + 1);
+            let end = content[nav.token.end..]
+                .find('\n')
+                .map_or(content.len(), |i| nav.token.end + i);
+            Some(&content[start..end])
+        } else {
+            nav.files
+                .iter()
+                .flat_map(|f| &f.data)
+                .find(|o| o.is_definition())
+                .map(|o| o.snippet.data.as_str())
+        };
+
+        let Some(definition) = definition else {
+            return Ok(Value::Null);
+        };
+
+        let lang = file.doc.lang.as_deref().unwrap_or_default().to_lowercase();
+        Ok(serde_json::to_value(Hover {
+            contents: MarkupContent {
+                kind: "markdown",
+                value: format!("```{lang}\n{}\n```", definition.trim_end()),
+            },
+            range: protocol::range_at(content, nav.token),
+        })?)
+    }
+
+    async fn document_symbol(&self, params: DocumentSymbolParams) -> Result<Value> {
+        let uri = &params.text_document.uri;
+        let file = self.file(uri).await?;
+
+        let mut symbols = file.doc.symbol_locations.list();
+        symbols.sort_by_key(|s| s.range.start.byte);
+
+        let symbols = symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let range = symbol.range.start.byte..symbol.range.end.byte;
+                Some(SymbolInformation {
+                    name: file.doc.content.get(range.clone())?.to_owned(),
+                    kind: protocol::symbol_kind(&symbol.kind),
+                    location: Location {
+                        uri: uri.clone(),
+                        range: protocol::range_at(&file.doc.content, range),
+                    },
+                    container_name: None,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(serde_json::to_value(symbols)?)
+    }
+
+    /// Find symbols whose name contains the query, in any indexed repository.
+    async fn workspace_symbol(&self, params: WorkspaceSymbolParams) -> Result<Value> {
+        if params.query.is_empty() {
+            return Ok(Value::Array(vec![]));
+        }
+
+        let expr = Expression::Term(Query {
+            target: Some(Target::Symbol(Literal::Plain(params.query.as_str().into()))),
+            ..Default::default()
+        });
+
+        let collector = (TopDocs::with_limit(SYMBOL_FILES), MultiCollector::new());
+        let results = self
+            .app
+            .indexes
+            .file
+            .query(&expr, &ContentReader, collector)
+            .await?;
+
+        let query = params.query.to_lowercase();
+        let mut symbols = Vec::new();
+        for doc in results.docs {
+            let Some(uri) = doc
+                .repo_ref
+                .parse::<RepoRef>()
+                .ok()
+                .and_then(|repo_ref| self.uri(&repo_ref, &doc.relative_path))
+            else {
+                continue;
+            };
+
+            for symbol in doc.symbol_locations.list() {
+                let range = symbol.range.start.byte..symbol.range.end.byte;
+                let Some(name) = doc.content.get(range.clone()) else {
+                    continue;
+                };
+
+                if !name.to_lowercase().contains(&query) {
+                    continue;
+                }
+
+                symbols.push(SymbolInformation {
+                    name: name.to_owned(),
+                    kind: protocol::symbol_kind(&symbol.kind),
+                    location: Location {
+                        uri: uri.clone(),
+                        range: protocol::range_at(&doc.content, range),
+                    },
+                    container_name: Some(doc.repo_name.clone()),
+                });
+            }
+        }
+
+        symbols.truncate(MAX_WORKSPACE_SYMBOLS);
+        Ok(serde_json::to_value(symbols)?)
+    }
+
+    /// Find the indexed document of a file, from the repository it is checked out in.
+    async fn file(&self, uri: &str) -> Result<IndexedFile> {
+        let path = protocol::file_path(uri).context("not a file URI")?;
+        let path = std::fs::canonicalize(&path).unwrap_or(path);
+
+        // the innermost repository wins, in case repositories are nested
+        let mut repo = None::<(RepoRef, PathBuf)>;
+        self.app.repo_pool.scan(|repo_ref, r| {
+            let deeper = repo.as_ref().map_or(true, |(_, root)| {
+                r.disk_path.components().count() > root.components().count()
+            });
+
+            if path.starts_with(&r.disk_path) && deeper {
+                repo = Some((repo_ref.clone(), r.disk_path.clone()));
+            }
+        });
+
+        let (repo_ref, root) = repo.context("file is not in an indexed repository")?;
+        let relative_path = path
+            .strip_prefix(&root)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let doc = self
+            .app
+            .indexes
+            .file
+            .by_path(&repo_ref, &relative_path, None)
+            .await?
+            .context("file is not indexed")?;
+
+        Ok(IndexedFile { repo_ref, doc })
+    }
+
+    /// The URI of a file in an indexed repository.
+    fn uri(&self, repo_ref: &RepoRef, relative_path: &str) -> Option<String> {
+        self.app.repo_pool.read(repo_ref, |_, repo| {
+            protocol::file_uri(&repo.disk_path.join(relative_path))
+        })
+    }
+
+    /// Run code navigation for the token at `position`, over the same documents that
+    /// `/api/token-info` would use.
+    async fn navigate(&self, file: &IndexedFile, position: Position) -> Result<Option<Navigation>> {
+        let doc = &file.doc;
+        let Some(offset) = protocol::offset_at(&doc.content, position) else {
+            return Ok(None);
+        };
+
+        // precise navigation from a SCIP index takes precedence over scope graphs
+        if let Some(info) =
+            scip::token_info(&self.app, &file.repo_ref, doc, offset..offset, None).await
+        {
+            return Ok(Some(Navigation {
+                token: info.range,
+                is_definition: info.is_definition,
+                files: info.data,
+            }));
+        }
+
+        let Some(graph) = doc.symbol_locations.scope_graph() else {
+            return Ok(None);
+        };
+
+        let Some(idx) = graph.node_by_range(offset, offset) else {
+            return Ok(None);
+        };
+
+        let range = graph.graph[idx].range();
+        let token = range.start.byte..range.end.byte;
+        let is_definition = matches!(graph.graph[idx], NodeKind::Def(_));
+
+        let langs = match doc.lang.as_deref().map(TSLanguage::from_id) {
+            Some(Language::Supported(config)) => config.language_ids,
+            _ => &[],
+        };
+
+        let indexes = &self.app.indexes;
+        let symbols = SymbolTable::new(&self.app.sql);
+        let name = &doc.content[token.clone()];
+        let all_docs = match symbol_table_docs(
+            indexes,
+            &symbols,
+            &file.repo_ref,
+            name,
+            langs,
+            None,
+            doc,
+        )
+        .await
+        {
+            Some(docs) => docs,
+            None => {
+                indexes
+                    .file
+                    .by_repo(&file.repo_ref, langs.iter(), None)
+                    .await
+            }
+        };
+
+        let Some(source_document_idx) = all_docs
+            .iter()
+            .position(|d| d.relative_path == doc.relative_path)
+        else {
+            return Ok(None);
+        };
+
+        let ctx = CodeNavigationContext {
+            repo_ref: file.repo_ref.clone(),
+            token: Token {
+                relative_path: &doc.relative_path,
+                start_byte: token.start,
+                end_byte: token.end,
+            },
+            all_docs,
+            source_document_idx,
+        };
+
+        Ok(Some(Navigation {
+            files: ctx.token_info(),
+            token,
+            is_definition,
+        }))
+    }
+
+    /// The locations of the occurrences found by code navigation, which are all in the same
+    /// repository as the navigated file.
+    fn locations(
+        &self,
+        file: &IndexedFile,
+        files: &[FileSymbols],
+        include: impl Fn(&Occurrence) -> bool,
+    ) -> Vec<Location> {
+        files
+            .iter()
+            .filter_map(|f| Some((self.uri(&file.repo_ref, &f.file)?, &f.data)))
+            .flat_map(|(uri, data)| {
+                data.iter().filter(|o| include(o)).map(move |o| Location {
+                    uri: uri.clone(),
+                    range: occurrence_range(o),
+                })
+            })
+            .collect()
+    }
+}
+
+fn parse<T: DeserializeOwned>(params: Value) -> Result<T, ResponseError> {
+    serde_json::from_value(params)
+        .map_err(|err| ResponseError::new(protocol::INVALID_PARAMS, err.to_string()))
+}
+
+/// The LSP range of an occurrence, counting UTF-16 code units in the lines of its snippet.
+fn occurrence_range(occurrence: &Occurrence) -> protocol::Range {
+    let snippet = &occurrence.snippet;
+    let position = |point: &Point| {
+        let character = point
+            .line
+            .checked_sub(snippet.line_range.start)
+            .and_then(|i| snippet.data.lines().nth(i))
+            .and_then(|line| line.get(..point.column))
+            .map_or(point.column, |prefix| prefix.encode_utf16().count());
+
+        Position {
+            line: point.line as u32,
+            character: character as u32,
+        }
+    };
+
+    protocol::Range {
+        start: position(&occurrence.range.start),
+        end: position(&occurrence.range.end),
+    }
+}
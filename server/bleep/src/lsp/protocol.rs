@@ -0,0 +1,478 @@
+//! The subset of the Language Server Protocol that bleep speaks, and its JSON-RPC framing.
+//!
+//! Messages are JSON objects preceded by a `Content-Length` header, as described in the
+//! [base protocol](https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#baseProtocol).
+//! Positions count UTF-16 code units, which is the only encoding every client supports.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// A message could not be parsed.
+pub const PARSE_ERROR: i64 = -32700;
+
+/// The method was not found, or is not supported.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The params of a request could not be parsed.
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// A request failed for a reason other than invalid input.
+pub const REQUEST_FAILED: i64 = -32803;
+
+/// A request or a notification sent by the client.
+///
+/// Notifications don't have an `id`, and don't get a response. Responses to requests sent by the
+/// server have no `method`, and are ignored, as bleep never sends any.
+#[derive(Deserialize, Debug)]
+pub struct Message {
+    #[serde(default)]
+    pub id: Option<Value>,
+
+    #[serde(default)]
+    pub method: Option<String>,
+
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// A message read from the client.
+#[derive(Debug)]
+pub enum Incoming {
+    Message(Message),
+
+    /// A message that could not be parsed, along with the `id` of the request if it has one.
+    ///
+    /// The stream can still be read from, as the body of the message was skipped.
+    Invalid(Option<Value>, anyhow::Error),
+}
+
+#[derive(Serialize, Debug)]
+pub struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseError>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Response {
+    pub fn new(id: Value, result: std::result::Result<Value, ResponseError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+impl ResponseError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Read the next message, returning `None` once the client closes the stream.
+///
+/// Only I/O errors are returned as errors. Invalid messages are returned as such, so that the
+/// server can keep reading the messages that follow.
+pub async fn read_message(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<Option<Incoming>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let Some(content_length) = content_length else {
+        let err = anyhow!("message without a valid Content-Length header");
+        return Ok(Some(Incoming::Invalid(None, err)));
+    };
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some(match serde_json::from_slice::<Value>(&body) {
+        Ok(value) => match Message::deserialize(&value) {
+            Ok(message) => Incoming::Message(message),
+            Err(err) => Incoming::Invalid(value.get("id").cloned(), err.into()),
+        },
+        Err(err) => Incoming::Invalid(None, err.into()),
+    }))
+}
+
+pub async fn write_message(
+    writer: &mut (impl AsyncWrite + Unpin),
+    message: &impl Serialize,
+) -> Result<()> {
+    let body = serde_json::to_vec(message)?;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+        .await?;
+    writer.write_all(&body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TextDocumentIdentifier {
+    pub uri: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentPositionParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceContext {
+    pub include_declaration: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceParams {
+    #[serde(flatten)]
+    pub position: TextDocumentPositionParams,
+    pub context: ReferenceContext,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbolParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WorkspaceSymbolParams {
+    pub query: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInformation {
+    pub name: String,
+    pub kind: u32,
+    pub location: Location,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Hover {
+    pub contents: MarkupContent,
+    pub range: Range,
+}
+
+#[derive(Serialize, Debug)]
+pub struct MarkupContent {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+/// The capabilities advertised in response to `initialize`.
+pub fn server_capabilities() -> Value {
+    serde_json::json!({
+        "capabilities": {
+            // documents are read from the index, so the editor buffer is never synced
+            "textDocumentSync": 0,
+            "definitionProvider": true,
+            "referencesProvider": true,
+            "hoverProvider": true,
+            "documentSymbolProvider": true,
+            "workspaceSymbolProvider": true,
+        },
+        "serverInfo": {
+            "name": "bleep",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+    })
+}
+
+/// The `SymbolKind` of a bleep symbol kind, such as `function` or `struct`.
+pub fn symbol_kind(kind: &str) -> u32 {
+    match kind {
+        "module" | "header" => 2,
+        "namespace" => 3,
+        "package" => 4,
+        "class" | "record" => 5,
+        "method" => 6,
+        "property" => 7,
+        "field" | "member" => 8,
+        "enum" => 10,
+        "interface" | "trait" => 11,
+        "function" | "func" | "generator" | "macro" => 12,
+        "constant" | "const" => 14,
+        "enumerator" => 22,
+        "struct" | "union" => 23,
+        "typedef" | "type" | "alias" => 26,
+        // variables, parameters, locals and anything else
+        _ => 13,
+    }
+}
+
+/// The byte offset of an LSP position in `content`, if it is within bounds.
+///
+/// Characters past the end of a line are clamped to the end of that line, as the spec requires.
+pub fn offset_at(content: &str, position: Position) -> Option<usize> {
+    let line_start = if position.line == 0 {
+        0
+    } else {
+        content
+            .match_indices('\n')
+            .nth(position.line as usize - 1)
+            .map(|(i, _)| i + 1)?
+    };
+
+    let line = &content[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return Some(line_start + i);
+        }
+        units += c.len_utf16();
+    }
+
+    Some(line_start + line.len())
+}
+
+/// The LSP position of a byte offset in `content`.
+pub fn position_at(content: &str, offset: usize) -> Position {
+    let prefix = &content[..offset.min(content.len())];
+    let line_start = prefix.rfind('\n').map_or(0, |i| i + 1);
+
+    Position {
+        line: prefix.matches('\n').count() as u32,
+        character: prefix[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+pub fn range_at(content: &str, range: std::ops::Range<usize>) -> Range {
+    Range {
+        start: position_at(content, range.start),
+        end: position_at(content, range.end),
+    }
+}
+
+/// The local path of a `file://` URI.
+pub fn file_path(uri: &str) -> Option<PathBuf> {
+    let path = percent_decode(uri.strip_prefix("file://")?)?;
+
+    // windows paths are written as `file:///C:/path`
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+        _ => &path,
+    };
+
+    Some(PathBuf::from(path))
+}
+
+/// The `file://` URI of a local path.
+pub fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{b:02X}")),
+        }
+    }
+
+    uri
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn framing() {
+        let mut out = Vec::new();
+        write_message(
+            &mut out,
+            &serde_json::json!({"id": 1, "method": "shutdown"}),
+        )
+        .await
+        .unwrap();
+
+        let framed = String::from_utf8(out.clone()).unwrap();
+        assert!(framed.starts_with("Content-Length: 28\r\n\r\n{"));
+
+        let mut reader = &out[..];
+        let Some(Incoming::Message(message)) = read_message(&mut reader).await.unwrap() else {
+            panic!("expected a message");
+        };
+        assert_eq!(message.id, Some(1.into()));
+        assert_eq!(message.method.as_deref(), Some("shutdown"));
+        assert!(read_message(&mut reader).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn invalid_messages() {
+        let input = concat!(
+            "Content-Type: application/json\r\n\r\n",
+            "Content-Length: 5\r\n\r\n{oops",
+            "Content-Length: 22\r\n\r\n{\"id\": 2, \"method\": 3}",
+            "Content-Length: 27\r\n\r\n{\"id\": 3, \"method\": \"exit\"}",
+        );
+        let mut reader = input.as_bytes();
+
+        let mut read = Vec::new();
+        while let Some(incoming) = read_message(&mut reader).await.unwrap() {
+            read.push(match incoming {
+                Incoming::Message(message) => (true, message.id),
+                Incoming::Invalid(id, _) => (false, id),
+            });
+        }
+
+        assert_eq!(
+            read,
+            [
+                (false, None),
+                (false, None),
+                (false, Some(2.into())),
+                (true, Some(3.into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn positions() {
+        let content = "fn main() {\n    let é = \"𝄞x\";\n}\n";
+
+        let x = content.find('x').unwrap();
+        let position = position_at(content, x);
+
+        // `𝄞` takes two UTF-16 code units, `é` one
+        assert_eq!(
+            position,
+            Position {
+                line: 1,
+                character: 15
+            }
+        );
+        assert_eq!(offset_at(content, position), Some(x));
+
+        // characters past the end of a line are clamped
+        let end = offset_at(
+            content,
+            Position {
+                line: 0,
+                character: 100,
+            },
+        );
+        assert_eq!(end, Some(content.find('\n').unwrap()));
+
+        assert_eq!(
+            offset_at(
+                content,
+                Position {
+                    line: 10,
+                    character: 0
+                }
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn uris() {
+        let path = Path::new("/home/me/my repo/src/main.rs");
+        let uri = file_uri(path);
+        assert_eq!(uri, "file:///home/me/my%20repo/src/main.rs");
+        assert_eq!(file_path(&uri).unwrap(), path);
+
+        assert_eq!(
+            file_path("file:///C%3A/src/lib.rs").unwrap(),
+            PathBuf::from("C:/src/lib.rs")
+        );
+        assert_eq!(file_path("untitled:Untitled-1"), None);
+    }
+
+    #[test]
+    fn symbol_kinds() {
+        assert_eq!(symbol_kind("function"), 12);
+        assert_eq!(symbol_kind("struct"), 23);
+        assert_eq!(symbol_kind("parameter"), 13);
+    }
+}
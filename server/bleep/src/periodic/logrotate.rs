@@ -62,18 +62,16 @@ fn collect_branches_for_repos(queries: Vec<String>) -> scc::HashMap<String, Hash
     queries.par_iter().for_each(|q| {
         let parsed = parser::parse_nl(q);
         match parsed {
-            Ok(ParsedQuery::Grep(list)) => {
-                for q in list {
-                    if let Some((r, b)) = q
-                        .repo
-                        .and_then(|r| r.as_plain())
-                        .zip(q.branch.and_then(|b| b.as_plain()))
-                    {
-                        record_branch(&map, r, b);
+            Ok(ParsedQuery::Grep(expr)) => {
+                for q in expr.positive_terms() {
+                    if let Some(r) = q.repo.as_ref().and_then(|r| r.as_plain()) {
+                        for b in q.branches.iter().filter_map(|b| b.as_plain()) {
+                            record_branch(&map, r.clone(), b);
+                        }
                     }
                 }
             }
-            Ok(ParsedQuery::Semantic(semantic)) => {
+            Ok(ParsedQuery::Semantic(semantic) | ParsedQuery::Hybrid(semantic)) => {
                 for r in semantic.repos() {
                     for b in semantic.branch() {
                         record_branch(&map, r.clone(), b);
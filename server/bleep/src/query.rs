@@ -1,6 +1,14 @@
+pub mod ast;
 pub mod compiler;
+pub mod cursor;
 pub mod execute;
+pub mod explain;
+pub mod export;
+pub mod facets;
+pub mod fuzzy;
 pub mod languages;
 pub mod parser;
 pub mod planner;
 pub mod ranking;
+pub mod replace;
+pub mod suggest;
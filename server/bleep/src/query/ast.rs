@@ -0,0 +1,210 @@
+//! Helpers for structural search, using tree-sitter query patterns such as:
+//!
+//! ```text
+//! (call_expression function: (identifier) @name (#eq? @name "parse"))
+//! ```
+
+use std::ops::Range;
+
+use super::parser::ParseError;
+use crate::intelligence::{Language, TSLanguage, TSLanguageConfig, TreeSitterFile, ALL_LANGUAGES};
+
+/// Predicates whose arguments appear verbatim in every match.
+///
+/// Arguments to other predicates are skipped: they can be regular expressions (`#match?`), text
+/// that must not appear (`#not-eq?`), or alternatives of which only one appears (`#any-of?`).
+const LITERAL_PREDICATES: &[&str] = &["#eq?"];
+
+/// Extract all string literals from a tree-sitter query pattern.
+///
+/// These are anonymous nodes like `"fn"`, or arguments to predicates like `#eq?`. Any match of the
+/// pattern contains every one of these strings verbatim.
+pub fn literals(pattern: &str) -> Vec<String> {
+    let mut literals = Vec::new();
+    let mut predicate = String::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // a new node or predicate starts
+            '(' | ')' => predicate.clear(),
+
+            '#' => {
+                predicate.clear();
+                predicate.push(c);
+                predicate.extend(chars.by_ref().take_while(|c| !c.is_whitespace()));
+            }
+
+            '"' => {
+                let mut literal = String::new();
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => literal.push('\n'),
+                            Some('t') => literal.push('\t'),
+                            Some(c) => literal.push(c),
+                            None => break,
+                        },
+                        c => literal.push(c),
+                    }
+                }
+
+                if !literal.is_empty()
+                    && (predicate.is_empty() || LITERAL_PREDICATES.contains(&predicate.as_str()))
+                {
+                    literals.push(literal);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    literals
+}
+
+/// Get the longest string literal in a pattern, which is used to narrow down candidate documents.
+pub fn longest_literal(pattern: &str) -> Option<String> {
+    literals(pattern).into_iter().max_by_key(String::len)
+}
+
+/// Ensure a pattern has at least one capture, so that there is something to highlight.
+///
+/// Patterns without captures are captured in their entirety.
+pub fn with_capture(pattern: &str) -> String {
+    if pattern.contains('@') {
+        pattern.to_owned()
+    } else {
+        format!("{} @match", pattern.trim())
+    }
+}
+
+/// A structural search pattern, compiled for every language whose grammar it is valid in.
+///
+/// Patterns name the nodes of a specific grammar, so a pattern for Rust is usually not valid in
+/// any other language, and never matches documents written in them.
+pub struct Pattern {
+    queries: Vec<(&'static TSLanguageConfig, tree_sitter::Query)>,
+}
+
+impl Pattern {
+    /// Compile a pattern, failing if it is not valid in the grammar of any supported language.
+    pub fn new(pattern: &str) -> Result<Self, ParseError> {
+        let pattern = with_capture(pattern);
+        let mut error = None;
+
+        let queries = ALL_LANGUAGES
+            .iter()
+            .filter_map(
+                |&language| match tree_sitter::Query::new((language.grammar)(), &pattern) {
+                    Ok(query) => Some((language, query)),
+                    Err(err) => {
+                        error.get_or_insert(err);
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+
+        match error {
+            Some(err) if queries.is_empty() => Err(ParseError::InvalidPattern(format!(
+                "{} at line {}, column {}",
+                err.message,
+                err.row + 1,
+                err.column + 1
+            ))),
+            _ => Ok(Self { queries }),
+        }
+    }
+
+    /// The byte ranges of the nodes captured in a document, sorted by their position, or `None`
+    /// if the pattern doesn't match it.
+    pub fn matches(&self, content: &[u8], lang: &str) -> Option<Vec<Range<usize>>> {
+        let Language::Supported(language) = TSLanguage::from_id(lang) else {
+            return None;
+        };
+
+        let (_, query) = self
+            .queries
+            .iter()
+            .find(|(l, _)| std::ptr::eq(*l, language))?;
+
+        let ranges = TreeSitterFile::try_build(content, lang)
+            .ok()?
+            .structural_matches(query);
+
+        (!ranges.is_empty()).then_some(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_literals() {
+        assert_eq!(
+            literals(r#"(call_expression function: (identifier) @f (#eq? @f "parse"))"#),
+            vec!["parse".to_owned()],
+        );
+
+        assert_eq!(
+            literals(r#"(function_item "fn" name: (identifier) @name (#match? @name "^test_"))"#),
+            vec!["fn".to_owned()],
+        );
+
+        assert_eq!(
+            literals(r#"((string_literal) @s (#eq? @s "\"quoted\""))"#),
+            vec![r#""quoted""#.to_owned()],
+        );
+
+        assert!(literals("(struct_item name: (type_identifier) @name)").is_empty());
+
+        // excluded text and alternatives don't necessarily appear in a match
+        assert_eq!(
+            literals(r#"(function_item "fn" name: (identifier) @x (#not-eq? @x "foo"))"#),
+            vec!["fn".to_owned()],
+        );
+        assert!(literals(r#"((identifier) @x (#any-of? @x "a" "bbbb"))"#).is_empty());
+        assert_eq!(
+            longest_literal(r#"((identifier) @x (#any-of? @x "a" "bbbb"))"#),
+            None
+        );
+    }
+
+    #[test]
+    fn longest() {
+        assert_eq!(
+            longest_literal(r#"(use_declaration "use" (#eq? @x "std::collections"))"#).as_deref(),
+            Some("std::collections"),
+        );
+
+        assert_eq!(longest_literal("(identifier)"), None);
+    }
+
+    #[test]
+    fn captures() {
+        assert_eq!(with_capture("(identifier)"), "(identifier) @match");
+        assert_eq!(with_capture("(identifier) @id"), "(identifier) @id");
+    }
+
+    #[test]
+    fn patterns() {
+        let pattern =
+            Pattern::new(r#"(function_item name: (identifier) @name (#eq? @name "parse"))"#)
+                .unwrap();
+
+        let src = b"fn parse() {}\nfn main() { parse(); }\n";
+        assert_eq!(pattern.matches(src, "rust"), Some(vec![3..8]));
+        assert_eq!(pattern.matches(b"fn main() {}", "rust"), None);
+        assert_eq!(pattern.matches(b"def parse(): pass", "python"), None);
+
+        assert!(matches!(
+            Pattern::new("(function_item"),
+            Err(ParseError::InvalidPattern(_))
+        ));
+        assert!(Pattern::new("(no_such_node_kind)").is_err());
+    }
+}
@@ -2,6 +2,7 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     mem,
+    ops::Range,
 };
 
 use anyhow::{Context, Result};
@@ -9,13 +10,14 @@ use compact_str::CompactString;
 use either::Either;
 use smallvec::SmallVec;
 use tantivy::{
-    query::{AllQuery, BooleanQuery, BoostQuery, TermQuery},
-    schema::{Field, IndexRecordOption},
-    Index, Term,
+    query::{AllQuery, BooleanQuery, BoostQuery, EmptyQuery, Occur, RangeQuery, TermQuery},
+    schema::{BytesOptions, Field, IndexRecordOption},
+    Index, Score, Term,
 };
 
 use crate::query::{
-    parser::{Literal, Query},
+    fuzzy,
+    parser::{Expression, Literal, Query, QueryExpr},
     planner,
 };
 
@@ -25,17 +27,47 @@ enum Extraction<'a> {
     /// Match a literal against a tantivy `text` field.
     Literal(Literal<'a>),
 
+    /// Match any n-gram of a word against a tantivy `text` field, for typo-tolerant searches.
+    Fuzzy(Cow<'a, str>),
+
     /// Match a string against a tantivy `bytes` field.
     ByteString(&'a Cow<'a, str>),
+
+    /// Match a range of values against an indexed tantivy `u64` field.
+    Range(Range<u64>),
+
+    /// Match a value against an indexed tantivy `bool` field.
+    Bool(bool),
+
+    /// Match a value against an indexed tantivy `u64` field.
+    Value(u64),
+
+    /// Match the trigrams of a name against a tantivy `bytes` field of symbol name trigrams
+    /// prefixed with their kind, for any of the kinds.
+    KindedName(&'a HashSet<Cow<'a, str>>, &'a str),
 }
 
-/// A closure that tries to pull out an `Extraction` variant, given a `Query` reference.
-type Extractor = dyn for<'a> FnMut(&'a Query<'a>) -> Option<Extraction<'a>>;
+impl Extraction<'_> {
+    /// Whether the compiled query matches more documents than the extraction itself, as literals
+    /// are matched through their n-grams.
+    fn is_superset(&self) -> bool {
+        matches!(
+            self,
+            Self::Literal(..) | Self::Fuzzy(..) | Self::KindedName(..)
+        )
+    }
+}
+
+/// A closure that pulls out a list of `Extraction` variants, given a `Query` reference.
+///
+/// A field matches if any of the extracted terms match.
+type Extractor = dyn for<'a> FnMut(&'a Query<'a>) -> SmallVec<[Extraction<'a>; 1]>;
 
 #[derive(Default)]
 pub struct Compiler {
     priority: HashSet<Field>,
     extractors: HashMap<Field, Box<Extractor>>,
+    boosts: Vec<(Field, Score, Box<Extractor>)>,
 }
 
 impl Compiler {
@@ -61,7 +93,55 @@ impl Compiler {
     {
         self.extractors.insert(
             tantivy_field,
-            Box::new(move |q| extractor(q).map(Extraction::Literal)),
+            Box::new(move |q| extractor(q).map(Extraction::Literal).into_iter().collect()),
+        );
+
+        self
+    }
+
+    /// Add a literal field that tolerates typos in fuzzy queries to the compiler.
+    ///
+    /// This is similar to `literal`, but plain literals of `fuzzy:true` queries match any document
+    /// sharing an n-gram with them, leaving it to readers to find the actual fuzzy matches.
+    pub fn fuzzy_literal<F>(mut self, tantivy_field: Field, mut extractor: F) -> Self
+    where
+        F: for<'b> FnMut(&'b Query<'b>) -> Option<Literal<'b>> + 'static,
+    {
+        self.extractors.insert(
+            tantivy_field,
+            Box::new(move |q| {
+                extractor(q)
+                    .map(|lit| match lit {
+                        Literal::Plain(text) if q.is_fuzzy() && fuzzy::max_edits(&text) > 0 => {
+                            Extraction::Fuzzy(text)
+                        }
+                        lit => Extraction::Literal(lit),
+                    })
+                    .into_iter()
+                    .collect()
+            }),
+        );
+
+        self
+    }
+
+    /// Add a set of literals to the compiler.
+    ///
+    /// This is similar to `literal`, but the compiled query matches the `Field` against any of
+    /// the literals in the set.
+    pub fn literals<F>(mut self, tantivy_field: Field, mut extractor: F) -> Self
+    where
+        F: for<'b> FnMut(&'b Query<'b>) -> &'b HashSet<Literal<'b>> + 'static,
+    {
+        self.extractors.insert(
+            tantivy_field,
+            Box::new(move |q| {
+                extractor(q)
+                    .iter()
+                    .cloned()
+                    .map(Extraction::Literal)
+                    .collect()
+            }),
         );
 
         self
@@ -76,86 +156,368 @@ impl Compiler {
     {
         self.extractors.insert(
             tantivy_field,
-            Box::new(move |q| extractor(q).map(Extraction::ByteString)),
+            Box::new(move |q| {
+                extractor(q)
+                    .map(Extraction::ByteString)
+                    .into_iter()
+                    .collect()
+            }),
         );
         self
     }
 
-    /// Compile a list of queries into a single Tantivy query that matches any
-    /// of them.
-    pub fn compile<'a, I>(mut self, queries: I, index: &Index) -> Result<DynQuery>
+    /// Add a set of byte strings to the compiler.
+    ///
+    /// Matches any `Cow<str>` in the set against a tantivy `bytes` field.
+    pub fn byte_strings<F>(mut self, tantivy_field: Field, mut extractor: F) -> Self
     where
-        I: Iterator<Item = &'a Query<'a>>,
+        F: for<'b> FnMut(&'b Query<'b>) -> &'b HashSet<Cow<'b, str>> + 'static,
     {
-        let mut sub_queries: SmallVec<[DynQuery; 2]> = SmallVec::new();
+        self.extractors.insert(
+            tantivy_field,
+            Box::new(move |q| extractor(q).iter().map(Extraction::ByteString).collect()),
+        );
+        self
+    }
 
-        for query in queries {
-            let mut intersection = Vec::new();
+    /// Add a numeric range field to the compiler.
+    ///
+    /// Matches documents whose `u64` field lies within the extracted range.
+    pub fn range<F>(mut self, tantivy_field: Field, mut extractor: F) -> Self
+    where
+        F: for<'b> FnMut(&'b Query<'b>) -> Option<Range<u64>> + 'static,
+    {
+        self.extractors.insert(
+            tantivy_field,
+            Box::new(move |q| extractor(q).map(Extraction::Range).into_iter().collect()),
+        );
+        self
+    }
 
-            for (field, extractor) in &mut self.extractors {
-                let Some(extraction) = extractor(query) else {
-                    continue
-                };
-
-                let field_query = match extraction {
-                    Extraction::Literal(Literal::Plain(text)) => {
-                        let tokenizer = index
-                            .tokenizer_for_field(*field)
-                            .context("field is missing tokenizer")?;
-
-                        let mut token_stream = tokenizer.token_stream(&text);
-                        let tokens = std::iter::from_fn(move || {
-                            token_stream.next().map(|tok| CompactString::new(&tok.text))
-                        });
-
-                        let terms = if query.is_case_sensitive() {
-                            tokens.map(|s| str_to_query(*field, &s)).collect::<Vec<_>>()
-                        } else {
-                            tokens
-                                .map(|s| {
-                                    let terms = case_permutations(&s)
-                                        .map(|s| str_to_query(*field, &s))
-                                        .collect();
-
-                                    Box::new(BooleanQuery::union(terms)) as DynQuery
-                                })
-                                .collect()
-                        };
+    /// Add a boolean field to the compiler.
+    ///
+    /// Matches documents whose `bool` field is equal to the extracted value.
+    pub fn boolean<F>(mut self, tantivy_field: Field, mut extractor: F) -> Self
+    where
+        F: for<'b> FnMut(&'b Query<'b>) -> Option<bool> + 'static,
+    {
+        self.extractors.insert(
+            tantivy_field,
+            Box::new(move |q| extractor(q).map(Extraction::Bool).into_iter().collect()),
+        );
+        self
+    }
 
-                        let mut field_query: DynQuery = Box::new(BooleanQuery::intersection(terms));
+    /// Add a set of `u64` values to the compiler.
+    ///
+    /// Matches documents whose `u64` field is equal to any of the extracted values.
+    pub fn values<F>(mut self, tantivy_field: Field, mut extractor: F) -> Self
+    where
+        F: for<'b> FnMut(&'b Query<'b>) -> Vec<u64> + 'static,
+    {
+        self.extractors.insert(
+            tantivy_field,
+            Box::new(move |q| extractor(q).into_iter().map(Extraction::Value).collect()),
+        );
+        self
+    }
 
-                        if self.priority.contains(field) {
-                            field_query = Box::new(BoostQuery::new(field_query, 10.0));
-                        }
+    /// Add a field of symbol names indexed by kind to the compiler.
+    ///
+    /// The field holds every trigram of the name of each symbol, prefixed with the kind of that
+    /// symbol, as in `function\0par`. Documents match if, for any of the extracted kinds, every
+    /// trigram of the extracted name appears in the name of a symbol of that kind.
+    pub fn kinded_names<F>(mut self, tantivy_field: Field, mut extractor: F) -> Self
+    where
+        F: for<'b> FnMut(&'b Query<'b>) -> Option<(&'b HashSet<Cow<'b, str>>, &'b str)> + 'static,
+    {
+        self.extractors.insert(
+            tantivy_field,
+            Box::new(move |q| {
+                extractor(q)
+                    .map(|(kinds, name)| Extraction::KindedName(kinds, name))
+                    .into_iter()
+                    .collect()
+            }),
+        );
+        self
+    }
 
-                        field_query
-                    }
-                    Extraction::Literal(Literal::Regex(regex)) => {
-                        let plan = planner::plan(&regex)?;
-                        plan_to_query(plan, *field, query.is_case_sensitive())
-                    }
+    /// Boost the score of documents whose `Field` matches an extracted literal.
+    ///
+    /// Unlike other fields, a boosted field does not restrict the set of matching documents.
+    pub fn boost<F>(mut self, tantivy_field: Field, weight: Score, mut extractor: F) -> Self
+    where
+        F: for<'b> FnMut(&'b Query<'b>) -> Option<Literal<'b>> + 'static,
+    {
+        self.boosts.push((
+            tantivy_field,
+            weight,
+            Box::new(move |q| extractor(q).map(Extraction::Literal).into_iter().collect()),
+        ));
+
+        self
+    }
+
+    /// Compile a boolean expression of queries into a single Tantivy query.
+    ///
+    /// Each `and`, `or` and negation is lowered into a nested `BooleanQuery`, so groups are never
+    /// expanded into every combination of their branches.
+    pub fn compile<'a>(mut self, expr: &'a QueryExpr<'a>, index: &Index) -> Result<DynQuery> {
+        self.compile_expr(expr, false, index)
+    }
+
+    fn compile_expr<'a>(
+        &mut self,
+        expr: &'a QueryExpr<'a>,
+        negated: bool,
+        index: &Index,
+    ) -> Result<DynQuery> {
+        Ok(match expr {
+            Expression::Term(query) => self.compile_query(query, negated, index)?,
+            Expression::And(exprs) => Box::new(BooleanQuery::intersection(
+                exprs
+                    .iter()
+                    .map(|e| self.compile_expr(e, negated, index))
+                    .collect::<Result<_>>()?,
+            )),
+            Expression::Or(exprs) => Box::new(BooleanQuery::union(
+                exprs
+                    .iter()
+                    .map(|e| self.compile_expr(e, negated, index))
+                    .collect::<Result<_>>()?,
+            )),
+            Expression::Not(expr) => Box::new(BooleanQuery::new(vec![
+                (Occur::Must, Box::new(AllQuery) as DynQuery),
+                (Occur::MustNot, self.compile_expr(expr, !negated, index)?),
+            ])),
+        })
+    }
+
+    /// Compile a single query, matching documents that match all of its terms.
+    ///
+    /// A `negated` query is excluded from the results, so it must not match more than it should.
+    /// Literals are matched through their n-grams, which are a superset of what they match, so
+    /// negated queries containing them match nothing at all, and readers post-filter these
+    /// instead.
+    fn compile_query<'a>(
+        &mut self,
+        query: &'a Query<'a>,
+        negated: bool,
+        index: &Index,
+    ) -> Result<DynQuery> {
+        let case_sensitive = query.is_case_sensitive();
+        let mut clauses = Vec::new();
+
+        for (field, extractor) in &mut self.extractors {
+            let extractions = extractor(query);
+            if negated && extractions.iter().any(Extraction::is_superset) {
+                return Ok(Box::new(EmptyQuery));
+            }
+
+            let priority = self.priority.contains(field);
+            let mut alternatives = extractions
+                .into_iter()
+                .map(|extraction| {
+                    // only plain literals are boosted, regexes and exact matches score as usual
+                    let boost =
+                        priority && matches!(extraction, Extraction::Literal(Literal::Plain(_)));
+                    let query = compile_extraction(extraction, *field, case_sensitive, index)?;
+                    Ok(if boost {
+                        Box::new(BoostQuery::new(query, 10.0)) as DynQuery
+                    } else {
+                        query
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let field_query: DynQuery = match alternatives.len() {
+                0 => continue,
+                1 => alternatives.pop().unwrap(),
+                _ => Box::new(BooleanQuery::union(alternatives)),
+            };
+
+            clauses.push((Occur::Must, field_query));
+        }
+
+        for (field, weight, extractor) in &mut self.boosts {
+            if *weight == 0.0 || negated {
+                continue;
+            }
+
+            for extraction in extractor(query) {
+                let field_query = compile_extraction(extraction, *field, case_sensitive, index)?;
+                clauses.push((
+                    Occur::Should,
+                    Box::new(BoostQuery::new(field_query, *weight)),
+                ));
+            }
+        }
 
-                    Extraction::ByteString(bs) => {
-                        let term = Term::from_field_bytes(*field, bs.as_bytes());
-                        let q = TermQuery::new(term, IndexRecordOption::Basic);
-                        Box::new(q) as DynQuery
+        'exclusions: for excluded in &query.exclude {
+            let mut fields = Vec::new();
+            for (field, extractor) in &mut self.extractors {
+                let extractions = extractor(excluded);
+
+                // N-grams are a superset of what a literal matches, and regex plans can even
+                // match every document. Excluding them would drop documents that merely share
+                // n-grams with the literal, so readers post-filter the whole exclusion instead,
+                // including its other labels, as the kind in `-symbol:function:parse`.
+                if extractions.iter().any(Extraction::is_superset) {
+                    if negated {
+                        return Ok(Box::new(EmptyQuery));
                     }
-                };
 
-                intersection.push(field_query);
+                    continue 'exclusions;
+                }
+
+                let mut alternatives = extractions
+                    .into_iter()
+                    .map(|extraction| compile_extraction(extraction, *field, case_sensitive, index))
+                    .collect::<Result<Vec<_>>>()?;
+
+                match alternatives.len() {
+                    0 => {}
+                    1 => fields.push(alternatives.pop().unwrap()),
+                    _ => fields.push(Box::new(BooleanQuery::union(alternatives))),
+                }
             }
 
-            sub_queries.push(Box::new(BooleanQuery::intersection(intersection)));
+            // an exclusion only excludes documents matching all of its labels
+            match fields.len() {
+                0 => {}
+                1 => clauses.push((Occur::MustNot, fields.pop().unwrap())),
+                _ => clauses.push((Occur::MustNot, Box::new(BooleanQuery::intersection(fields)))),
+            }
         }
 
-        Ok(if sub_queries.len() == 1 {
-            sub_queries.pop().unwrap()
-        } else {
-            Box::new(BooleanQuery::union(sub_queries.into_vec()))
-        })
+        // A negated query that doesn't restrict any field of this index, such as a content search
+        // when searching for file names, can't be used to exclude anything.
+        if negated && clauses.is_empty() {
+            return Ok(Box::new(EmptyQuery));
+        }
+
+        // A boolean query without any required clauses only matches what its optional clauses
+        // match, which is nothing if it is made up solely of exclusions. Queries that don't
+        // restrict any field, such as structural searches without literals, match everything.
+        if clauses.iter().all(|(occur, _)| *occur != Occur::Must) {
+            clauses.push((Occur::Must, Box::new(AllQuery)));
+        }
+
+        Ok(Box::new(BooleanQuery::new(clauses)))
     }
 }
 
+/// Compile a single extracted term into a query against `field`.
+fn compile_extraction(
+    extraction: Extraction<'_>,
+    field: Field,
+    case_sensitive: bool,
+    index: &Index,
+) -> Result<DynQuery> {
+    Ok(match extraction {
+        Extraction::Literal(Literal::Plain(text)) => {
+            let tokenizer = index
+                .tokenizer_for_field(field)
+                .context("field is missing tokenizer")?;
+
+            let mut token_stream = tokenizer.token_stream(&text);
+            let tokens = std::iter::from_fn(move || {
+                token_stream.next().map(|tok| CompactString::new(&tok.text))
+            });
+
+            let terms = if case_sensitive {
+                tokens.map(|s| str_to_query(field, &s)).collect::<Vec<_>>()
+            } else {
+                tokens
+                    .map(|s| {
+                        let terms = case_permutations(&s)
+                            .map(|s| str_to_query(field, &s))
+                            .collect();
+
+                        Box::new(BooleanQuery::union(terms)) as DynQuery
+                    })
+                    .collect()
+            };
+
+            Box::new(BooleanQuery::intersection(terms))
+        }
+        Extraction::Literal(Literal::Regex(regex)) => {
+            let plan = planner::plan(&regex)?;
+            plan_to_query(plan, field, case_sensitive)
+        }
+
+        // a typo only breaks the n-grams it overlaps, so any misspelling of the text still shares
+        // at least one n-gram with it
+        Extraction::Fuzzy(text) => {
+            let chars = text.chars().collect::<Vec<_>>();
+            let terms = chars
+                .windows(fuzzy::gram_len(&text))
+                .map(|gram| gram.iter().collect::<CompactString>())
+                .flat_map(|s| {
+                    if case_sensitive {
+                        Either::Left(std::iter::once(s))
+                    } else {
+                        Either::Right(case_permutations(&s))
+                    }
+                })
+                .map(|s| str_to_query(field, &s))
+                .collect();
+
+            Box::new(BooleanQuery::union(terms))
+        }
+
+        Extraction::ByteString(bs) => {
+            let term = Term::from_field_bytes(field, bs.as_bytes());
+            let q = TermQuery::new(term, IndexRecordOption::Basic);
+            Box::new(q) as DynQuery
+        }
+
+        Extraction::Range(range) => Box::new(RangeQuery::new_u64(field, range)) as DynQuery,
+
+        Extraction::Bool(value) => {
+            let term = Term::from_field_bool(field, value);
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as DynQuery
+        }
+
+        Extraction::Value(value) => {
+            let term = Term::from_field_u64(field, value);
+            Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as DynQuery
+        }
+
+        Extraction::KindedName(kinds, name) => {
+            let kinds = kinds
+                .iter()
+                .map(|kind| {
+                    let grams = trigrams(name)
+                        .map(|gram| {
+                            let grams = if case_sensitive {
+                                Either::Left(std::iter::once(gram))
+                            } else {
+                                Either::Right(case_permutations(&gram))
+                            };
+
+                            let terms = grams
+                                .map(|gram| {
+                                    Term::from_field_bytes(field, &kinded_gram(kind, &gram))
+                                })
+                                .map(|term| TermQuery::new(term, IndexRecordOption::Basic))
+                                .map(|q| Box::new(q) as DynQuery)
+                                .collect();
+
+                            Box::new(BooleanQuery::union(terms)) as DynQuery
+                        })
+                        .collect();
+
+                    Box::new(BooleanQuery::intersection(grams)) as DynQuery
+                })
+                .collect();
+
+            Box::new(BooleanQuery::union(kinds))
+        }
+    })
+}
+
 fn plan_to_query(plan: planner::Fragment, field: Field, case_sensitive: bool) -> DynQuery {
     match plan {
         planner::Fragment::Literal(s) => {
@@ -204,6 +566,12 @@ fn str_to_query(field: Field, s: &str) -> DynQuery {
 
 /// Split a string into trigrams, returning a bigram or unigram if the string is shorter than 3
 /// characters.
+/// A trigram of a symbol name, prefixed with the kind of the symbol, as indexed in the
+/// `kinded_symbols` field.
+pub fn kinded_gram(kind: &str, gram: &str) -> Vec<u8> {
+    format!("{kind}\0{gram}").into_bytes()
+}
+
 pub fn trigrams(s: &str) -> impl Iterator<Item = CompactString> {
     let mut chars = s.chars().collect::<SmallVec<[char; 6]>>();
 
@@ -304,8 +672,6 @@ pub fn case_permutations(s: &str) -> impl Iterator<Item = CompactString> {
 
 #[cfg(test)]
 mod tests {
-    use tantivy::query::Occur;
-
     use super::*;
 
     #[test]
@@ -353,6 +719,286 @@ mod tests {
         assert_eq!(out, &["a㐀b", "A㐀b", "a㐀B", "A㐀B"]);
     }
 
+    #[test]
+    fn test_compile_exclusions() {
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let lang = builder.add_bytes_field("lang", BytesOptions::default().set_indexed());
+        let index = Index::create_in_ram(builder.build());
+
+        let expr = crate::query::parser::parse("not lang:rust -lang:go").unwrap();
+        let query = Compiler::new()
+            .byte_strings(lang, |q| &q.langs)
+            .compile(&expr, &index)
+            .unwrap();
+
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        let occurs = query
+            .clauses()
+            .iter()
+            .map(|(occur, _)| *occur)
+            .collect::<Vec<_>>();
+
+        assert_eq!(occurs, &[Occur::MustNot, Occur::MustNot, Occur::Must]);
+        assert!(query.clauses()[2].1.downcast_ref::<AllQuery>().is_some());
+    }
+
+    #[test]
+    fn test_compile_literal_exclusions() {
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let path = builder.add_text_field("path", tantivy::schema::TEXT);
+        let index = Index::create_in_ram(builder.build());
+
+        let compile = |q: &str| {
+            let expr = crate::query::parser::parse(q).unwrap();
+            Compiler::new()
+                .literals(path, |q| &q.paths)
+                .compile(&expr, &index)
+                .unwrap()
+        };
+
+        // `-path:test` is left to readers, rather than excluding every path containing the
+        // n-grams of `test`
+        let query = compile("path:src -path:test");
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        assert!(query
+            .clauses()
+            .iter()
+            .all(|(occur, _)| *occur == Occur::Must));
+
+        let query = compile("not path:test");
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        let (occur, excluded) = &query.clauses()[1];
+        assert_eq!(*occur, Occur::MustNot);
+        assert!(excluded.downcast_ref::<EmptyQuery>().is_some());
+    }
+
+    #[test]
+    fn test_compile_partial_exclusions() {
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let symbols = builder.add_text_field("symbols", tantivy::schema::TEXT);
+        let kinds = builder.add_bytes_field("kinds", BytesOptions::default().set_indexed());
+        let index = Index::create_in_ram(builder.build());
+
+        let expr = crate::query::parser::parse("foo -symbol:function:bar").unwrap();
+        let query = Compiler::new()
+            .literal(symbols, |q| q.target.as_ref()?.symbol().cloned())
+            .byte_strings(kinds, |q| &q.symbol_kinds)
+            .compile(&expr, &index)
+            .unwrap();
+
+        // excluding every function would drop files that define functions other than `bar`
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        assert!(query
+            .clauses()
+            .iter()
+            .all(|(occur, _)| *occur != Occur::MustNot));
+    }
+
+    #[test]
+    fn test_compile_priority() {
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let path = builder.add_text_field("path", tantivy::schema::TEXT);
+        let index = Index::create_in_ram(builder.build());
+
+        let compile = |q: &str| {
+            let expr = crate::query::parser::parse(q).unwrap();
+            Compiler::new()
+                .literals(path, |q| &q.paths)
+                .priority(&[path])
+                .compile(&expr, &index)
+                .unwrap()
+        };
+
+        let query = compile("path:src");
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        assert!(query.clauses()[0].1.downcast_ref::<BoostQuery>().is_some());
+
+        let query = compile("path:/sr.c/");
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        assert!(query.clauses()[0].1.downcast_ref::<BoostQuery>().is_none());
+    }
+
+    #[test]
+    fn test_compile_fuzzy() {
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let content = builder.add_text_field("content", tantivy::schema::TEXT);
+        let index = Index::create_in_ram(builder.build());
+
+        let compile = |q: &str| {
+            let expr = crate::query::parser::parse(q).unwrap();
+            Compiler::new()
+                .fuzzy_literal(content, |q| q.target.as_ref()?.content().cloned())
+                .compile(&expr, &index)
+                .unwrap()
+        };
+
+        // every case permutation of the bigrams `pa`, `ar`, `rs` and `se`
+        let query = compile("fuzzy:true parse");
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        let grams = query.clauses()[0].1.downcast_ref::<BooleanQuery>().unwrap();
+        assert_eq!(grams.clauses().len(), 16);
+        assert!(grams
+            .clauses()
+            .iter()
+            .all(|(occur, _)| *occur == Occur::Should));
+
+        let query = compile("fuzzy:true case:sensitive parse");
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        let grams = query.clauses()[0].1.downcast_ref::<BooleanQuery>().unwrap();
+        assert_eq!(grams.clauses().len(), 4);
+    }
+
+    #[test]
+    fn test_compile_disjunction() {
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let lang = builder.add_bytes_field("lang", BytesOptions::default().set_indexed());
+        let index = Index::create_in_ram(builder.build());
+
+        let expr = crate::query::parser::parse("lang:rust lang:go foo").unwrap();
+        let query = Compiler::new()
+            .byte_strings(lang, |q| &q.langs)
+            .compile(&expr, &index)
+            .unwrap();
+
+        // A single `Must` clause, holding a union of both languages.
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        assert_eq!(query.clauses().len(), 1);
+
+        let (occur, langs) = &query.clauses()[0];
+        assert_eq!(*occur, Occur::Must);
+
+        let langs = langs.downcast_ref::<BooleanQuery>().unwrap();
+        assert_eq!(langs.clauses().len(), 2);
+        assert!(langs
+            .clauses()
+            .iter()
+            .all(|(occur, _)| *occur == Occur::Should));
+    }
+
+    #[test]
+    fn test_compile_nested_groups() {
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let lang = builder.add_bytes_field("lang", BytesOptions::default().set_indexed());
+        let index = Index::create_in_ram(builder.build());
+
+        let expr =
+            crate::query::parser::parse("(lang:rust or lang:go) -(lang:c or lang:java)").unwrap();
+        let query = Compiler::new()
+            .byte_strings(lang, |q| &q.langs)
+            .compile(&expr, &index)
+            .unwrap();
+
+        // An intersection of both groups, rather than every combination of their branches.
+        let query = query.downcast_ref::<BooleanQuery>().unwrap();
+        let occurs = query
+            .clauses()
+            .iter()
+            .map(|(occur, _)| *occur)
+            .collect::<Vec<_>>();
+        assert_eq!(occurs, &[Occur::Must, Occur::Must]);
+
+        let union = query.clauses()[0].1.downcast_ref::<BooleanQuery>().unwrap();
+        assert_eq!(union.clauses().len(), 2);
+
+        let negation = query.clauses()[1].1.downcast_ref::<BooleanQuery>().unwrap();
+        let occurs = negation
+            .clauses()
+            .iter()
+            .map(|(occur, _)| *occur)
+            .collect::<Vec<_>>();
+        assert_eq!(occurs, &[Occur::Must, Occur::MustNot]);
+    }
+
+    #[test]
+    fn test_compile_file_predicates() {
+        use tantivy::{collector::Count, doc, schema::INDEXED};
+
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let size = builder.add_u64_field("size", INDEXED);
+        let is_dir = builder.add_bool_field("is_dir", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+
+        let mut writer = index.writer(15_000_000).unwrap();
+        writer
+            .add_document(doc!(size => 100u64, is_dir => false))
+            .unwrap();
+        writer
+            .add_document(doc!(size => 4096u64, is_dir => false))
+            .unwrap();
+        writer.add_document(doc!(is_dir => true)).unwrap();
+        writer.commit().unwrap();
+
+        let searcher = index.reader().unwrap().searcher();
+        let count = |q: &str| {
+            let expr = crate::query::parser::parse(q).unwrap();
+            let query = Compiler::new()
+                .range(size, |q| q.size.clone())
+                .boolean(is_dir, |q| q.is_dir)
+                .compile(&expr, &index)
+                .unwrap();
+
+            searcher.search(&query, &Count).unwrap()
+        };
+
+        assert_eq!(count("size:>1k"), 1);
+        assert_eq!(count("size:<=4k is:file"), 2);
+        assert_eq!(count("size:<4k"), 1);
+        assert_eq!(count("is:dir"), 1);
+        assert_eq!(count("-size:>1k"), 2);
+    }
+
+    #[test]
+    fn test_compile_kinded_names() {
+        use crate::query::parser::Target;
+        use tantivy::{collector::Count, Document};
+
+        let mut builder = tantivy::schema::SchemaBuilder::new();
+        let kinded = builder.add_bytes_field("kinded", BytesOptions::default().set_indexed());
+        let index = Index::create_in_ram(builder.build());
+
+        let symbols_doc = |symbols: &[(&str, &str)]| {
+            let mut doc = Document::new();
+            for (kind, name) in symbols {
+                for gram in trigrams(name) {
+                    doc.add_bytes(kinded, kinded_gram(kind, &gram));
+                }
+            }
+            doc
+        };
+
+        let mut writer = index.writer(15_000_000).unwrap();
+        writer
+            .add_document(symbols_doc(&[
+                ("function", "parse_nl"),
+                ("variable", "foo"),
+            ]))
+            .unwrap();
+        writer
+            .add_document(symbols_doc(&[("function", "foo"), ("variable", "parse")]))
+            .unwrap();
+        writer.commit().unwrap();
+
+        let searcher = index.reader().unwrap().searcher();
+        let count = |q: &str| {
+            let expr = crate::query::parser::parse(q).unwrap();
+            let query = Compiler::new()
+                .kinded_names(kinded, |q| match &q.target {
+                    Some(Target::Symbol(Literal::Plain(name))) => Some((&q.symbol_kinds, &**name)),
+                    _ => None,
+                })
+                .compile(&expr, &index)
+                .unwrap();
+
+            searcher.search(&query, &Count).unwrap()
+        };
+
+        // the name and the kind must belong to the same symbol
+        assert_eq!(count("symbol:function:parse"), 1);
+        assert_eq!(count("symbol:variable:parse"), 1);
+        assert_eq!(count("symbol:function:PARSE"), 1);
+        assert_eq!(count("symbol:struct:parse"), 0);
+    }
+
     #[test]
     fn test_plan_to_query() {
         // Call the planner directly for simplicity; it has its own tests.
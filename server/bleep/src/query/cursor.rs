@@ -0,0 +1,261 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use tantivy::{
+    collector::{Collector, ScoreSegmentTweaker, ScoreTweaker, SegmentCollector},
+    DocAddress, DocId, Score, SegmentId, SegmentReader,
+};
+
+/// The position of the last hit on a page of search results.
+///
+/// Document addresses are only stable for a single generation of the index, so a cursor is
+/// rejected once the index has been updated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    pub generation: u64,
+
+    /// The time that recency was ranked against on the first page, in unix seconds.
+    ///
+    /// Later pages are ranked against the same time, so that the scores of documents don't change
+    /// between requests.
+    pub now: u64,
+    pub score: Score,
+    pub doc: DocAddress,
+}
+
+/// A cursor that can't be resumed from, which is the caller's fault.
+#[derive(Debug, thiserror::Error)]
+pub enum CursorError {
+    #[error("invalid cursor")]
+    Invalid,
+    #[error("the index has changed since this cursor was created")]
+    Stale,
+}
+
+impl Cursor {
+    /// The time to rank recency against, either that of the first page when resuming a search
+    /// after `cursor`, or the current time.
+    pub fn now(cursor: Option<&Cursor>) -> u64 {
+        cursor.map_or_else(
+            || {
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            },
+            |c| c.now,
+        )
+    }
+
+    /// Serialize this cursor into an opaque string, to be handed out to clients.
+    pub fn encode(&self) -> String {
+        format!(
+            "{:x}-{:x}-{:x}-{:x}-{:x}",
+            self.generation,
+            self.now,
+            self.score.to_bits(),
+            self.doc.segment_ord,
+            self.doc.doc_id
+        )
+    }
+
+    /// Parse a cursor previously produced by `Cursor::encode`.
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut parts = s.split('-').map(|p| u64::from_str_radix(p, 16));
+        let mut next = || parts.next()?.ok();
+
+        let cursor = Self {
+            generation: next()?,
+            now: next()?,
+            score: Score::from_bits(next()?.try_into().ok()?),
+            doc: DocAddress {
+                segment_ord: next()?.try_into().ok()?,
+                doc_id: next()?.try_into().ok()?,
+            },
+        };
+
+        parts.next().is_none().then_some(cursor)
+    }
+
+    /// Whether a hit is ranked after this cursor.
+    ///
+    /// This mirrors the order of `TopDocs`: descending score, with ties broken by ascending
+    /// document address.
+    fn is_before(&self, score: Score, doc: DocAddress) -> bool {
+        score < self.score
+            || (score == self.score
+                && (doc.segment_ord, doc.doc_id) > (self.doc.segment_ord, self.doc.doc_id))
+    }
+}
+
+/// A collector which resumes a search after a cursor.
+///
+/// Documents are scored by a tweaker before being passed on to the inner collector, and those
+/// that were already returned on a previous page are skipped entirely. They neither take a place
+/// in the ranking nor count towards totals and stats, which only cover the rest of the results.
+pub struct CursorCollector<T, C> {
+    tweaker: T,
+    cursor: Option<Cursor>,
+    segment_ords: HashMap<SegmentId, u32>,
+    collector: C,
+}
+
+pub struct CursorSegmentCollector<T, C> {
+    tweaker: T,
+    cursor: Option<Cursor>,
+    segment_ord: Option<u32>,
+    collector: C,
+}
+
+impl<T, C> CursorCollector<T, C> {
+    /// Create a new collector, using the segment order of `searcher`.
+    pub fn new(
+        tweaker: T,
+        cursor: Option<Cursor>,
+        searcher: &tantivy::Searcher,
+        collector: C,
+    ) -> Self {
+        let segment_ords = searcher
+            .segment_readers()
+            .iter()
+            .enumerate()
+            .map(|(ord, reader)| (reader.segment_id(), ord as u32))
+            .collect();
+
+        Self {
+            tweaker,
+            cursor,
+            segment_ords,
+            collector,
+        }
+    }
+}
+
+impl<T, C> Collector for CursorCollector<T, C>
+where
+    T: ScoreTweaker<Score> + Send + Sync,
+    C: Collector,
+{
+    type Fruit = C::Fruit;
+
+    type Child = CursorSegmentCollector<T::Child, C::Child>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: u32,
+        segment_reader: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        Ok(CursorSegmentCollector {
+            tweaker: self.tweaker.segment_tweaker(segment_reader)?,
+            cursor: self.cursor,
+            segment_ord: self.segment_ords.get(&segment_reader.segment_id()).copied(),
+            collector: self
+                .collector
+                .for_segment(segment_local_id, segment_reader)?,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<C::Child as SegmentCollector>::Fruit>,
+    ) -> tantivy::Result<C::Fruit> {
+        self.collector.merge_fruits(segment_fruits)
+    }
+}
+
+impl<T, C> SegmentCollector for CursorSegmentCollector<T, C>
+where
+    T: ScoreSegmentTweaker<Score>,
+    C: SegmentCollector,
+{
+    type Fruit = C::Fruit;
+
+    fn collect(&mut self, doc_id: DocId, score: Score) {
+        let score = self.tweaker.score(doc_id, score);
+
+        if let (Some(cursor), Some(segment_ord)) = (self.cursor, self.segment_ord) {
+            let doc = DocAddress {
+                segment_ord,
+                doc_id,
+            };
+
+            if !cursor.is_before(score, doc) {
+                return;
+            }
+        }
+
+        self.collector.collect(doc_id, score)
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.collector.harvest()
+    }
+}
+
+/// A score tweaker that leaves scores untouched.
+pub struct RawScore;
+
+impl ScoreTweaker<Score> for RawScore {
+    type Child = RawScore;
+
+    fn segment_tweaker(&self, _: &SegmentReader) -> tantivy::Result<Self::Child> {
+        Ok(RawScore)
+    }
+}
+
+impl ScoreSegmentTweaker<Score> for RawScore {
+    fn score(&mut self, _: DocId, score: Score) -> Score {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let cursor = Cursor {
+            generation: 42,
+            now: 1_700_000_000,
+            score: 1.5,
+            doc: DocAddress {
+                segment_ord: 3,
+                doc_id: 1337,
+            },
+        };
+
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(Cursor::decode("2a-6553f100-3fc00000-3"), None);
+        assert_eq!(Cursor::decode("2a-6553f100-3fc00000-3-539-0"), None);
+        assert_eq!(Cursor::decode("not a cursor"), None);
+    }
+
+    #[test]
+    fn ordering() {
+        let cursor = Cursor {
+            generation: 0,
+            now: 0,
+            score: 1.0,
+            doc: DocAddress {
+                segment_ord: 1,
+                doc_id: 10,
+            },
+        };
+
+        let doc = |segment_ord, doc_id| DocAddress {
+            segment_ord,
+            doc_id,
+        };
+
+        assert!(cursor.is_before(0.5, doc(0, 0)));
+        assert!(cursor.is_before(1.0, doc(1, 11)));
+        assert!(cursor.is_before(1.0, doc(2, 0)));
+        assert!(!cursor.is_before(1.0, doc(1, 10)));
+        assert!(!cursor.is_before(1.0, doc(0, 20)));
+        assert!(!cursor.is_before(2.0, doc(3, 0)));
+    }
+}
@@ -3,18 +3,28 @@ use std::{
     sync::Arc,
 };
 
-use super::{parser, ranking::DocumentTweaker};
+use super::{
+    ast,
+    cursor::{Cursor, CursorError, RawScore},
+    facets::Facet,
+    fuzzy::{FuzzyTarget, FuzzyTweaker},
+    parser,
+    ranking::DocumentTweaker,
+    suggest::{self, Suggestion},
+};
 use crate::{
-    collector::{BytesFilterCollector, FrequencyCollector},
+    collector::{BytesFilterCollector, FacetCollector, FieldsFilterCollector, FrequencyCollector},
     indexes::{
         reader::{base_name, ContentReader, FileReader, OpenReader, RepoReader},
-        DocumentRead, File, Indexable, Indexer, Indexes, Repo,
+        DocumentRead, File, Indexable, Indexer, Indexes, Repo, SearchResults,
     },
+    repo::iterator::{FileClass, LastCommit},
     snippet::{HighlightedString, SnippedFile, Snipper},
 };
 
 use anyhow::{bail, Result};
 use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
 use regex::{bytes::RegexBuilder as ByteRegexBuilder, RegexBuilder};
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
@@ -47,6 +57,13 @@ pub struct ApiQuery {
     #[serde(default)]
     pub page: usize,
 
+    /// An opaque cursor, as returned in the `next_cursor` field of a previous response.
+    ///
+    /// When set, results resume after the last hit of the previous page and `page` is ignored.
+    /// Counts and stats then only cover the remaining results.
+    #[serde(default)]
+    pub cursor: Option<String>,
+
     #[serde(default = "default_page_size")]
     pub page_size: usize,
 
@@ -68,6 +85,15 @@ pub struct ApiQuery {
     /// The number of lines of context in the snippet after the search result
     #[serde(alias = "ca", default = "default_context")]
     context_after: usize,
+
+    /// A comma-separated list of facets to count results by, on top of `lang` and `repo`.
+    ///
+    /// Either `dir`, `branch`, `class` or `ext`, or one of the `lang`, `extension`, `file_class`
+    /// or `raw_repo_name` fields of the file index. Other fields can't be counted by, as most
+    /// have a value per file, and are rejected along with unknown names. Only the most frequent
+    /// values are counted.
+    #[serde(default)]
+    pub facets: String,
 }
 
 #[derive(Serialize)]
@@ -80,6 +106,9 @@ pub struct QueryResponse {
     pub data: Vec<QueryResult>,
     /// Stats for nerds
     pub stats: ResultStats,
+    /// Corrected queries, when a search for a single word found nothing
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<Suggestion>,
 }
 
 impl crate::webserver::ApiResponse for QueryResponse {}
@@ -100,12 +129,30 @@ pub struct PagingMetadata {
     /// total number of search results across all pages, only populated
     /// if the client requests it
     total_count: Option<usize>,
+
+    /// cursor pointing at the last result of this page, only populated
+    /// if there may be more results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
 }
 
 #[derive(Default, Serialize, Deserialize, Debug)]
 pub struct ResultStats {
     pub lang: HashMap<String, usize>,
     pub repo: HashMap<String, usize>,
+
+    /// Counts for every facet requested in `ApiQuery::facets`, by facet name and value
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub facets: HashMap<String, HashMap<String, usize>>,
+}
+
+/// The final item of a streamed query response
+#[derive(Serialize)]
+pub struct QuerySummary {
+    /// Paging metadata, combined across all executors
+    metadata: PagingMetadata,
+    /// Stats for nerds, combined across all executors
+    stats: ResultStats,
 }
 
 #[derive(Serialize)]
@@ -133,6 +180,22 @@ pub enum QueryResult {
 
     #[serde(rename = "lang")]
     Lang(String),
+
+    #[serde(rename = "repo")]
+    Repo(String),
+
+    #[serde(rename = "path")]
+    Path(String),
+
+    #[serde(rename = "branch")]
+    Branch(String),
+
+    #[serde(rename = "symbol")]
+    Symbol(String),
+
+    // Only returned by streamed queries
+    #[serde(rename = "summary")]
+    Summary(QuerySummary),
 }
 
 #[derive(Serialize)]
@@ -161,6 +224,8 @@ pub struct FileData {
     size: usize,
     loc: usize,
     sloc: usize,
+    last_commit: Option<LastCommit>,
+    class: FileClass,
 }
 
 #[derive(Serialize)]
@@ -190,7 +255,7 @@ pub trait ExecuteQuery {
     async fn execute(
         &self,
         indexer: &Indexer<Self::Index>,
-        queries: &[parser::Query<'_>],
+        expr: &parser::QueryExpr<'_>,
         q: &ApiQuery,
     ) -> Result<QueryResponse>;
 }
@@ -206,18 +271,23 @@ impl ApiQuery {
     pub async fn query_with(
         self: Arc<Self>,
         indexes: Arc<Indexes>,
-        queries: Vec<parser::Query<'_>>,
+        expr: parser::QueryExpr<'_>,
     ) -> Result<QueryResponse> {
+        let branches = match &expr {
+            parser::Expression::Or(branches) => branches.as_slice(),
+            expr => std::slice::from_ref(expr),
+        };
+
         // FIXME: this for-loop prevents us from ever producing heterogenous
         // results.
         //
-        // It picks up the first query in the query list and produces results only
-        // for that query. A query containing an `or` operator; such as:
+        // It picks up the first branch of the query and produces results only
+        // for that branch. A query containing an `or` operator; such as:
         //
         //     symbol:foo or repo:bar
         //
         //
-        // is actually broken down into two separate queries:
+        // is actually made up of two separate branches:
         //
         //  - `symbol:foo` which operates on the `File` index
         //  - `repo:bar` which operates on the `Repo` index
@@ -232,32 +302,159 @@ impl ApiQuery {
         // For the time-being, we take the easy way out by prioritizing the first
         // target of the query, in this case `symbol:foo`. Queries that produce
         // homogenous results will work as expected: `repo:foo or repo:bar`.
-        for q in &queries {
-            if ContentReader.query_matches(q) {
+        for branch in branches {
+            if ContentReader.query_matches(branch) {
                 tracing::trace!("executing with ContentReader");
-                return ContentReader.execute(&indexes.file, &queries, &self).await;
-            } else if RepoReader.query_matches(q) {
+                return ContentReader.execute(&indexes.file, &expr, &self).await;
+            } else if RepoReader.query_matches(branch) {
                 tracing::trace!("executing with RepoReader");
-                return RepoReader.execute(&indexes.repo, &queries, &self).await;
-            } else if FileReader.query_matches(q) {
+                return RepoReader.execute(&indexes.repo, &expr, &self).await;
+            } else if FileReader.query_matches(branch) {
                 tracing::trace!("executing with FileReader");
-                return FileReader.execute(&indexes.file, &queries, &self).await;
-            } else if OpenReader.query_matches(q) {
+                return FileReader.execute(&indexes.file, &expr, &self).await;
+            } else if OpenReader.query_matches(branch) {
                 tracing::trace!("executing with OpenReader");
-                return OpenReader.execute(&indexes.file, &queries, &self).await;
+                return OpenReader.execute(&indexes.file, &expr, &self).await;
             }
         }
 
         bail!("mangled query")
     }
 
+    /// Execute this query against every index that can answer it, streaming results as each
+    /// executor finishes.
+    ///
+    /// Repositories are produced first, then file names, then snippets, or the opened files and
+    /// directories of open queries. The stream ends with a `QueryResult::Summary`, combining the
+    /// stats and paging metadata of all executors.
+    pub fn query_stream(
+        self: Arc<Self>,
+        indexes: Arc<Indexes>,
+    ) -> impl Stream<Item = Result<QueryResult>> {
+        async_stream::try_stream! {
+            let expr = parser::parse(&self.q)?;
+            tracing::debug!("compiled query as {expr:?}");
+
+            let mut executors = Vec::new();
+
+            if RepoReader.query_matches(&expr) {
+                executors.push(RepoReader.execute(&indexes.repo, &expr, &self));
+            }
+
+            // the executors that follow search the file index
+            let repo_executors = executors.len();
+
+            if FileReader.query_matches(&expr) {
+                executors.push(FileReader.execute(&indexes.file, &expr, &self));
+            }
+
+            if ContentReader.query_matches(&expr) {
+                executors.push(ContentReader.execute(&indexes.file, &expr, &self));
+            }
+
+            if OpenReader.query_matches(&expr) {
+                executors.push(OpenReader.execute(&indexes.file, &expr, &self));
+            }
+
+            // cursors point into the results of the executor that returned them, so they can only
+            // be used to page through the results of a single executor
+            let single_executor = executors.len() == 1;
+
+            // Executors run concurrently, but their results are produced in the order above.
+            let mut responses = stream::iter(executors).buffered(4).enumerate();
+            let mut repo_stats = ResultStats::default();
+            let mut file_stats = ResultStats::default();
+            let mut total_count = 0;
+            let mut next_cursor = None;
+
+            while let Some((i, response)) = responses.next().await {
+                let response = response?;
+
+                // file names and snippets can come from the same files, which are only counted
+                // once
+                if i < repo_executors {
+                    repo_stats = repo_stats.merge(response.stats);
+                } else {
+                    file_stats = file_stats.union(response.stats);
+                }
+
+                total_count += response.metadata.total_count.unwrap_or_default();
+                if single_executor {
+                    next_cursor = response.metadata.next_cursor;
+                }
+
+                for result in response.data {
+                    yield result;
+                }
+            }
+
+            let metadata = PagingMetadata {
+                next_cursor,
+                ..PagingMetadata::new(self.page, self.page_size, Some(total_count))
+            };
+
+            yield QueryResult::Summary(QuerySummary {
+                metadata,
+                stats: repo_stats.merge(file_stats),
+            });
+        }
+    }
+
+    /// A copy of this query that returns its first `n` results on a single page, for callers that
+    /// rank results themselves.
+    pub(crate) fn first(&self, n: usize) -> Self {
+        Self {
+            q: self.q.clone(),
+            page: 0,
+            cursor: None,
+            page_size: n,
+            calculate_totals: false,
+            context_before: self.context_before,
+            context_after: self.context_after,
+            facets: String::new(),
+        }
+    }
+
+    fn facets(&self) -> Vec<Facet> {
+        Facet::parse_list(&self.facets)
+    }
+
     fn limit(&self) -> usize {
         // do not permit a page-size of 0
         self.page_size.max(1)
     }
 
     fn offset(&self) -> usize {
-        self.page_size * self.page
+        if self.cursor.is_some() {
+            0
+        } else {
+            self.page_size * self.page
+        }
+    }
+
+    fn cursor(&self) -> Result<Option<Cursor>> {
+        match &self.cursor {
+            Some(c) => Cursor::decode(c)
+                .map(Some)
+                .ok_or_else(|| CursorError::Invalid.into()),
+            None => Ok(None),
+        }
+    }
+
+    /// A cursor to the next page of results, if this page was full.
+    ///
+    /// `now` is the time that recency was ranked against, see `Cursor::now`.
+    fn next_cursor<T>(&self, results: &SearchResults<'_, T>, now: u64) -> Option<Cursor> {
+        let (score, doc) = results
+            .last_hit
+            .filter(|_| results.hit_count == self.limit())?;
+
+        Some(Cursor {
+            generation: results.generation,
+            now,
+            score,
+            doc,
+        })
     }
 }
 
@@ -268,11 +465,57 @@ impl PagingMetadata {
             page_size,
             page_count: total_count.map(|t| div_ceil(t, page_size)),
             total_count,
+            next_cursor: None,
         }
     }
+
+    pub fn with_next_cursor(mut self, cursor: Option<Cursor>) -> Self {
+        self.next_cursor = cursor.map(|c| c.encode());
+        self
+    }
+
+    pub fn total_count(&self) -> Option<usize> {
+        self.total_count
+    }
+
+    pub fn next_cursor(&self) -> Option<&str> {
+        self.next_cursor.as_deref()
+    }
 }
 
 impl ResultStats {
+    /// Combine the stats of two responses, summing the counts of shared keys.
+    fn merge(self, other: Self) -> Self {
+        self.combine(other, |a, b| a + b)
+    }
+
+    /// Combine the stats of two responses that may count the same documents, keeping the larger
+    /// count of shared keys.
+    ///
+    /// This is exact when the documents of one response are a subset of the other's, and a lower
+    /// bound otherwise, but never counts a document twice.
+    fn union(self, other: Self) -> Self {
+        self.combine(other, usize::max)
+    }
+
+    fn combine(mut self, other: Self, f: impl Fn(usize, usize) -> usize) -> Self {
+        let combine_counts = |counts: &mut HashMap<String, usize>, other: HashMap<_, _>| {
+            for (k, v) in other {
+                let count = counts.entry(k).or_default();
+                *count = f(*count, v);
+            }
+        };
+
+        combine_counts(&mut self.lang, other.lang);
+        combine_counts(&mut self.repo, other.repo);
+
+        for (facet, counts) in other.facets {
+            combine_counts(self.facets.entry(facet).or_default(), counts);
+        }
+
+        self
+    }
+
     fn with_lang_freqs(mut self, mut lang_freqs: HashMap<Vec<u8>, usize>) -> Self {
         self.lang = lang_freqs
             .iter_mut()
@@ -286,6 +529,20 @@ impl ResultStats {
         self
     }
 
+    fn with_facets(
+        mut self,
+        facets: impl IntoIterator<Item = (Facet, HashMap<String, usize>)>,
+    ) -> Self {
+        for (facet, counts) in facets {
+            let counts = counts
+                .into_iter()
+                .map(|(k, v)| (facet.display(k), v))
+                .collect();
+            self.facets.insert(facet.name().to_owned(), counts);
+        }
+        self
+    }
+
     fn with_repo_freqs(mut self, mut repo_freqs: HashMap<Vec<u8>, usize>) -> Self {
         self.repo = repo_freqs
             .iter_mut()
@@ -299,6 +556,261 @@ impl ResultStats {
     }
 }
 
+/// The raw fields that content results are post-filtered on.
+const CONTENT_FIELDS: [RawField; 7] = [
+    RawField::Content,
+    RawField::Lang,
+    RawField::Path,
+    RawField::Repo,
+    RawField::Branches,
+    RawField::Author,
+    RawField::Symbols,
+];
+
+/// The raw fields that file results are post-filtered on.
+const FILE_FIELDS: [RawField; 5] = [
+    RawField::Path,
+    RawField::Repo,
+    RawField::Branches,
+    RawField::Lang,
+    RawField::Author,
+];
+
+/// A raw field of a file, that terms can be post-filtered on.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RawField {
+    Content,
+    Lang,
+    Path,
+    Repo,
+    Branches,
+    Author,
+    Symbols,
+}
+
+/// The values of the raw fields of a file. Fields that a reader doesn't read are left empty.
+#[derive(Default, Clone, Copy)]
+struct RawDoc<'a> {
+    content: &'a [u8],
+    lang: &'a [u8],
+    path: &'a [u8],
+    repo: &'a [u8],
+    branches: &'a [u8],
+    author: &'a [u8],
+    symbols: &'a [u8],
+}
+
+impl<'a> RawDoc<'a> {
+    fn get(&self, field: RawField) -> &'a [u8] {
+        match field {
+            RawField::Content => self.content,
+            RawField::Lang => self.lang,
+            RawField::Path => self.path,
+            RawField::Repo => self.repo,
+            RawField::Branches => self.branches,
+            RawField::Author => self.author,
+            RawField::Symbols => self.symbols,
+        }
+    }
+}
+
+/// The regexes that a single term of a query is post-filtered with, as compiled tantivy queries
+/// only narrow results down to a superset of the matching documents.
+#[derive(Clone)]
+struct TermFilter {
+    /// The regexes that each field is checked against. The term does not match unless one of the
+    /// regexes of every field matches.
+    checks: Vec<(RawField, Vec<regex::bytes::Regex>)>,
+
+    /// The exclusions of the term, which make it not match if they match.
+    excluded: Vec<TermFilter>,
+
+    /// Whether the checked fields alone decide if the term matches.
+    ///
+    /// Otherwise, the term has other labels that can't be checked here, such as the `size:>10k`
+    /// in `-(size:>10k bar)`, and a document passing every check only makes the term unknown.
+    exact: bool,
+
+    /// The structural pattern that documents must also match, for structural searches.
+    pattern: Option<Arc<ast::Pattern>>,
+}
+
+impl TermFilter {
+    /// Build the filter of a term, checking the labels that can be checked against `fields`.
+    ///
+    /// Structural patterns are looked up in `patterns`, so that they are only compiled once.
+    fn new(
+        q: &parser::Query<'_>,
+        fields: &[RawField],
+        patterns: &HashMap<String, Arc<ast::Pattern>>,
+    ) -> Self {
+        let build = |regex_str: &str| {
+            ByteRegexBuilder::new(regex_str)
+                .multi_line(true)
+                .case_insensitive(!q.is_case_sensitive())
+                .build()
+                .ok()
+        };
+
+        // the labels that are left unchecked, which are cleared as checks are added for them
+        let mut rest = parser::Query {
+            exclude: Vec::new(),
+            ..q.clone()
+        };
+
+        let mut checks = Vec::new();
+        let mut check = |field, regexes: Vec<String>| {
+            if !fields.contains(&field) {
+                return false;
+            }
+
+            match regexes.iter().map(|r| build(r)).collect::<Option<Vec<_>>>() {
+                Some(regexes) => {
+                    checks.push((field, regexes));
+                    true
+                }
+                None => false,
+            }
+        };
+
+        let mut pattern = None;
+        match &q.target {
+            // structural searches are matched against the syntax tree of each document, which is
+            // only parsed if it contains the longest literal in the pattern
+            Some(parser::Target::Ast(ast_pattern)) => {
+                let ast_pattern = ast_pattern.clone().unwrap();
+                if let Some(compiled) = patterns.get(ast_pattern.as_ref()) {
+                    let literal = ast::longest_literal(&ast_pattern).map(|l| regex::escape(&l));
+                    if literal.map_or(true, |l| check(RawField::Content, vec![l])) {
+                        pattern = Some(compiled.clone());
+                        rest.target = None;
+                    }
+                }
+            }
+            Some(parser::Target::Content(lit)) => {
+                let regex_str = q.target_regex_str(lit).into_owned();
+                if check(RawField::Content, vec![regex_str]) {
+                    rest.target = None;
+                }
+            }
+            Some(parser::Target::Symbol(_)) | None => {}
+        }
+
+        // symbols are listed one per line as `kind:name`, and are matched on both, as in
+        // `symbol:function:parse` or `kind:struct Error`
+        let name = match &q.target {
+            Some(parser::Target::Symbol(lit)) => Some(lit),
+            Some(parser::Target::Content(lit)) if !q.symbol_kinds.is_empty() => Some(lit),
+            _ => None,
+        };
+        if name.is_some() || !q.symbol_kinds.is_empty() {
+            let kinds = if q.symbol_kinds.is_empty() {
+                "[^:\\n]*".to_owned()
+            } else {
+                let kinds = q.symbol_kinds.iter().map(|k| regex::escape(k));
+                format!("(?:{})", kinds.collect::<Vec<_>>().join("|"))
+            };
+            let name = name.map(|lit| format!("[^\\n]*?(?:{})", q.target_regex_str(lit)));
+
+            if check(
+                RawField::Symbols,
+                vec![format!("^{kinds}:{}", name.unwrap_or_default())],
+            ) {
+                if let Some(parser::Target::Symbol(_)) = q.target {
+                    rest.target = None;
+                }
+                rest.symbol_kinds.clear();
+            }
+        }
+
+        let regex_strs = |lits: &HashSet<parser::Literal<'_>>| -> Vec<String> {
+            lits.iter().map(|l| l.regex_str().into_owned()).collect()
+        };
+
+        if !q.paths.is_empty() && check(RawField::Path, regex_strs(&q.paths)) {
+            rest.paths.clear();
+        }
+
+        if let Some(repo) = &q.repo {
+            if check(RawField::Repo, vec![repo.regex_str().into_owned()]) {
+                rest.repo = None;
+            }
+        }
+
+        if !q.branches.is_empty() && check(RawField::Branches, regex_strs(&q.branches)) {
+            rest.branches.clear();
+        }
+
+        if let Some(author) = &q.author {
+            if check(RawField::Author, vec![author.regex_str().into_owned()]) {
+                rest.author = None;
+            }
+        }
+
+        // languages are matched exactly by the index, and only here to decide negations
+        let langs = q
+            .langs
+            .iter()
+            .map(|l| format!("(?-i)^{}$", regex::escape(l)))
+            .collect::<Vec<_>>();
+        if !q.langs.is_empty() && check(RawField::Lang, langs) {
+            rest.langs.clear();
+        }
+
+        let excluded = q
+            .exclude
+            .iter()
+            .map(|e| {
+                let e = parser::Query {
+                    case_sensitive: q.case_sensitive,
+                    ..e.clone()
+                };
+                Self::new(&e, fields, patterns)
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            exact: rest.target.is_none() && !rest.has_filters() && excluded.iter().all(|e| e.exact),
+            checks,
+            excluded,
+            pattern,
+        }
+    }
+
+    fn evaluate(&self, doc: &RawDoc<'_>) -> Option<bool> {
+        if self.excluded.iter().any(|e| e.evaluate(doc) == Some(true)) {
+            return Some(false);
+        }
+
+        for (field, regexes) in &self.checks {
+            let value = doc.get(*field);
+            if !regexes.iter().any(|r| r.is_match(value)) {
+                return Some(false);
+            }
+        }
+
+        // parsing is by far the most expensive check, so it goes last
+        if let Some(pattern) = &self.pattern {
+            let lang = std::str::from_utf8(doc.lang).unwrap_or_default();
+            if pattern.matches(doc.content, lang).is_none() {
+                return Some(false);
+            }
+        }
+
+        self.exact.then_some(true)
+    }
+}
+
+impl parser::Expression<TermFilter> {
+    /// Whether a document should be kept, given the values of its raw fields.
+    ///
+    /// Terms that can't be decided from these fields are unknown, and are left to the tantivy
+    /// query.
+    fn accepts(&self, doc: &RawDoc<'_>) -> bool {
+        self.evaluate(&mut |term| term.evaluate(doc)) != Some(false)
+    }
+}
+
 #[async_trait]
 impl ExecuteQuery for ContentReader {
     type Index = File;
@@ -306,39 +818,68 @@ impl ExecuteQuery for ContentReader {
     async fn execute(
         &self,
         indexer: &Indexer<Self::Index>,
-        queries: &[parser::Query<'_>],
+        expr: &parser::QueryExpr<'_>,
         q: &ApiQuery,
     ) -> Result<QueryResponse> {
-        // queries that produce content results
-        let relevant_queries = queries.iter().filter(|q| self.query_matches(q));
+        // the branches of the query that produce content results
+        let expr = self.select(expr);
 
         // a list of targets, for a query of the form `symbol:foo or bar`, this is:
         // - a symbol target: foo
         // - a content target: bar
-        let targets = relevant_queries
-            .filter_map(|q| Some((q.target.as_ref()?, q.is_case_sensitive())))
+        //
+        // each target is kept with the rest of its term, for the kinds of symbols it is
+        // restricted to, as in `kind:struct`, and the flags that change how it matches, as in
+        // `fuzzy:true` or `word:true`
+        let targets = expr
+            .positive_terms()
+            .filter(|q| self.term_matches(q))
             .collect::<SmallVec<[_; 2]>>();
 
-        // a regex filter to get rid of docs that contain the trigrams but not the text
-        let byte_regexes = targets
-            .iter()
-            .filter_map(|(target, case)| {
-                ByteRegexBuilder::new(&target.literal().regex_str())
-                    .multi_line(true)
-                    .case_insensitive(!case)
-                    .build()
-                    .ok()
+        // structural patterns are compiled once, for every language they are valid in
+        let patterns = expr
+            .terms()
+            .flat_map(|q| std::iter::once(q).chain(&q.exclude))
+            .filter_map(|q| q.target.as_ref()?.ast())
+            .map(|pattern| {
+                let pattern = pattern.clone().unwrap().into_owned();
+                let compiled = ast::Pattern::new(&pattern)?;
+                Ok((pattern, Arc::new(compiled)))
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        // fuzzy targets rank documents by how close their best match is
+        let fuzzy_targets = expr
+            .positive_terms()
+            .filter(|q| self.term_matches(q))
+            .filter_map(FuzzyTarget::new)
+            .collect::<Arc<[_]>>();
+
+        // a regex filter to get rid of docs that contain the trigrams but not the text, including
+        // exclusions such as `-content:foo` or `-path:test`, which cannot be expressed precisely
+        // in the compiled tantivy query
+        let filter = expr.map(&mut |q| TermFilter::new(q, &CONTENT_FIELDS, &patterns));
 
         let raw_content = indexer.source.raw_content;
         let repo_field = indexer.source.raw_repo_name;
         let lang_field = indexer.source.lang;
+        let path_field = indexer.source.raw_relative_path;
+        let branches_field = indexer.source.raw_branches;
+        let author_field = indexer.source.raw_last_commit_author;
+        let symbols_field = indexer.source.raw_symbols;
 
         // our results will consist of the top-k docs...
-        let top_k = TopDocs::with_limit(q.limit())
-            .and_offset(q.offset())
-            .tweak_score(DocumentTweaker(indexer.source.clone()));
+        let cursor = q.cursor()?;
+        let now = Cursor::now(cursor.as_ref());
+        let tweaker = FuzzyTweaker::new(
+            DocumentTweaker {
+                schema: indexer.source.clone(),
+                now,
+            },
+            raw_content,
+            fuzzy_targets,
+        );
+        let top_k = TopDocs::with_limit(q.limit()).and_offset(q.offset());
 
         // ...plus some rich search metadata
         let total_count_collector = tantivy::collector::Count;
@@ -349,34 +890,86 @@ impl ExecuteQuery for ContentReader {
         let total_count_handle = metadata_collector.add_collector(total_count_collector);
         let lang_stats_handle = metadata_collector.add_collector(lang_stats_collector);
         let repo_stats_handle = metadata_collector.add_collector(repo_stats_collector);
-
-        // our final search results contain top-k, total count, language stats, repo stats,
-        // filtered by the target regex
-        let collector = BytesFilterCollector::new(
-            raw_content,
-            move |b| byte_regexes.iter().any(|r| r.is_match(b)), // a doc is accepted if it contains at least 1 target
-            (top_k, metadata_collector),
+        let facet_handles = q
+            .facets()
+            .into_iter()
+            .map(|facet| {
+                let collector = FacetCollector(facet.field(&indexer.source)?);
+                Ok((facet, metadata_collector.add_collector(collector)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let searcher = indexer.searcher().await;
+
+        // our final search results contain top-k, total count, language stats, repo stats and
+        // facet counts of the results after the cursor, filtered by the target regex and
+        // structural patterns
+        let collector = FieldsFilterCollector::new(
+            [
+                raw_content,
+                lang_field,
+                path_field,
+                repo_field,
+                branches_field,
+                author_field,
+                symbols_field,
+            ],
+            // a doc is accepted unless its fields show that it doesn't match the query
+            move |[content, lang, path, repo, branches, author, symbols]| {
+                filter.accepts(&RawDoc {
+                    content,
+                    lang,
+                    path,
+                    repo,
+                    branches,
+                    author,
+                    symbols,
+                })
+            },
+            indexer.resume_after(&searcher, cursor, tweaker, (top_k, metadata_collector))?,
         );
 
-        let mut results = indexer.query(queries.iter(), self, collector).await?;
+        let mut results = indexer.query_with_searcher(searcher, &expr, self, collector)?;
+        let next_cursor = q.next_cursor(&results, now);
         let data = results
             .docs
             .filter_map(|doc| {
                 let snipper = Snipper::default().context(q.context_before, q.context_after);
                 let mut all_snippets = None::<SnippedFile>;
 
-                for (target, case_sensitive) in &targets {
-                    let (is_symbol, lit) = match target {
-                        parser::Target::Symbol(lit) => (true, lit),
-                        parser::Target::Content(lit) => (false, lit),
+                for term in &targets {
+                    let snipper = snipper.case_sensitive(term.is_case_sensitive());
+                    let kinds = &term.symbol_kinds;
+                    let regex_str = |lit| term.target_regex_str(lit);
+                    let snippets = match &term.target {
+                        Some(parser::Target::Ast(pattern)) => patterns
+                            .get(pattern.clone().unwrap().as_ref())
+                            .zip(doc.lang.as_deref())
+                            .and_then(|(pattern, lang)| {
+                                pattern.matches(doc.content.as_bytes(), lang)
+                            })
+                            .and_then(|ranges| snipper.all_for_ranges(ranges, &doc)),
+
+                        // with a kind of symbol, as in `symbol:function:parse` or
+                        // `kind:struct Error`, we only highlight symbols of that kind
+                        Some(parser::Target::Symbol(lit) | parser::Target::Content(lit))
+                            if !kinds.is_empty() =>
+                        {
+                            snipper
+                                .symbols_for_doc(Some(&regex_str(lit)), kinds, &doc)
+                                .unwrap()
+                        }
+                        Some(parser::Target::Symbol(lit)) => snipper
+                            .find_symbols(true)
+                            .all_for_doc(&regex_str(lit), &doc)
+                            .unwrap(),
+                        Some(parser::Target::Content(lit)) => {
+                            snipper.all_for_doc(&regex_str(lit), &doc).unwrap()
+                        }
+                        None => snipper.symbols_for_doc(None, kinds, &doc).unwrap(),
                     };
 
-                    if let Some(snippets) = snipper
-                        .find_symbols(is_symbol)
-                        .case_sensitive(*case_sensitive)
-                        .all_for_doc(&lit.regex_str(), &doc)
-                        .unwrap()
-                    {
+                    if let Some(snippets) = snippets {
                         all_snippets = if let Some(data) = all_snippets {
                             Some(data.merge(snippets))
                         } else {
@@ -393,9 +986,23 @@ impl ExecuteQuery for ContentReader {
 
         let stats = ResultStats::default()
             .with_lang_freqs(lang_stats_handle.extract(&mut results.metadata))
-            .with_repo_freqs(repo_stats_handle.extract(&mut results.metadata));
-
-        let metadata = PagingMetadata::new(q.page, q.page_size, Some(total_count));
+            .with_repo_freqs(repo_stats_handle.extract(&mut results.metadata))
+            .with_facets(
+                facet_handles
+                    .into_iter()
+                    .map(|(facet, handle)| (facet, handle.extract(&mut results.metadata))),
+            );
+
+        let metadata = PagingMetadata::new(q.page, q.page_size, Some(total_count))
+            .with_next_cursor(next_cursor);
+
+        // a typo in an identifier returns nothing at all, so we suggest similar symbol names
+        let suggestions = match suggest::searched_word(&expr) {
+            Some(word) if data.is_empty() && q.offset() == 0 && q.cursor.is_none() => {
+                suggest::did_you_mean(indexer, &q.q, &expr, &word).await?
+            }
+            _ => vec![],
+        };
 
         let count = data.len();
         let response = QueryResponse {
@@ -403,6 +1010,7 @@ impl ExecuteQuery for ContentReader {
             metadata,
             data,
             stats,
+            suggestions,
         };
         Ok(response)
     }
@@ -415,32 +1023,35 @@ impl ExecuteQuery for FileReader {
     async fn execute(
         &self,
         indexer: &Indexer<File>,
-        queries: &[parser::Query<'_>],
+        expr: &parser::QueryExpr<'_>,
         q: &ApiQuery,
     ) -> Result<QueryResponse> {
-        let (filter_regexes, byte_filter_regexes): (Vec<_>, Vec<_>) = queries
-            .iter()
-            .filter(|q| self.query_matches(q))
-            .filter_map(|q| {
-                let regex_str = q.path.as_ref()?.regex_str();
-                let case_insensitive = !q.is_case_sensitive();
-                let regex = RegexBuilder::new(&regex_str)
-                    .case_insensitive(case_insensitive)
-                    .build()
-                    .ok()?;
-                let byte_regex = ByteRegexBuilder::new(&regex_str)
-                    .case_insensitive(case_insensitive)
+        let expr = self.select(expr);
+
+        // regexes to highlight matching paths with
+        let filter_regexes = expr
+            .positive_terms()
+            .flat_map(|q| q.paths.iter().map(move |p| (p, q.is_case_sensitive())))
+            .filter_map(|(path, case)| {
+                RegexBuilder::new(&path.regex_str())
+                    .case_insensitive(!case)
                     .build()
-                    .ok()?;
-                Some((regex, byte_regex))
+                    .ok()
             })
-            .unzip();
+            .collect::<Vec<_>>();
+
+        // a doc is accepted unless its path shows that it doesn't match the query, including
+        // exclusions such as `-path:/test/`, which cannot be expressed precisely in the compiled
+        // tantivy query
+        let filter = expr.map(&mut |q| TermFilter::new(q, &FILE_FIELDS, &HashMap::new()));
 
         let top_k = TopDocs::with_limit(q.limit()).and_offset(q.offset());
 
         let path_field = indexer.source.raw_relative_path;
         let repo_field = indexer.source.raw_repo_name;
         let lang_field = indexer.source.lang;
+        let branches_field = indexer.source.raw_branches;
+        let author_field = indexer.source.raw_last_commit_author;
 
         let total_count_collector = tantivy::collector::Count;
         let lang_stats_collector = FrequencyCollector(lang_field);
@@ -450,14 +1061,45 @@ impl ExecuteQuery for FileReader {
         let total_count_handle = metadata_collector.add_collector(total_count_collector);
         let lang_stats_handle = metadata_collector.add_collector(lang_stats_collector);
         let repo_stats_handle = metadata_collector.add_collector(repo_stats_collector);
-
-        let collector = BytesFilterCollector::new(
-            path_field,
-            move |b| byte_filter_regexes.iter().any(|r| r.is_match(b)), // a doc is accepted if it contains at least 1 target
-            (top_k, metadata_collector),
+        let facet_handles = q
+            .facets()
+            .into_iter()
+            .map(|facet| {
+                let collector = FacetCollector(facet.field(&indexer.source)?);
+                Ok((facet, metadata_collector.add_collector(collector)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let searcher = indexer.searcher().await;
+        let collector = FieldsFilterCollector::new(
+            [
+                path_field,
+                repo_field,
+                branches_field,
+                lang_field,
+                author_field,
+            ],
+            move |[path, repo, branches, lang, author]| {
+                filter.accepts(&RawDoc {
+                    path,
+                    repo,
+                    branches,
+                    lang,
+                    author,
+                    ..RawDoc::default()
+                })
+            },
+            indexer.resume_after(
+                &searcher,
+                q.cursor()?,
+                RawScore,
+                (top_k, metadata_collector),
+            )?,
         );
 
-        let mut results = indexer.query(queries.iter(), self, collector).await?;
+        let mut results = indexer.query_with_searcher(searcher, &expr, self, collector)?;
+        // file and repo results are not ranked by recency
+        let next_cursor = q.next_cursor(&results, 0);
 
         let data = results
             .docs
@@ -482,15 +1124,22 @@ impl ExecuteQuery for FileReader {
 
         let stats = ResultStats::default()
             .with_lang_freqs(lang_stats_handle.extract(&mut results.metadata))
-            .with_repo_freqs(repo_stats_handle.extract(&mut results.metadata));
+            .with_repo_freqs(repo_stats_handle.extract(&mut results.metadata))
+            .with_facets(
+                facet_handles
+                    .into_iter()
+                    .map(|(facet, handle)| (facet, handle.extract(&mut results.metadata))),
+            );
 
-        let metadata = PagingMetadata::new(q.page, q.page_size, Some(total_count));
+        let metadata = PagingMetadata::new(q.page, q.page_size, Some(total_count))
+            .with_next_cursor(next_cursor);
 
         let response = QueryResponse {
             count: data.len(),
             data,
             metadata,
             stats,
+            suggestions: vec![],
         };
 
         Ok(response)
@@ -504,15 +1153,17 @@ impl ExecuteQuery for RepoReader {
     async fn execute(
         &self,
         indexer: &Indexer<Self::Index>,
-        queries: &[parser::Query<'_>],
+        expr: &parser::QueryExpr<'_>,
         q: &ApiQuery,
     ) -> Result<QueryResponse> {
-        let (filter_regexes, byte_filter_regexes): (Vec<_>, Vec<_>) = queries
-            .iter()
-            .filter(|q| self.query_matches(q))
-            .filter_map(|q| {
-                let regex_str = q.path.as_ref()?.regex_str();
-                let case_insensitive = !q.is_case_sensitive();
+        let expr = self.select(expr);
+        let (filter_regexes, byte_filter_regexes): (Vec<_>, Vec<_>) = expr
+            .positive_terms()
+            .filter(|q| self.term_matches(q))
+            .flat_map(|q| q.paths.iter().map(move |p| (p, q.is_case_sensitive())))
+            .filter_map(|(path, case)| {
+                let regex_str = path.regex_str();
+                let case_insensitive = !case;
                 let regex = RegexBuilder::new(&regex_str)
                     .case_insensitive(case_insensitive)
                     .build()
@@ -535,13 +1186,21 @@ impl ExecuteQuery for RepoReader {
         let repo_stats_handle = metadata_collector.add_collector(repo_stats_collector);
         let total_count_handle = metadata_collector.add_collector(total_count_collector);
 
+        let searcher = indexer.searcher().await;
         let collector = BytesFilterCollector::new(
             name_field,
             move |b| byte_filter_regexes.iter().any(|r| r.is_match(b)), // a doc is accepted if it contains at least 1 target
-            (top_k, metadata_collector),
+            indexer.resume_after(
+                &searcher,
+                q.cursor()?,
+                RawScore,
+                (top_k, metadata_collector),
+            )?,
         );
 
-        let mut results = indexer.query(queries.iter(), self, collector).await?;
+        let mut results = indexer.query_with_searcher(searcher, &expr, self, collector)?;
+        // file and repo results are not ranked by recency
+        let next_cursor = q.next_cursor(&results, 0);
 
         let data = results
             .docs
@@ -563,13 +1222,15 @@ impl ExecuteQuery for RepoReader {
             .with_repo_freqs(repo_stats_handle.extract(&mut results.metadata));
 
         let total_count = total_count_handle.extract(&mut results.metadata);
-        let metadata = PagingMetadata::new(q.page, q.page_size, Some(total_count));
+        let metadata = PagingMetadata::new(q.page, q.page_size, Some(total_count))
+            .with_next_cursor(next_cursor);
 
         let response = QueryResponse {
             count: data.len(),
             data,
             metadata,
             stats,
+            suggestions: vec![],
         };
 
         Ok(response)
@@ -583,7 +1244,7 @@ impl ExecuteQuery for OpenReader {
     async fn execute(
         &self,
         indexer: &Indexer<Self::Index>,
-        queries: &[parser::Query<'_>],
+        expr: &parser::QueryExpr<'_>,
         _q: &ApiQuery,
     ) -> Result<QueryResponse> {
         #[derive(Debug)]
@@ -592,12 +1253,13 @@ impl ExecuteQuery for OpenReader {
             repo_name: String,
         }
 
-        let open_directives = queries
-            .iter()
-            .filter(|q| self.query_matches(q))
+        let expr = self.select(expr);
+        let open_directives = expr
+            .positive_terms()
+            .filter(|q| self.term_matches(q))
             .filter_map(|q| {
                 Some(Directive {
-                    relative_path: match q.path.as_ref() {
+                    relative_path: match q.paths.iter().next() {
                         None => "".into(),
                         Some(parser::Literal::Plain(p)) => p.to_string(),
                         Some(parser::Literal::Regex(..)) => return None,
@@ -640,7 +1302,7 @@ impl ExecuteQuery for OpenReader {
             (top_docs, empty_collector),
         );
 
-        let results = indexer.query(queries.iter(), self, collector).await?;
+        let results = indexer.query(&expr, self, collector).await?;
 
         // Map of (repo_name, relative_path) -> (String, entry set)
         //
@@ -683,6 +1345,8 @@ impl ExecuteQuery for OpenReader {
                             .count()
                             .saturating_add(1),
                         siblings: vec![],
+                        last_commit: doc.last_commit.clone(),
+                        class: doc.class,
                     });
 
                     continue;
@@ -745,6 +1409,7 @@ impl ExecuteQuery for OpenReader {
             data,
             metadata: PagingMetadata::default(),
             stats: ResultStats::default(),
+            suggestions: vec![],
         };
 
         Ok(response)
@@ -821,12 +1486,168 @@ mod tests {
                 page: 0,
                 page_size: 100,
                 page_count: Some(6),
-                total_count: Some(520)
+                total_count: Some(520),
+                next_cursor: None,
+            },
+            stats: ResultStats {
+                repo: repos,
+                lang: langs,
+                ..Default::default()
             },
-            stats: ResultStats { repo: repos, lang: langs },
+            suggestions: vec![],
         })
         .unwrap();
 
         assert_eq!(expected, observed);
     }
+
+    #[test]
+    fn stats_union() {
+        let stats = |langs: &[(&str, usize)]| ResultStats {
+            lang: langs.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+            ..Default::default()
+        };
+
+        let merged = stats(&[("Rust", 2), ("Go", 1)]).merge(stats(&[("Rust", 3)]));
+        assert_eq!(merged.lang, stats(&[("Rust", 5), ("Go", 1)]).lang);
+
+        let union = stats(&[("Rust", 2), ("Go", 1)]).union(stats(&[("Rust", 3)]));
+        assert_eq!(union.lang, stats(&[("Rust", 3), ("Go", 1)]).lang);
+    }
+
+    #[test]
+    fn negated_term_filters() {
+        let patterns = HashMap::new();
+        let filter = |q, fields: &[RawField]| {
+            parser::parse(q)
+                .unwrap()
+                .map(&mut |q| TermFilter::new(q, fields, &patterns))
+        };
+        let content = |content| RawDoc {
+            content,
+            ..RawDoc::default()
+        };
+        let path = |path| RawDoc {
+            path,
+            ..RawDoc::default()
+        };
+
+        let content_filter = filter("-(author:foo bar)", &CONTENT_FIELDS);
+        assert!(!content_filter.accepts(&RawDoc {
+            content: b"let bar = 1;",
+            author: b"foo <foo@bloop.ai>",
+            ..RawDoc::default()
+        }));
+        assert!(content_filter.accepts(&RawDoc {
+            content: b"let bar = 1;",
+            author: b"baz <baz@bloop.ai>",
+            ..RawDoc::default()
+        }));
+        assert!(content_filter.accepts(&RawDoc {
+            content: b"let baz = 1;",
+            author: b"foo <foo@bloop.ai>",
+            ..RawDoc::default()
+        }));
+
+        let file_filter = filter("path:src -author:alice", &FILE_FIELDS);
+        assert!(!file_filter.accepts(&RawDoc {
+            path: b"src/lib.rs",
+            author: b"Alice <alice@bloop.ai>",
+            ..RawDoc::default()
+        }));
+        assert!(file_filter.accepts(&RawDoc {
+            path: b"src/lib.rs",
+            author: b"Bob <bob@bloop.ai>",
+            ..RawDoc::default()
+        }));
+
+        let content_filter = filter("-(repo:foo bar)", &CONTENT_FIELDS);
+        assert!(!content_filter.accepts(&RawDoc {
+            content: b"let bar = 1;",
+            repo: b"foo",
+            ..RawDoc::default()
+        }));
+        assert!(content_filter.accepts(&RawDoc {
+            content: b"let bar = 1;",
+            repo: b"baz",
+            ..RawDoc::default()
+        }));
+
+        let content_filter = filter("-(bar or baz)", &CONTENT_FIELDS);
+        assert!(!content_filter.accepts(&content(b"let bar = 1;")));
+        assert!(content_filter.accepts(&content(b"let foo = 1;")));
+
+        let content_filter = filter("foo -content:bar", &CONTENT_FIELDS);
+        assert!(content_filter.accepts(&content(b"foo")));
+        assert!(!content_filter.accepts(&content(b"foo bar")));
+        assert!(!content_filter.accepts(&content(b"baz")));
+
+        // symbol targets must also be symbols, of the right kind
+        let symbols = |symbols| RawDoc {
+            content: b"fn bar() { foo }",
+            symbols,
+            ..RawDoc::default()
+        };
+        let content_filter = filter("-(symbol:bar)", &CONTENT_FIELDS);
+        assert!(!content_filter.accepts(&symbols(b"function:bar")));
+        assert!(content_filter.accepts(&symbols(b"function:foo")));
+
+        let content_filter = filter("foo -symbol:function:bar", &CONTENT_FIELDS);
+        assert!(!content_filter.accepts(&symbols(b"variable:foo\nfunction:bar")));
+        assert!(content_filter.accepts(&symbols(b"variable:foo\nvariable:bar")));
+        assert!(content_filter.accepts(&symbols(b"function:foo\nvariable:bar")));
+
+        // paths that merely share the trigrams of an excluded path are kept
+        let content_filter = filter("foo -path:test", &CONTENT_FIELDS);
+        assert!(content_filter.accepts(&RawDoc {
+            content: b"foo",
+            path: b"src/setter.rs",
+            ..RawDoc::default()
+        }));
+        assert!(!content_filter.accepts(&RawDoc {
+            content: b"foo",
+            path: b"src/test.rs",
+            ..RawDoc::default()
+        }));
+
+        let file_filter = filter("-(lang:rust path:test)", &FILE_FIELDS);
+        assert!(!file_filter.accepts(&RawDoc {
+            path: b"src/test.rs",
+            lang: b"rust",
+            ..RawDoc::default()
+        }));
+        assert!(file_filter.accepts(&RawDoc {
+            path: b"src/test.go",
+            lang: b"go",
+            ..RawDoc::default()
+        }));
+
+        let file_filter = filter("-(path:test or path:bench)", &FILE_FIELDS);
+        assert!(!file_filter.accepts(&path(b"src/test.rs")));
+        assert!(file_filter.accepts(&path(b"src/lib.rs")));
+
+        let file_filter = filter("-(path:test foo)", &FILE_FIELDS);
+        assert!(file_filter.accepts(&path(b"src/test.rs")));
+    }
+
+    #[test]
+    fn structural_term_filters() {
+        let pattern = "(function_item name: (identifier) @name)";
+        let patterns = HashMap::from([(
+            pattern.to_owned(),
+            Arc::new(ast::Pattern::new(pattern).unwrap()),
+        )]);
+
+        let expr = parser::parse(&format!("ast:'{pattern}'")).unwrap();
+        let filter = expr.map(&mut |q| TermFilter::new(q, &CONTENT_FIELDS, &patterns));
+        let doc = |content, lang| RawDoc {
+            content,
+            lang,
+            ..RawDoc::default()
+        };
+
+        assert!(filter.accepts(&doc(b"fn parse() {}", b"rust")));
+        assert!(!filter.accepts(&doc(b"let parse = 1;", b"rust")));
+        assert!(!filter.accepts(&doc(b"fn parse() {}", b"go")));
+    }
 }
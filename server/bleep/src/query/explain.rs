@@ -0,0 +1,175 @@
+//! Explain how a query is parsed, planned and executed, to debug queries that return unexpected
+//! results.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tantivy::collector::Count;
+
+use super::{
+    execute::{ApiQuery, ExecuteQuery},
+    parser::{self, Literal, Target},
+    planner,
+};
+use crate::indexes::{
+    reader::{ContentReader, FileReader, RepoReader},
+    DocumentRead, Indexable, Indexer, Indexes,
+};
+
+#[derive(Serialize, Debug)]
+pub struct Explanation {
+    /// The parsed boolean expression
+    expression: String,
+
+    /// The trigram plan of every regex that is compiled into the tantivy query
+    regexes: Vec<RegexPlan>,
+
+    /// What every index that can answer the query did with it
+    indexes: Vec<IndexExplanation>,
+}
+
+impl crate::webserver::ApiResponse for Explanation {}
+
+#[derive(Serialize, Debug)]
+pub struct RegexPlan {
+    regex: String,
+
+    /// The optimized plan, or `None` if planning failed
+    plan: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct IndexExplanation {
+    reader: &'static str,
+
+    /// The compiled tantivy query
+    query: String,
+
+    /// Number of documents matched by the tantivy query
+    hits: usize,
+
+    /// Number of documents left after post-filtering with regexes
+    filtered_hits: Option<usize>,
+}
+
+impl ApiQuery {
+    pub async fn explain(self: Arc<Self>, indexes: Arc<Indexes>) -> Result<Explanation> {
+        let expr = parser::parse(&self.q)?;
+
+        let regexes = expr
+            .terms()
+            .flat_map(regexes)
+            .map(|regex| match planner::plan(regex) {
+                Ok(plan) => RegexPlan {
+                    regex: regex.to_owned(),
+                    plan: Some(format!("{plan:?}")),
+                    error: None,
+                },
+                Err(err) => RegexPlan {
+                    regex: regex.to_owned(),
+                    plan: None,
+                    error: Some(err.to_string()),
+                },
+            })
+            .collect();
+
+        let explained = [
+            explain_index("repo", &RepoReader, &indexes.repo, &expr, &self).await?,
+            explain_index("file", &FileReader, &indexes.file, &expr, &self).await?,
+            explain_index("content", &ContentReader, &indexes.file, &expr, &self).await?,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        Ok(Explanation {
+            expression: format!("{expr:?}"),
+            regexes,
+            indexes: explained,
+        })
+    }
+}
+
+/// Run a query against a single index, counting hits before and after post-filtering.
+///
+/// Returns `None` if the reader cannot answer any branch of the query.
+async fn explain_index<R, T>(
+    name: &'static str,
+    reader: &R,
+    indexer: &Indexer<T>,
+    expr: &parser::QueryExpr<'_>,
+    q: &ApiQuery,
+) -> Result<Option<IndexExplanation>>
+where
+    R: DocumentRead<Schema = T> + ExecuteQuery<Index = T>,
+    T: Indexable,
+{
+    if !reader.query_matches(expr) {
+        return Ok(None);
+    }
+
+    let selected = reader.select(expr);
+    let compiled = reader.compile(&indexer.source, &selected, &indexer.index)?;
+    let hits = indexer
+        .reader
+        .read()
+        .await
+        .searcher()
+        .search(&compiled, &Count)
+        .context("failed to execute search query")?;
+
+    let filtered_hits = reader
+        .execute(indexer, expr, q)
+        .await?
+        .metadata
+        .total_count();
+
+    Ok(Some(IndexExplanation {
+        reader: name,
+        query: format!("{compiled:?}"),
+        hits,
+        filtered_hits,
+    }))
+}
+
+/// All regexes in a query that are planned into trigram queries by the compiler.
+///
+/// Plain literals are tokenized instead, and excluded regexes are only used to post-filter.
+fn regexes<'a>(query: &'a parser::Query<'a>) -> impl Iterator<Item = &'a str> {
+    let target = match &query.target {
+        Some(Target::Symbol(lit) | Target::Content(lit)) => Some(lit),
+        Some(Target::Ast(_)) | None => None,
+    };
+
+    query
+        .org
+        .iter()
+        .chain(&query.repo)
+        .chain(&query.paths)
+        .chain(&query.branches)
+        .chain(&query.author)
+        .chain(target)
+        .filter_map(|lit| match lit {
+            Literal::Regex(regex) => Some(regex.as_ref()),
+            Literal::Plain(_) => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_regexes() {
+        let expr = parser::parse("repo:/^bloop$/ path:src /fn \\w+/ -content:/todo/").unwrap();
+        let mut observed = expr.terms().flat_map(regexes).collect::<Vec<_>>();
+        observed.sort();
+
+        assert_eq!(observed, vec!["^bloop$", "fn \\w+"]);
+
+        let expr = parser::parse("ast:\"(identifier) @id\"").unwrap();
+        assert_eq!(expr.terms().flat_map(regexes).count(), 0);
+    }
+}
@@ -0,0 +1,330 @@
+//! Export every match of a content search, rather than a single page of results.
+//!
+//! Matches are streamed as they are found, either as JSON Lines, CSV, or a SARIF 2.1 log that
+//! code-scanning tools can ingest.
+
+use std::sync::Arc;
+
+use anyhow::{ensure, Result};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+    execute::{ApiQuery, ExecuteQuery, QueryResult},
+    parser,
+};
+use crate::{
+    indexes::{reader::ContentReader, DocumentRead, Indexes},
+    snippet::SnippedFile,
+};
+
+/// Number of files searched at a time while exporting
+const EXPORT_PAGE_SIZE: usize = 500;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_RULE: &str = "search-match";
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Jsonl,
+    Csv,
+    Sarif,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    pub format: ExportFormat,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Jsonl => "application/x-ndjson",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Sarif => "application/sarif+json",
+        }
+    }
+
+    pub fn file_extension(self) -> &'static str {
+        match self {
+            Self::Jsonl => "jsonl",
+            Self::Csv => "csv",
+            Self::Sarif => "sarif",
+        }
+    }
+}
+
+/// A single highlighted match in a file.
+///
+/// Lines and columns start at 1, and columns are counted in characters. The end column points
+/// just past the last character of the match.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Match {
+    repo_name: String,
+    repo_ref: String,
+    relative_path: String,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+
+    /// The whole line the match starts on
+    text: String,
+}
+
+impl ApiQuery {
+    /// Stream every match of this query in the given format, paging through the whole index.
+    ///
+    /// Only content searches can be exported, as other searches don't point at lines in a file.
+    pub fn export(
+        self: Arc<Self>,
+        indexes: Arc<Indexes>,
+        format: ExportFormat,
+    ) -> Result<impl Stream<Item = Result<String>>> {
+        let expr = parser::parse(&self.q)?;
+        ensure!(
+            ContentReader.query_matches(&expr),
+            "only content searches can be exported"
+        );
+
+        Ok(async_stream::try_stream! {
+            let expr = parser::parse(&self.q)?;
+            let mut cursor = None;
+            let mut first = true;
+
+            if let Some(header) = header(format, &self.q) {
+                yield header;
+            }
+
+            loop {
+                let mut page = self.first(EXPORT_PAGE_SIZE);
+                page.cursor = cursor;
+
+                let response = ContentReader.execute(&indexes.file, &expr, &page).await?;
+                cursor = response.metadata.next_cursor().map(str::to_owned);
+
+                for file in response.data.into_iter().filter_map(|r| match r {
+                    QueryResult::Snippets(file) => Some(file),
+                    _ => None,
+                }) {
+                    for m in matches(&file) {
+                        yield render(format, &m, first)?;
+                        first = false;
+                    }
+                }
+
+                if cursor.is_none() {
+                    break;
+                }
+            }
+
+            if let Some(footer) = footer(format) {
+                yield footer;
+            }
+        })
+    }
+}
+
+/// Split the highlights of a file's snippets into individual matches.
+fn matches(file: &SnippedFile) -> impl Iterator<Item = Match> + '_ {
+    file.snippets.iter().flat_map(move |snippet| {
+        let data = snippet.data.as_str();
+        let mut highlights = snippet.highlights.clone();
+        highlights.sort_by_key(|h| (h.start, h.end));
+
+        highlights.into_iter().filter_map(move |h| {
+            let (line, column, line_start) = position(data.get(..h.start)?);
+            let (end_line, end_column, _) = position(data.get(..h.end)?);
+
+            let line_end = data[h.start..]
+                .find('\n')
+                .map_or(data.len(), |i| i + h.start);
+
+            Some(Match {
+                repo_name: file.repo_name.clone(),
+                repo_ref: file.repo_ref.clone(),
+                relative_path: file.relative_path.clone(),
+                line: snippet.line_range.start + line + 1,
+                column,
+                end_line: snippet.line_range.start + end_line + 1,
+                end_column,
+                text: data[line_start..line_end].trim_end_matches('\r').to_owned(),
+            })
+        })
+    })
+}
+
+/// The 0-based line and 1-based column at the end of `prefix`, and the byte offset of the start of
+/// that line.
+fn position(prefix: &str) -> (usize, usize, usize) {
+    let line = prefix.matches('\n').count();
+    let line_start = prefix.rfind('\n').map_or(0, |i| i + 1);
+    let column = prefix[line_start..].chars().count() + 1;
+    (line, column, line_start)
+}
+
+fn header(format: ExportFormat, q: &str) -> Option<String> {
+    match format {
+        ExportFormat::Jsonl => None,
+        ExportFormat::Csv => Some("repo,path,line,column,text\n".to_owned()),
+        ExportFormat::Sarif => {
+            let tool = json!({
+                "driver": {
+                    "name": "bloop",
+                    "informationUri": "https://bloop.ai",
+                    "rules": [{
+                        "id": SARIF_RULE,
+                        "shortDescription": { "text": format!("Matches of `{q}`") },
+                    }],
+                },
+            });
+
+            // the log is a single JSON document, so results are streamed between this opening
+            // fragment and the footer
+            Some(format!(
+                r#"{{"$schema":{},"version":"2.1.0","runs":[{{"tool":{tool},"results":["#,
+                json!(SARIF_SCHEMA),
+            ))
+        }
+    }
+}
+
+fn footer(format: ExportFormat) -> Option<String> {
+    match format {
+        ExportFormat::Sarif => Some("]}]}\n".to_owned()),
+        ExportFormat::Jsonl | ExportFormat::Csv => None,
+    }
+}
+
+fn render(format: ExportFormat, m: &Match, first: bool) -> Result<String> {
+    Ok(match format {
+        ExportFormat::Jsonl => format!("{}\n", serde_json::to_string(m)?),
+        ExportFormat::Csv => format!(
+            "{},{},{},{},{}\n",
+            csv_field(&m.repo_name),
+            csv_field(&m.relative_path),
+            m.line,
+            m.column,
+            csv_field(&m.text),
+        ),
+        ExportFormat::Sarif => {
+            let result = json!({
+                "ruleId": SARIF_RULE,
+                "level": "note",
+                "message": { "text": m.text.trim() },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": m.relative_path },
+                        "region": {
+                            "startLine": m.line,
+                            "startColumn": m.column,
+                            "endLine": m.end_line,
+                            "endColumn": m.end_column,
+                            "snippet": { "text": m.text },
+                        },
+                    },
+                }],
+                "properties": {
+                    "repoName": m.repo_name,
+                    "repoRef": m.repo_ref,
+                },
+            });
+
+            if first {
+                result.to_string()
+            } else {
+                format!(",{result}")
+            }
+        }
+    })
+}
+
+/// Quote a CSV field if needed, as described in RFC 4180.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snippet::Snippet;
+
+    fn file() -> SnippedFile {
+        SnippedFile {
+            relative_path: "src/main.rs".to_owned(),
+            repo_name: "bloop".to_owned(),
+            repo_ref: "local//bloop".to_owned(),
+            lang: Some("Rust".to_owned()),
+            snippets: vec![Snippet {
+                data: "fn main() {\n    let héllo = \"world\";\n}\n".to_owned(),
+                line_range: 9..12,
+                highlights: vec![30..35, 3..7],
+                symbols: vec![],
+            }],
+        }
+    }
+
+    #[test]
+    fn split_matches() {
+        let file = file();
+        let matches = matches(&file).collect::<Vec<_>>();
+
+        assert_eq!(matches.len(), 2);
+
+        assert_eq!(matches[0].line, 10);
+        assert_eq!(matches[0].column, 4);
+        assert_eq!(matches[0].end_column, 8);
+        assert_eq!(matches[0].text, "fn main() {");
+
+        // columns are counted in characters, not bytes
+        assert_eq!(matches[1].line, 11);
+        assert_eq!(matches[1].column, 18);
+        assert_eq!(matches[1].end_line, 11);
+        assert_eq!(matches[1].end_column, 23);
+        assert_eq!(matches[1].text, "    let héllo = \"world\";");
+    }
+
+    #[test]
+    fn csv_rows() {
+        let file = file();
+        let rows = matches(&file)
+            .map(|m| render(ExportFormat::Csv, &m, false).unwrap())
+            .collect::<String>();
+
+        assert_eq!(
+            rows,
+            "bloop,src/main.rs,10,4,fn main() {\n\
+             bloop,src/main.rs,11,18,\"    let héllo = \"\"world\"\";\"\n"
+        );
+    }
+
+    #[test]
+    fn sarif_log() {
+        let file = file();
+        let mut log = header(ExportFormat::Sarif, "main").unwrap();
+        for (i, m) in matches(&file).enumerate() {
+            log += &render(ExportFormat::Sarif, &m, i == 0).unwrap();
+        }
+        log += &footer(ExportFormat::Sarif).unwrap();
+
+        let log: serde_json::Value = serde_json::from_str(&log).unwrap();
+        assert_eq!(log["version"], "2.1.0");
+
+        let results = log["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["region"]["startLine"],
+            11
+        );
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "src/main.rs"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Facets that search results can be counted by, on top of the `lang` and `repo` stats.
+//!
+//! Besides the built-in facets, results can be counted by the values of a few categorical fast
+//! fields of the file index, such as `lang`. Fields with a value per file, such as the contents or
+//! commit times of files, would return as many counts as there are results.
+
+use anyhow::{bail, Result};
+use tantivy::schema::FieldType;
+
+use crate::{
+    collector::FacetField,
+    indexes::{File, Indexable},
+    repo::iterator::FileClass,
+};
+
+/// The fast fields of the file index that results can be counted by, with `Facet::Field`.
+const FIELDS: &[&str] = &["lang", "extension", "file_class", "raw_repo_name"];
+
+/// A facet that results can't be counted by, which is the caller's fault.
+#[derive(Debug, thiserror::Error)]
+#[error("unknown facet `{0}`, expected one of dir, branch, class, ext, {fields}", fields = FIELDS.join(", "))]
+pub struct UnknownFacet(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Facet {
+    /// The top-level directory of a file
+    Dir,
+    Branch,
+    Class,
+    Ext,
+
+    /// A categorical fast field of the file index, by name
+    Field(String),
+}
+
+impl Facet {
+    /// Parse a comma-separated list of facets, as in `facets=dir,ext`.
+    pub fn parse_list(list: &str) -> Vec<Self> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| match name {
+                "dir" => Self::Dir,
+                "branch" => Self::Branch,
+                "class" => Self::Class,
+                "ext" => Self::Ext,
+                field => Self::Field(field.to_owned()),
+            })
+            .collect()
+    }
+
+    /// The key of this facet in `ResultStats::facets`.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Dir => "dir",
+            Self::Branch => "branch",
+            Self::Class => "class",
+            Self::Ext => "ext",
+            Self::Field(name) => name,
+        }
+    }
+
+    /// The fast field this facet is read from.
+    pub fn field(&self, source: &File) -> Result<FacetField> {
+        Ok(match self {
+            Self::Dir => FacetField::TopDirectory(source.raw_relative_path),
+            Self::Branch => FacetField::Lines(source.raw_branches),
+            Self::Class => FacetField::U64(source.file_class),
+            Self::Ext => FacetField::Bytes(source.extension),
+            Self::Field(name) => {
+                let schema = source.schema();
+                let Some(field) = schema
+                    .get_field(name)
+                    .filter(|_| FIELDS.contains(&name.as_str()))
+                else {
+                    return Err(UnknownFacet(name.clone()).into());
+                };
+
+                let entry = schema.get_field_entry(field);
+                match entry.field_type() {
+                    FieldType::Bytes(_) if entry.is_fast() => FacetField::Bytes(field),
+                    FieldType::U64(_) if entry.is_fast() => FacetField::U64(field),
+                    _ => bail!("`{name}` is not a bytes or numeric fast field"),
+                }
+            }
+        })
+    }
+
+    /// Turn a raw value of this facet into the value shown to users.
+    pub fn display(&self, value: String) -> String {
+        match self {
+            Self::Class => value
+                .parse()
+                .map(|v| FileClass::from_u64(v).name().to_owned())
+                .unwrap_or(value),
+            _ => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_facets() {
+        assert_eq!(
+            Facet::parse_list("dir, ext,,lang"),
+            [Facet::Dir, Facet::Ext, Facet::Field("lang".to_owned())]
+        );
+        assert!(Facet::parse_list("").is_empty());
+    }
+
+    #[test]
+    fn display_class() {
+        assert_eq!(Facet::Class.display("1".to_owned()), "test");
+        assert_eq!(Facet::Ext.display("rs".to_owned()), "rs");
+    }
+}
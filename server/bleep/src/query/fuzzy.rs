@@ -0,0 +1,364 @@
+//! Typo-tolerant searches, as in `fuzzy:true prase`.
+//!
+//! A fuzzy target also matches words that are one or two edits away from it. Candidate documents
+//! are those sharing an n-gram with the target, which are then matched with a regex that spells
+//! out every allowed edit, and ranked by how close their best match is to the target.
+
+use std::{borrow::Cow, collections::BTreeSet, sync::Arc};
+
+use tantivy::{
+    collector::{ScoreSegmentTweaker, ScoreTweaker},
+    fastfield::BytesFastFieldReader,
+    schema::Field,
+    DocId, Score, SegmentReader,
+};
+
+use super::parser::{Literal, Query, Target};
+
+/// Words shorter than this are matched exactly, as their misspellings don't necessarily share a
+/// bigram with them.
+const MIN_WORD_LEN: usize = 4;
+
+/// Words longer than this are matched exactly, as the number of ways to misspell them grows
+/// quadratically with their length.
+const MAX_WORD_LEN: usize = 20;
+
+/// Words up to this long tolerate a single edit, longer words tolerate two.
+const SINGLE_EDIT_LEN: usize = 5;
+
+/// A character inserted or substituted by a typo.
+const ANY: &str = "[0-9A-Za-z_]";
+
+/// The number of edits tolerated in matches of `word`.
+pub fn max_edits(word: &str) -> usize {
+    match word.chars().count() {
+        n if !(MIN_WORD_LEN..=MAX_WORD_LEN).contains(&n) => 0,
+        n if n <= SINGLE_EDIT_LEN => 1,
+        _ => 2,
+    }
+}
+
+/// The length of the n-grams that every match of `word` shares at least one of.
+///
+/// A word of `n` characters has `n - q + 1` n-grams of length `q`, and each edit breaks at most
+/// `q` of them, so some survive as long as `n - q + 1 > q * edits`. Longer n-grams are rarer,
+/// and narrow down candidates better, but only n-grams up to 3 characters long are indexed.
+pub fn gram_len(word: &str) -> usize {
+    let n = word.chars().count();
+    let edits = max_edits(word);
+
+    (1..=3).rev().find(|q| n + 1 > q * (edits + 1)).unwrap_or(1)
+}
+
+/// The regex a target literal is matched with, before any word boundaries are added by
+/// [`Query::target_regex_str`].
+///
+/// In fuzzy queries, plain literals also match whole words that are a few edits away from them.
+/// Regexes are never fuzzy.
+pub fn regex_str<'a>(lit: &Literal<'a>, fuzzy: bool) -> Cow<'a, str> {
+    match lit {
+        Literal::Plain(word) if fuzzy && max_edits(word) > 0 => build_fuzzy_regex(word).into(),
+        lit => lit.regex_str(),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Token {
+    Char(char),
+    Any,
+}
+
+/// Build a regex matching `word` anywhere, or any whole word within `max_edits(word)` edits of
+/// it.
+fn build_fuzzy_regex(word: &str) -> String {
+    let mut patterns = BTreeSet::from([word.chars().map(Token::Char).collect::<Vec<_>>()]);
+    for _ in 0..max_edits(word) {
+        patterns = edit(patterns);
+    }
+
+    let variants = patterns
+        .into_iter()
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| {
+            let boundary = |token: Option<&Token>| match token {
+                Some(Token::Char(c)) if !c.is_alphanumeric() && *c != '_' => "",
+                _ => "\\b",
+            };
+
+            let body = pattern
+                .iter()
+                .map(|token| match token {
+                    Token::Char(c) => regex::escape(&c.to_string()),
+                    Token::Any => ANY.to_owned(),
+                })
+                .collect::<String>();
+
+            format!(
+                "{}{body}{}",
+                boundary(pattern.first()),
+                boundary(pattern.last())
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // exact matches come first, so they are preferred over typos starting at the same position
+    format!("{}|{}", regex::escape(word), variants.join("|"))
+}
+
+/// Apply every single insertion, deletion and substitution to a set of patterns, keeping the
+/// original patterns.
+fn edit(patterns: BTreeSet<Vec<Token>>) -> BTreeSet<Vec<Token>> {
+    let mut edited = patterns.clone();
+
+    for pattern in &patterns {
+        for i in 0..=pattern.len() {
+            let mut inserted = pattern.clone();
+            inserted.insert(i, Token::Any);
+            edited.insert(inserted);
+
+            // editing a character that was already inserted or substituted is redundant
+            if let Some(Token::Char(_)) = pattern.get(i) {
+                let mut deleted = pattern.clone();
+                deleted.remove(i);
+                edited.insert(deleted);
+
+                let mut substituted = pattern.clone();
+                substituted[i] = Token::Any;
+                edited.insert(substituted);
+            }
+        }
+    }
+
+    edited
+}
+
+/// The number of single character insertions, deletions or substitutions that turn `a` into `b`.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// A fuzzy target of a query, used to find how far the matches in a document are from it.
+pub struct FuzzyTarget {
+    word: String,
+    regex: regex::bytes::Regex,
+    case_sensitive: bool,
+}
+
+impl FuzzyTarget {
+    /// The fuzzy target of a query, if it is a fuzzy query for a plain content or symbol target.
+    pub fn new(query: &Query<'_>) -> Option<Self> {
+        let (Target::Content(Literal::Plain(word)) | Target::Symbol(Literal::Plain(word))) =
+            query.target.as_ref()?
+        else {
+            return None;
+        };
+
+        if !query.is_fuzzy() || max_edits(word) == 0 {
+            return None;
+        }
+
+        let regex = regex::bytes::RegexBuilder::new(&build_fuzzy_regex(word))
+            .case_insensitive(!query.is_case_sensitive())
+            .build()
+            .ok()?;
+
+        let word = if query.is_case_sensitive() {
+            word.to_string()
+        } else {
+            word.to_lowercase()
+        };
+
+        Some(Self {
+            word,
+            regex,
+            case_sensitive: query.is_case_sensitive(),
+        })
+    }
+
+    /// The edit distance of the closest match in `content`, if there are any matches.
+    fn distance(&self, content: &[u8]) -> Option<usize> {
+        let mut closest = None;
+
+        for m in self.regex.find_iter(content) {
+            let text = String::from_utf8_lossy(m.as_bytes());
+            let distance = if self.case_sensitive {
+                levenshtein(&self.word, &text)
+            } else {
+                levenshtein(&self.word, &text.to_lowercase())
+            };
+
+            if distance == 0 {
+                return Some(0);
+            }
+
+            closest = Some(closest.map_or(distance, |c: usize| c.min(distance)));
+        }
+
+        closest
+    }
+}
+
+/// A score tweaker that ranks documents by how close their best fuzzy match is to the targets of
+/// a query.
+///
+/// Scores are first computed by the inner tweaker, and then divided by one more than the edit
+/// distance of the closest match, so that exact matches rank first.
+pub struct FuzzyTweaker<T> {
+    inner: T,
+    raw_content: Field,
+    targets: Arc<[FuzzyTarget]>,
+}
+
+pub struct FuzzySegmentScorer<T> {
+    inner: T,
+    content: Option<BytesFastFieldReader>,
+    targets: Arc<[FuzzyTarget]>,
+}
+
+impl<T> FuzzyTweaker<T> {
+    pub fn new(inner: T, raw_content: Field, targets: Arc<[FuzzyTarget]>) -> Self {
+        Self {
+            inner,
+            raw_content,
+            targets,
+        }
+    }
+}
+
+impl<T: ScoreTweaker<Score>> ScoreTweaker<Score> for FuzzyTweaker<T> {
+    type Child = FuzzySegmentScorer<T::Child>;
+
+    fn segment_tweaker(&self, segment_reader: &SegmentReader) -> tantivy::Result<Self::Child> {
+        // without fuzzy targets, there is no need to read the contents of each document
+        let content = if self.targets.is_empty() {
+            None
+        } else {
+            Some(segment_reader.fast_fields().bytes(self.raw_content)?)
+        };
+
+        Ok(FuzzySegmentScorer {
+            inner: self.inner.segment_tweaker(segment_reader)?,
+            content,
+            targets: Arc::clone(&self.targets),
+        })
+    }
+}
+
+impl<T: ScoreSegmentTweaker<Score>> ScoreSegmentTweaker<Score> for FuzzySegmentScorer<T> {
+    fn score(&mut self, doc: DocId, score: Score) -> Score {
+        let score = self.inner.score(doc, score);
+
+        let Some(content) = &self.content else {
+            return score;
+        };
+
+        let content = content.get_bytes(doc);
+        match self
+            .targets
+            .iter()
+            .filter_map(|t| t.distance(content))
+            .min()
+        {
+            Some(distance) => score / (1.0 + distance as f32),
+            None => score,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parser::{self, Expression};
+
+    fn fuzzy_target(q: &str) -> Option<FuzzyTarget> {
+        match parser::parse(q).unwrap() {
+            Expression::Term(query) => FuzzyTarget::new(&query),
+            _ => panic!("not a single term"),
+        }
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(levenshtein("parse", "parse"), 0);
+        assert_eq!(levenshtein("prase", "parse"), 2);
+        assert_eq!(levenshtein("indxer", "indexer"), 1);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn tolerated_edits() {
+        assert_eq!(max_edits("id"), 0);
+        assert_eq!(max_edits("map"), 0);
+        assert_eq!(max_edits("parse"), 1);
+        assert_eq!(max_edits("indexer"), 2);
+        assert_eq!(max_edits("a_very_long_identifier_name"), 0);
+    }
+
+    #[test]
+    fn shared_grams() {
+        assert_eq!(gram_len("pars"), 2);
+        assert_eq!(gram_len("parse"), 2);
+        assert_eq!(gram_len("indexer"), 2);
+        assert_eq!(gram_len("semantic"), 2);
+        assert_eq!(gram_len("tokenizer"), 3);
+    }
+
+    #[test]
+    fn fuzzy_regex() {
+        let regex = regex::Regex::new(&build_fuzzy_regex("indexer")).unwrap();
+        let matches = |s: &str| regex.find(s).map(|m| m.as_str().to_owned());
+
+        // exact matches are found anywhere, typos only as whole words
+        assert_eq!(matches("reindexers"), Some("indexer".to_owned()));
+        assert_eq!(matches("let indxer = 1;"), Some("indxer".to_owned()));
+        assert_eq!(matches("fn idnexer()"), Some("idnexer".to_owned()));
+        assert_eq!(matches("fn indexes()"), Some("indexes".to_owned()));
+        assert_eq!(matches("reindxers"), None);
+        assert_eq!(matches("indices"), None);
+
+        // short words only tolerate a single edit
+        let regex = regex::Regex::new(&build_fuzzy_regex("parse")).unwrap();
+        assert!(regex.is_match("parze"));
+        assert!(!regex.is_match("prase"));
+    }
+
+    #[test]
+    fn literal_regex() {
+        let plain = Literal::Plain("parse".into());
+        assert_eq!(regex_str(&plain, false), "parse");
+        assert_ne!(regex_str(&plain, true), "parse");
+
+        let regex = Literal::Regex("par.e".into());
+        assert_eq!(regex_str(&regex, true), "par.e");
+    }
+
+    #[test]
+    fn closest_match() {
+        let target = fuzzy_target("fuzzy:true Indexer").unwrap();
+        assert_eq!(target.distance(b"let indxer = Indexes::new();"), Some(1));
+        assert_eq!(target.distance(b"struct Indexer;"), Some(0));
+        assert_eq!(target.distance(b"nothing to see here"), None);
+
+        let target = fuzzy_target("fuzzy:true case:sensitive Indexer").unwrap();
+        assert_eq!(target.distance(b"let indexer = 1;"), Some(1));
+
+        assert!(fuzzy_target("Indexer").is_none());
+        assert!(fuzzy_target("fuzzy:true /Ind.xer/").is_none());
+        assert!(fuzzy_target("fuzzy:true id").is_none());
+    }
+}
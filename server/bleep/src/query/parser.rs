@@ -1,7 +1,10 @@
 use pest::{iterators::Pair, Parser};
 use regex::Regex;
-use smallvec::{smallvec, SmallVec};
-use std::{borrow::Cow, collections::HashSet, mem};
+use regex_syntax::hir::literal::Literals;
+use std::{borrow::Cow, collections::HashSet, mem, ops::Range};
+
+use super::fuzzy;
+use crate::repo::iterator::FileClass;
 
 #[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct Query<'a> {
@@ -9,31 +12,86 @@ pub struct Query<'a> {
     pub case_sensitive: Option<bool>,
     pub global_regex: Option<bool>,
 
+    /// Whether symbol and content targets only match whole words, as in `word:true id`.
+    pub word: Option<bool>,
+
+    /// Whether plain content and symbol targets also match words that are one or two edits away,
+    /// as in `fuzzy:true prase`.
+    pub fuzzy: Option<bool>,
+
     pub org: Option<Literal<'a>>,
     pub repo: Option<Literal<'a>>,
-    pub path: Option<Literal<'a>>,
-    pub lang: Option<Cow<'a, str>>,
-    pub branch: Option<Literal<'a>>,
+
+    /// Path, language and branch filters. A document must match at least one entry of each
+    /// non-empty set, e.g. `lang:rust lang:go` matches either language.
+    pub paths: HashSet<Literal<'a>>,
+    pub langs: HashSet<Cow<'a, str>>,
+    pub branches: HashSet<Literal<'a>>,
+
+    /// Filters on the last commit that modified a file. The `modified` range holds unix
+    /// timestamps, e.g. `modified:>2024-01-01` matches anything modified after that day.
+    pub author: Option<Literal<'a>>,
+    pub modified: Option<Range<u64>>,
+
+    /// Filters on the size of a file in bytes and its number of lines, e.g. `size:>100k` or
+    /// `lines:<300`.
+    pub size: Option<Range<u64>>,
+    pub lines: Option<Range<u64>>,
+
+    /// Whether to only match directories or files, as in `is:dir` and `is:file`.
+    pub is_dir: Option<bool>,
+
+    /// File extensions, without the leading dot. A document must have one of these extensions,
+    /// e.g. `ext:rs ext:go` matches either.
+    pub extensions: HashSet<Cow<'a, str>>,
+
+    /// Classes of files, such as tests or generated code. A document must be of one of these
+    /// classes, e.g. `is:test is:docs` matches either.
+    pub classes: HashSet<FileClass>,
+
     pub target: Option<Target<'a>>,
+
+    /// Kinds of symbols to search for, such as `function` or `struct`. Symbol hits must have one
+    /// of these kinds, e.g. `kind:struct kind:enum Error` matches either kind.
+    pub symbol_kinds: HashSet<Cow<'a, str>>,
+
+    /// Negated terms, such as `-path:test` or `not lang:javascript`.
+    ///
+    /// Each entry holds a single label, and documents matching any of them are excluded.
+    pub exclude: Vec<Query<'a>>,
+}
+
+/// Intersect two optional ranges, where `None` matches everything.
+fn intersect(lhs: Option<Range<u64>>, rhs: Option<Range<u64>>) -> Option<Range<u64>> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => Some(lhs.start.max(rhs.start)..lhs.end.min(rhs.end)),
+        (lhs, rhs) => rhs.or(lhs),
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Target<'a> {
     Symbol(Literal<'a>),
     Content(Literal<'a>),
+
+    /// A tree-sitter query pattern, matched against the syntax tree of each document.
+    Ast(Literal<'a>),
 }
 
 #[derive(Debug, PartialEq, Eq)]
 #[allow(clippy::large_enum_variant)]
 pub enum ParsedQuery<'a> {
     Semantic(SemanticQuery<'a>),
-    Grep(Vec<Query<'a>>),
+    Grep(QueryExpr<'a>),
+
+    /// A natural language query that is also searched for lexically, see `mode:hybrid`.
+    Hybrid(SemanticQuery<'a>),
 }
 
 impl<'a> ParsedQuery<'a> {
     pub fn into_semantic(self) -> Option<SemanticQuery<'a>> {
         match self {
-            Self::Semantic(q) => Some(q),
+            Self::Semantic(q) | Self::Hybrid(q) => Some(q),
             _ => None,
         }
     }
@@ -46,6 +104,15 @@ pub struct SemanticQuery<'a> {
     pub langs: HashSet<Cow<'a, str>>,
     pub branch: HashSet<Literal<'a>>,
     pub target: Option<Literal<'a>>,
+
+    #[serde(default)]
+    pub excluded_repos: HashSet<Literal<'a>>,
+    #[serde(default)]
+    pub excluded_paths: HashSet<Literal<'a>>,
+    #[serde(default)]
+    pub excluded_langs: HashSet<Cow<'a, str>>,
+    #[serde(default)]
+    pub excluded_branches: HashSet<Literal<'a>>,
 }
 
 impl<'a> SemanticQuery<'a> {
@@ -69,6 +136,22 @@ impl<'a> SemanticQuery<'a> {
         self.branch.iter().filter_map(|t| t.as_plain())
     }
 
+    pub fn excluded_repos(&'a self) -> impl Iterator<Item = Cow<'a, str>> {
+        self.excluded_repos.iter().filter_map(|t| t.as_plain())
+    }
+
+    pub fn excluded_paths(&'a self) -> impl Iterator<Item = Cow<'a, str>> {
+        self.excluded_paths.iter().filter_map(|t| t.as_plain())
+    }
+
+    pub fn excluded_langs(&'a self) -> impl Iterator<Item = Cow<'a, str>> {
+        self.excluded_langs.iter().cloned()
+    }
+
+    pub fn excluded_branches(&'a self) -> impl Iterator<Item = Cow<'a, str>> {
+        self.excluded_branches.iter().filter_map(|t| t.as_plain())
+    }
+
     // TODO (@calyptobai): This is a quirk of the current conversation logic. We take only the
     // first branch because the UX operates on a single "current" branch. We can likely update
     // `SemanticQuery` to remove multiple branches altogether.
@@ -95,6 +178,26 @@ impl<'a> SemanticQuery<'a> {
                 .collect(),
             branch: self.branch.into_iter().map(Literal::into_owned).collect(),
             target: self.target.map(Literal::into_owned),
+            excluded_repos: self
+                .excluded_repos
+                .into_iter()
+                .map(Literal::into_owned)
+                .collect(),
+            excluded_paths: self
+                .excluded_paths
+                .into_iter()
+                .map(Literal::into_owned)
+                .collect(),
+            excluded_langs: self
+                .excluded_langs
+                .into_iter()
+                .map(|c| c.into_owned().into())
+                .collect(),
+            excluded_branches: self
+                .excluded_branches
+                .into_iter()
+                .map(Literal::into_owned)
+                .collect(),
         }
     }
 }
@@ -102,17 +205,30 @@ impl<'a> SemanticQuery<'a> {
 impl<'a> Query<'a> {
     /// Merge this query with another, overwriting current terms by terms in the new query, if they
     /// exist.
+    ///
+    /// Path, language, branch, extension and class filters are accumulated rather than overwritten, and
+    /// numeric ranges are intersected.
     fn merge(self, rhs: Self) -> Self {
         Self {
             open: rhs.open.or(self.open),
             case_sensitive: rhs.case_sensitive.or(self.case_sensitive),
             global_regex: rhs.global_regex.or(self.global_regex),
+            word: rhs.word.or(self.word),
+            fuzzy: rhs.fuzzy.or(self.fuzzy),
 
             org: rhs.org.or(self.org),
             repo: rhs.repo.or(self.repo),
-            path: rhs.path.or(self.path),
-            lang: rhs.lang.or(self.lang),
-            branch: rhs.branch.or(self.branch),
+            paths: self.paths.into_iter().chain(rhs.paths).collect(),
+            langs: self.langs.into_iter().chain(rhs.langs).collect(),
+            branches: self.branches.into_iter().chain(rhs.branches).collect(),
+
+            author: rhs.author.or(self.author),
+            modified: intersect(self.modified, rhs.modified),
+            size: intersect(self.size, rhs.size),
+            lines: intersect(self.lines, rhs.lines),
+            is_dir: rhs.is_dir.or(self.is_dir),
+            extensions: self.extensions.into_iter().chain(rhs.extensions).collect(),
+            classes: self.classes.into_iter().chain(rhs.classes).collect(),
 
             target: match (self.target, rhs.target) {
                 (Some(Target::Content(lhs)), Some(Target::Content(rhs))) => {
@@ -122,46 +238,14 @@ impl<'a> Query<'a> {
                 // TODO: Do we want to return an error here?
                 (lhs, rhs) => rhs.or(lhs),
             },
-        }
-    }
 
-    /// Fetch all `merge`s of this `Query` with a list of query groups.
-    ///
-    /// This is useful to flatten out a nested tree of queries. For example, with the following input
-    /// string:
-    ///
-    /// ```text
-    /// (repo:enterprise-search or repo:query-planner) (org:bloop or org:google) ParseError
-    /// ```
-    ///
-    /// This method will effectively flatten out the combinators, resulting in the equivalent list
-    /// of queries:
-    ///
-    /// ```text
-    ///    (repo:enterprise-search org:bloop  ParseError)
-    /// or (repo:query-planner     org:bloop  ParseError)
-    /// or (repo:enterprise-search org:google ParseError)
-    /// or (repo:query-planner     org:google ParseError)
-    /// ```
-    ///
-    /// The input argument `iter` expects an iterator, where each item is another iterator over a
-    /// set of queries joined with `or`. Because a list of queries is effectively an `or`, you can
-    /// interpret the input as being a list of `or` groups that are `and`ed together.
-    fn cross<I>(self, mut iter: I) -> SmallVec<[Self; 1]>
-    where
-        I: Clone + Iterator,
-        I::Item: Iterator<Item = Self>,
-    {
-        if let Some(queries) = iter.next() {
-            let mut list = smallvec![];
-
-            for rhs in queries {
-                list.extend(self.clone().merge(rhs).cross(iter.clone()));
-            }
+            symbol_kinds: self
+                .symbol_kinds
+                .into_iter()
+                .chain(rhs.symbol_kinds)
+                .collect(),
 
-            list
-        } else {
-            smallvec![self]
+            exclude: self.exclude.into_iter().chain(rhs.exclude).collect(),
         }
     }
 
@@ -170,14 +254,308 @@ impl<'a> Query<'a> {
         self.case_sensitive.unwrap_or_default()
     }
 
+    pub fn is_fuzzy(&self) -> bool {
+        // defaults to false if unset
+        self.fuzzy.unwrap_or_default()
+    }
+
+    pub fn is_word(&self) -> bool {
+        // defaults to false if unset
+        self.word.unwrap_or_default()
+    }
+
+    /// The regex that `lit`, the content or symbol target of this query, is matched with.
+    ///
+    /// Fuzzy queries also match typos of plain targets. Whole word queries add a word boundary on
+    /// each side of the target that starts or ends with a word character, so that `foo(` still
+    /// matches `foo(x)`, but not `afoo(x)`.
+    pub fn target_regex_str<'b>(&self, lit: &Literal<'b>) -> Cow<'b, str> {
+        let regex = fuzzy::regex_str(lit, self.is_fuzzy());
+        if !self.is_word() {
+            return regex;
+        }
+
+        let (starts_with_word, ends_with_word) = lit.word_edges();
+        let boundary = |word: bool| if word { "\\b" } else { "" };
+        format!(
+            "{}(?:{regex}){}",
+            boundary(starts_with_word),
+            boundary(ends_with_word)
+        )
+        .into()
+    }
+
+    /// Whether this term restricts documents by anything other than its target and exclusions,
+    /// such as a repository, path or language.
+    ///
+    /// Flags like `case:` only change how the other labels are matched, and are not filters.
+    pub fn has_filters(&self) -> bool {
+        self.org.is_some()
+            || self.repo.is_some()
+            || !self.paths.is_empty()
+            || !self.langs.is_empty()
+            || !self.branches.is_empty()
+            || self.author.is_some()
+            || self.modified.is_some()
+            || self.size.is_some()
+            || self.lines.is_some()
+            || self.is_dir.is_some()
+            || !self.extensions.is_empty()
+            || !self.classes.is_empty()
+            || !self.symbol_kinds.is_empty()
+    }
+
     fn set_global_regex(&mut self, value: Option<bool>) {
         self.global_regex = value;
         if let Some(true) = value {
             self.org.as_mut().map(Literal::make_regex);
             self.repo.as_mut().map(Literal::make_regex);
-            self.path.as_mut().map(Literal::make_regex);
+            self.author.as_mut().map(Literal::make_regex);
+            self.paths = mem::take(&mut self.paths)
+                .into_iter()
+                .map(|mut p| {
+                    p.make_regex();
+                    p
+                })
+                .collect();
             self.target.as_mut().map(Target::make_regex);
         }
+
+        for q in &mut self.exclude {
+            q.set_global_regex(value);
+        }
+    }
+
+    fn set_word(&mut self, value: Option<bool>) {
+        self.word = value;
+        for q in &mut self.exclude {
+            q.set_word(value);
+        }
+    }
+
+    /// Whether this query searches for a different target than `rhs`, in which case merging them
+    /// would drop one of the targets.
+    ///
+    /// Content targets never conflict, as they are joined into a single regex.
+    fn conflicts_with(&self, rhs: &Self) -> bool {
+        match (&self.target, &rhs.target) {
+            (Some(Target::Content(_)), Some(Target::Content(_))) => false,
+            (Some(_), Some(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A boolean expression, as written in a query string with `or`, negation and grouping.
+///
+/// Labels that are `and`ed together are merged into a single term where possible, so that
+/// `repo:bloop (foo or bar)` becomes an `And` of the `repo:bloop` term and an `Or` of two content
+/// terms.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expression<T> {
+    Term(T),
+    And(Vec<Expression<T>>),
+    Or(Vec<Expression<T>>),
+    Not(Box<Expression<T>>),
+}
+
+/// A parsed query string.
+pub type QueryExpr<'a> = Expression<Query<'a>>;
+
+impl<T> Expression<T> {
+    /// Join a list of expressions with `and`, flattening nested `And`s.
+    fn and(exprs: impl IntoIterator<Item = Self>) -> Self {
+        let mut list = Vec::new();
+        for expr in exprs {
+            match expr {
+                Self::And(exprs) => list.extend(exprs),
+                expr => list.push(expr),
+            }
+        }
+
+        if list.len() == 1 {
+            list.pop().unwrap()
+        } else {
+            Self::And(list)
+        }
+    }
+
+    /// Join a list of expressions with `or`, flattening nested `Or`s.
+    fn or(exprs: impl IntoIterator<Item = Self>) -> Self {
+        let mut list = Vec::new();
+        for expr in exprs {
+            match expr {
+                Self::Or(exprs) => list.extend(exprs),
+                expr => list.push(expr),
+            }
+        }
+
+        if list.len() == 1 {
+            list.pop().unwrap()
+        } else {
+            Self::Or(list)
+        }
+    }
+
+    /// Iterate over every term in this expression, in the order they were written.
+    pub fn terms(&self) -> impl Iterator<Item = &T> {
+        self.walk().map(|(_, term)| term)
+    }
+
+    /// Iterate over the terms that are not negated, in the order they were written.
+    ///
+    /// These are the terms that a matching document is expected to contain, such as the targets
+    /// to highlight.
+    pub fn positive_terms(&self) -> impl Iterator<Item = &T> {
+        self.walk()
+            .filter(|(negated, _)| !negated)
+            .map(|(_, term)| term)
+    }
+
+    /// Iterate over terms, along with whether they appear under an odd number of negations.
+    fn walk(&self) -> impl Iterator<Item = (bool, &T)> {
+        let mut stack = vec![(false, self)];
+
+        std::iter::from_fn(move || loop {
+            match stack.pop()? {
+                (negated, Self::Term(term)) => return Some((negated, term)),
+                (negated, Self::And(exprs) | Self::Or(exprs)) => {
+                    stack.extend(exprs.iter().rev().map(|e| (negated, e)))
+                }
+                (negated, Self::Not(expr)) => stack.push((!negated, expr)),
+            }
+        })
+    }
+
+    fn for_each_term_mut(&mut self, f: &mut impl FnMut(&mut T)) {
+        match self {
+            Self::Term(term) => f(term),
+            Self::And(exprs) | Self::Or(exprs) => {
+                exprs.iter_mut().for_each(|e| e.for_each_term_mut(f))
+            }
+            Self::Not(expr) => expr.for_each_term_mut(f),
+        }
+    }
+
+    /// Build an expression of the same shape, with every term mapped through `f`.
+    pub fn map<U>(&self, f: &mut impl FnMut(&T) -> U) -> Expression<U> {
+        match self {
+            Self::Term(term) => Expression::Term(f(term)),
+            Self::And(exprs) => Expression::And(exprs.iter().map(|e| e.map(f)).collect()),
+            Self::Or(exprs) => Expression::Or(exprs.iter().map(|e| e.map(f)).collect()),
+            Self::Not(expr) => Expression::Not(Box::new(expr.map(f))),
+        }
+    }
+
+    /// Evaluate this expression in three-valued logic, where `None` means a term's value is not
+    /// known, for example because it filters on a field that is not available.
+    pub fn evaluate(&self, f: &mut impl FnMut(&T) -> Option<bool>) -> Option<bool> {
+        match self {
+            Self::Term(term) => f(term),
+            Self::And(exprs) => {
+                let mut value = Some(true);
+                for e in exprs {
+                    match e.evaluate(f) {
+                        Some(false) => return Some(false),
+                        None => value = None,
+                        Some(true) => {}
+                    }
+                }
+                value
+            }
+            Self::Or(exprs) => {
+                let mut value = Some(false);
+                for e in exprs {
+                    match e.evaluate(f) {
+                        Some(true) => return Some(true),
+                        None => value = None,
+                        Some(false) => {}
+                    }
+                }
+                value
+            }
+            Self::Not(expr) => expr.evaluate(f).map(|v| !v),
+        }
+    }
+}
+
+impl<'a> Expression<Query<'a>> {
+    /// Merge the terms of this expression into a single query that is representative of it,
+    /// without expanding it into every combination of its `or` branches.
+    ///
+    /// The first branch of every `or` that satisfies `matches` is picked, falling back to the first
+    /// branch. Negated expressions end up as exclusions.
+    pub fn representative(&self, matches: &impl Fn(&Query<'a>) -> bool) -> Query<'a> {
+        match self {
+            Self::Term(query) => query.clone(),
+            Self::And(exprs) => exprs
+                .iter()
+                .map(|e| e.representative(matches))
+                .fold(Query::default(), Query::merge),
+            Self::Or(exprs) => {
+                let mut branches = exprs.iter().map(|e| e.representative(matches));
+                let first = branches.next().unwrap_or_default();
+
+                if matches(&first) {
+                    first
+                } else {
+                    branches.find(matches).unwrap_or(first)
+                }
+            }
+            Self::Not(expr) => Query {
+                exclude: vec![expr.representative(matches)],
+                ..Query::default()
+            },
+        }
+    }
+
+    /// Whether any way of satisfying this expression is a conjunction that satisfies `matches`.
+    pub fn matches(&self, matches: &impl Fn(&Query<'a>) -> bool) -> bool {
+        matches(&self.representative(matches))
+    }
+
+    /// Drop the branches of `or` expressions that don't satisfy `matches`.
+    ///
+    /// When no branch of an `or` satisfies `matches`, it is kept as a whole, as it is then a filter
+    /// on the other terms, such as `(repo:foo or repo:bar)` in `(repo:foo or repo:bar) baz`.
+    /// Negated expressions are always kept as a whole.
+    pub fn select(&self, matches: &impl Fn(&Query<'a>) -> bool) -> Self {
+        match self {
+            Self::Term(_) | Self::Not(_) => self.clone(),
+            Self::And(exprs) => Self::And(exprs.iter().map(|e| e.select(matches)).collect()),
+            Self::Or(exprs) => {
+                let selected = exprs
+                    .iter()
+                    .filter(|e| e.matches(matches))
+                    .map(|e| e.select(matches))
+                    .collect::<Vec<_>>();
+
+                if selected.is_empty() {
+                    self.clone()
+                } else {
+                    Self::or(selected)
+                }
+            }
+        }
+    }
+
+    /// Merge a term into a list of expressions that are `and`ed together.
+    ///
+    /// Terms without a target are merged into the first term, and terms with a target into the
+    /// last term that doesn't search for a conflicting target.
+    fn push_term(exprs: &mut Vec<Self>, query: Query<'a>) {
+        let position = if query.target.is_none() {
+            exprs.iter().position(|e| matches!(e, Self::Term(_)))
+        } else {
+            exprs
+                .iter()
+                .rposition(|e| matches!(e, Self::Term(q) if !q.conflicts_with(&query)))
+        };
+
+        match position.map(|i| &mut exprs[i]) {
+            Some(Self::Term(lhs)) => *lhs = mem::take(lhs).merge(query),
+            _ => exprs.push(Self::Term(query)),
+        }
     }
 }
 
@@ -187,6 +565,7 @@ impl<'a> Target<'a> {
         match self {
             Self::Symbol(lit) => lit,
             Self::Content(lit) => lit,
+            Self::Ast(lit) => lit,
         }
     }
 
@@ -194,22 +573,32 @@ impl<'a> Target<'a> {
     pub fn symbol(&self) -> Option<&Literal<'_>> {
         match self {
             Self::Symbol(lit) => Some(lit),
-            Self::Content(_) => None,
+            Self::Content(_) | Self::Ast(_) => None,
         }
     }
 
     /// Get the content literal, if present
     pub fn content(&self) -> Option<&Literal<'_>> {
         match self {
-            Self::Symbol(_) => None,
+            Self::Symbol(_) | Self::Ast(_) => None,
             Self::Content(lit) => Some(lit),
         }
     }
 
+    /// Get the structural search pattern, if present
+    pub fn ast(&self) -> Option<&Literal<'_>> {
+        match self {
+            Self::Ast(lit) => Some(lit),
+            Self::Symbol(_) | Self::Content(_) => None,
+        }
+    }
+
     fn make_regex(&mut self) {
         match self {
             Self::Symbol(lit) => lit.make_regex(),
             Self::Content(lit) => lit.make_regex(),
+            // Patterns are never regexes, but they can contain `#match?` predicates instead.
+            Self::Ast(_) => {}
         }
     }
 }
@@ -226,6 +615,12 @@ pub enum ParseError {
     UnparsedToken(String),
     #[error("multiple mode designators")]
     MultiMode,
+    #[error("invalid structural pattern: {0}")]
+    InvalidPattern(String),
+    #[error("unsupported negated label: {0:?}")]
+    UnsupportedNegation(String),
+    #[error("unknown kind of symbol: {0:?}")]
+    UnknownSymbolKind(String),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash, serde::Serialize, serde::Deserialize)]
@@ -288,6 +683,37 @@ impl<'a> Literal<'a> {
         }
     }
 
+    /// Whether matches of this literal can start and end with a word character, respectively.
+    ///
+    /// Regexes are checked through their literal prefixes and suffixes, and are assumed to start
+    /// or end with a word character when these are unknown.
+    fn word_edges(&self) -> (bool, bool) {
+        match self {
+            Self::Plain(text) => {
+                let is_word = |c: char| c.is_alphanumeric() || c == '_';
+                (text.starts_with(is_word), text.ends_with(is_word))
+            }
+            Self::Regex(regex) => {
+                let Ok(hir) = regex_syntax::Parser::new().parse(regex) else {
+                    return (true, true);
+                };
+
+                // non-ASCII bytes are part of a multi-byte character, which is most likely a
+                // letter
+                let is_word = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_' || !b.is_ascii();
+                let any_word = |lits: Literals, edge: fn(&[u8]) -> Option<&u8>| {
+                    let lits = lits.literals();
+                    lits.is_empty() || lits.iter().any(|l| edge(l).map_or(true, is_word))
+                };
+
+                (
+                    any_word(Literals::prefixes(&hir), <[u8]>::first),
+                    any_word(Literals::suffixes(&hir), <[u8]>::last),
+                )
+            }
+        }
+    }
+
     pub fn unwrap(self) -> Cow<'a, str> {
         match self {
             Literal::Plain(v) => v,
@@ -359,6 +785,7 @@ fn unescape(s: &str, term: char) -> String {
 enum ForceParsingAs {
     Grep,
     Semantic,
+    Hybrid,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -369,14 +796,32 @@ enum Expr<'a> {
     Org(Literal<'a>),
     Repo(Literal<'a>),
     Symbol(Literal<'a>),
+    SymbolKind(Cow<'a, str>),
     Path(Literal<'a>),
     Lang(Cow<'a, str>),
     Content(Literal<'a>),
     Branch(Literal<'a>),
+    Ast(Literal<'a>),
+    Author(Literal<'a>),
+    Modified(Range<u64>),
+    Size(Range<u64>),
+    Lines(Range<u64>),
+    IsDir(bool),
+    Class(FileClass),
+    Extension(Cow<'a, str>),
+
+    /// A negated label, excluding any results that match it.
+    Not(Box<Expr<'a>>),
+
+    /// A negated group, such as `-(foo or bar)`, excluding any results that match the group as a
+    /// whole.
+    NotGroup(Box<Expr<'a>>),
 
     CaseSensitive(bool),
     Open(bool),
     GlobalRegex(bool),
+    Word(bool),
+    Fuzzy(bool),
 
     /// This is only parsed so we it doesn't mix with the actual query
     /// Not actively used anywhere.
@@ -396,10 +841,97 @@ impl<'a> Expr<'a> {
             Rule::content => Content(Literal::from(pair.into_inner().next().unwrap())),
             Rule::path => Path(Literal::from(pair.into_inner().next().unwrap())),
             Rule::repo => Repo(Literal::from(pair.into_inner().next().unwrap())),
-            Rule::symbol => Symbol(Literal::from(pair.into_inner().next().unwrap())),
+            Rule::symbol => {
+                let mut inner = pair.into_inner();
+                let first = inner.next().unwrap();
+
+                match inner.next() {
+                    // `symbol:function:parse`
+                    Some(lit) if is_symbol_kind(first.as_str()) => And(vec![
+                        SymbolKind(first.as_str().into()),
+                        Symbol(Literal::from(lit)),
+                    ]),
+
+                    // Not a kind we know of, so this is part of the symbol, as in `symbol:std::io`
+                    Some(lit) => {
+                        let prefix = first.as_str();
+                        Symbol(match Literal::from(lit) {
+                            Literal::Plain(s) => Literal::Plain(format!("{prefix}:{s}").into()),
+                            Literal::Regex(s) => {
+                                Literal::Regex(format!("{}:{s}", regex::escape(prefix)).into())
+                            }
+                        })
+                    }
+
+                    None => Symbol(Literal::from(first)),
+                }
+            }
+            Rule::kind => {
+                let kind = pair.clone().into_inner().as_str();
+                if !is_symbol_kind(kind) {
+                    return Err(pair);
+                }
+
+                SymbolKind(kind.into())
+            }
             Rule::org => Org(Literal::from(pair.into_inner().next().unwrap())),
             Rule::branch => Branch(Literal::from(pair.into_inner().next().unwrap())),
             Rule::lang => Lang(pair.into_inner().as_str().into()),
+            Rule::ast => Ast(Literal::from(pair.into_inner().next().unwrap())),
+            Rule::author => Author(Literal::from(pair.into_inner().next().unwrap())),
+            Rule::modified => {
+                let mut inner = pair.clone().into_inner();
+                let comparison = inner.next().unwrap().as_str();
+
+                let Some(day) = parse_date(inner.next().unwrap().as_str()) else {
+                    return Err(pair);
+                };
+
+                Modified(comparison_range(comparison, day..day + 24 * 60 * 60))
+            }
+            Rule::size => {
+                let mut inner = pair.clone().into_inner();
+                let comparison = inner.next().unwrap().as_str();
+
+                let Some(size) = parse_size(inner.next().unwrap().as_str()) else {
+                    return Err(pair);
+                };
+
+                Size(comparison_range(comparison, size..size.saturating_add(1)))
+            }
+            Rule::lines => {
+                let mut inner = pair.clone().into_inner();
+                let comparison = inner.next().unwrap().as_str();
+
+                let Ok(lines) = inner.next().unwrap().as_str().parse::<u64>() else {
+                    return Err(pair);
+                };
+
+                Lines(comparison_range(comparison, lines..lines.saturating_add(1)))
+            }
+            Rule::is => match pair.clone().into_inner().as_str() {
+                "dir" | "directory" => IsDir(true),
+                "file" => IsDir(false),
+                class => match FileClass::parse(class) {
+                    Some(class) => Class(class),
+                    None => return Err(pair),
+                },
+            },
+            Rule::ext => Extension(
+                pair.into_inner()
+                    .as_str()
+                    .trim_start_matches('.')
+                    .to_lowercase()
+                    .into(),
+            ),
+            Rule::negation => Not(Box::new(Self::parse(
+                pair.into_inner().next().unwrap(),
+                top_level,
+            )?)),
+            Rule::negated_group => NotGroup(Box::new(Self::parse(
+                pair.into_inner().next().unwrap(),
+                top_level,
+            )?)),
 
             Rule::open => {
                 let inner = pair.into_inner().next().unwrap();
@@ -438,6 +970,34 @@ impl<'a> Expr<'a> {
                 }
             }
 
+            Rule::word => {
+                // Avoid parsing this flag unless it's at the top level.
+                if !top_level {
+                    return Err(pair);
+                }
+
+                let inner = pair.into_inner().next().unwrap();
+                match inner.as_str() {
+                    "true" => Word(true),
+                    "false" => Word(false),
+                    _ => unreachable!(),
+                }
+            }
+
+            Rule::fuzzy => {
+                // Avoid parsing this flag unless it's at the top level.
+                if !top_level {
+                    return Err(pair);
+                }
+
+                let inner = pair.into_inner().next().unwrap();
+                match inner.as_str() {
+                    "true" => Fuzzy(true),
+                    "false" => Fuzzy(false),
+                    _ => unreachable!(),
+                }
+            }
+
             Rule::mode_selector => {
                 // Avoid parsing this flag unless it's at the top level.
                 if !top_level {
@@ -448,6 +1008,7 @@ impl<'a> Expr<'a> {
                 match inner.as_str() {
                     "grep" => GlobalMode(ForceParsingAs::Grep),
                     "semantic" => GlobalMode(ForceParsingAs::Semantic),
+                    "hybrid" => GlobalMode(ForceParsingAs::Hybrid),
                     _ => unreachable!(),
                 }
             }
@@ -487,27 +1048,112 @@ impl<'a> Expr<'a> {
     }
 }
 
-/// Parse an input query string into a list of top-level `Query`s.
-pub fn parse(query: &str) -> Result<Vec<Query<'_>>, ParseError> {
+/// Parse a `YYYY-MM-DD` date into the unix timestamp of its first second, in UTC.
+fn parse_date(date: &str) -> Option<u64> {
+    let timestamp = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()?
+        .and_hms_opt(0, 0, 0)?
+        .timestamp();
+
+    u64::try_from(timestamp).ok()
+}
+
+/// Parse a size such as `100k` or `2mb` into a number of bytes.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.to_lowercase();
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier: u64 = match &size[digits.len()..] {
+        "" | "b" => 1,
+        "k" | "kb" => 1 << 10,
+        "m" | "mb" => 1 << 20,
+        "g" | "gb" => 1 << 30,
+        _ => return None,
+    };
+
+    digits.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Whether any supported language has a kind of symbol with this name, such as `function`.
+fn is_symbol_kind(kind: &str) -> bool {
+    use crate::intelligence::{NameSpaceMethods, ALL_LANGUAGES};
+
+    let kind = kind.to_lowercase();
+    ALL_LANGUAGES
+        .iter()
+        .any(|lang| lang.namespaces.all_symbols().contains(&kind.as_str()))
+}
+
+/// Get the range of values matching a comparison operator, given the range covered by the value
+/// being compared against.
+///
+/// For example, `>2024-01-01` matches anything from the end of that day onwards.
+fn comparison_range(comparison: &str, value: Range<u64>) -> Range<u64> {
+    match comparison {
+        ">" => value.end..u64::MAX,
+        ">=" => value.start..u64::MAX,
+        "<" => 0..value.start,
+        "<=" => 0..value.end,
+        _ => value,
+    }
+}
+
+/// Parse an input query string into a boolean expression over `Query`s.
+pub fn parse(query: &str) -> Result<QueryExpr<'_>, ParseError> {
     let pair = PestParser::parse(Rule::query, query)
         .map_err(Box::new)?
         .next()
         .unwrap();
-    let root =
-        Expr::parse(pair, true).map_err(|pair| ParseError::UnparsedToken(pair.to_string()))?;
+    let root = Expr::parse(pair, true).map_err(|pair| match pair.as_rule() {
+        Rule::kind => ParseError::UnknownSymbolKind(pair.into_inner().as_str().into()),
+        _ => ParseError::UnparsedToken(pair.to_string()),
+    })?;
 
-    let mut qs = flatten(root);
+    let mut expr = lower(root);
 
     // Find and redistribute global options.
-    let global_regex = qs.iter().fold(None, |a, e| e.global_regex.or(a));
-    let case_sensitive = qs.iter().fold(None, |a, e| e.case_sensitive.or(a));
+    let global_regex = expr.terms().fold(None, |a, e| e.global_regex.or(a));
+    let case_sensitive = expr.terms().fold(None, |a, e| e.case_sensitive.or(a));
+    let word = expr.terms().fold(None, |a, e| e.word.or(a));
+    let fuzzy = expr.terms().fold(None, |a, e| e.fuzzy.or(a));
 
-    for q in qs.iter_mut() {
+    expr.for_each_term_mut(&mut |q| {
         q.set_global_regex(global_regex);
+        q.set_word(word);
         q.case_sensitive = case_sensitive;
+        q.fuzzy = fuzzy;
+    });
+
+    Ok(expr)
+}
+
+/// The byte ranges of the literals of the content and symbol targets that are not negated in a
+/// query string, in the order they are written.
+///
+/// Quoted literals exclude their quotes, so that a target can be edited in place.
+pub fn target_spans(query: &str) -> Result<Vec<Range<usize>>, ParseError> {
+    fn walk(pair: Pair<'_, Rule>, parent: Option<Rule>, spans: &mut Vec<Range<usize>>) {
+        match pair.as_rule() {
+            Rule::negation | Rule::negated_group => {}
+            Rule::unquoted_literal | Rule::quoted_literal | Rule::single_quoted_literal
+                if matches!(parent, Some(Rule::element | Rule::content | Rule::symbol)) =>
+            {
+                let span = pair.as_span();
+                spans.push(span.start()..span.end());
+            }
+            rule => {
+                for inner in pair.into_inner() {
+                    walk(inner, Some(rule), spans);
+                }
+            }
+        }
+    }
+
+    let mut spans = Vec::new();
+    for pair in PestParser::parse(Rule::query, query).map_err(Box::new)? {
+        walk(pair, None, &mut spans);
     }
 
-    Ok(qs.into_vec())
+    Ok(spans)
 }
 
 pub fn parse_nl(query: &str) -> Result<ParsedQuery<'_>, ParseError> {
@@ -517,6 +1163,10 @@ pub fn parse_nl(query: &str) -> Result<ParsedQuery<'_>, ParseError> {
     let mut paths = HashSet::new();
     let mut langs = HashSet::new();
     let mut branch = HashSet::new();
+    let mut excluded_repos = HashSet::new();
+    let mut excluded_paths = HashSet::new();
+    let mut excluded_langs = HashSet::new();
+    let mut excluded_branches = HashSet::new();
     let mut target: Option<Literal> = None;
     let mut force_parsing_as = None;
     for pair in pairs {
@@ -537,6 +1187,30 @@ pub fn parse_nl(query: &str) -> Result<ParsedQuery<'_>, ParseError> {
                 let item = super::languages::parse_alias(pair.into_inner().as_str().into());
                 let _ = langs.insert(item);
             }
+            Rule::negation => {
+                let label = pair.into_inner().next().unwrap();
+                match label.as_rule() {
+                    Rule::repo => {
+                        let item = Literal::from(label.into_inner().next().unwrap());
+                        let _ = excluded_repos.insert(item);
+                    }
+                    Rule::path => {
+                        let item = Literal::from(label.into_inner().next().unwrap());
+                        let _ = excluded_paths.insert(item);
+                    }
+                    Rule::branch => {
+                        let item = Literal::from(label.into_inner().next().unwrap());
+                        let _ = excluded_branches.insert(item);
+                    }
+                    Rule::lang => {
+                        let item =
+                            super::languages::parse_alias(label.into_inner().as_str().into());
+                        let _ = excluded_langs.insert(item);
+                    }
+                    // semantic searches can only exclude repos, paths, languages and branches
+                    _ => return Err(ParseError::UnsupportedNegation(label.as_str().into())),
+                }
+            }
             Rule::raw_text => {
                 let rhs = Literal::from(pair);
                 if let Some(t) = target {
@@ -554,6 +1228,9 @@ pub fn parse_nl(query: &str) -> Result<ParsedQuery<'_>, ParseError> {
                     "semantic" if force_parsing_as.is_none() => {
                         force_parsing_as = Some(ForceParsingAs::Semantic);
                     }
+                    "hybrid" if force_parsing_as.is_none() => {
+                        force_parsing_as = Some(ForceParsingAs::Hybrid);
+                    }
                     _ => return Err(ParseError::MultiMode),
                 };
             }
@@ -561,81 +1238,156 @@ pub fn parse_nl(query: &str) -> Result<ParsedQuery<'_>, ParseError> {
         }
     }
 
+    let semantic = SemanticQuery {
+        repos,
+        paths,
+        langs,
+        branch,
+        target,
+        excluded_repos,
+        excluded_paths,
+        excluded_langs,
+        excluded_branches,
+    };
+
     match force_parsing_as {
         Some(ForceParsingAs::Grep) => parse(query).map(ParsedQuery::Grep),
-        _ => Ok(ParsedQuery::Semantic(SemanticQuery {
-            repos,
-            paths,
-            langs,
-            branch,
-            target,
-        })),
+        Some(ForceParsingAs::Hybrid) => Ok(ParsedQuery::Hybrid(semantic)),
+        _ => Ok(ParsedQuery::Semantic(semantic)),
     }
 }
 
-fn flatten(root: Expr<'_>) -> SmallVec<[Query<'_>; 1]> {
-    match root {
-        Expr::Repo(repo) => smallvec![Query {
+/// Lower a parsed expression into a boolean expression over queries.
+///
+/// Labels that are `and`ed together are merged into single terms, see
+/// [`Expression::push_term`].
+fn lower(root: Expr<'_>) -> QueryExpr<'_> {
+    Expression::Term(match root {
+        Expr::Repo(repo) => Query {
             repo: Some(repo),
             ..Default::default()
-        }],
-        Expr::Branch(branch) => smallvec![Query {
-            branch: Some(branch),
+        },
+        Expr::Branch(branch) => Query {
+            branches: [branch].into(),
             ..Default::default()
-        }],
-        Expr::Org(org) => smallvec![Query {
+        },
+        Expr::Org(org) => Query {
             org: Some(org),
             ..Default::default()
-        }],
-        Expr::Path(path) => smallvec![Query {
-            path: Some(path),
+        },
+        Expr::Path(path) => Query {
+            paths: [path].into(),
+            ..Default::default()
+        },
+        Expr::Author(author) => Query {
+            author: Some(author),
+            ..Default::default()
+        },
+        Expr::Modified(range) => Query {
+            modified: Some(range),
+            ..Default::default()
+        },
+        Expr::Size(range) => Query {
+            size: Some(range),
+            ..Default::default()
+        },
+        Expr::Lines(range) => Query {
+            lines: Some(range),
             ..Default::default()
-        }],
+        },
+        Expr::IsDir(is_dir) => Query {
+            is_dir: Some(is_dir),
+            ..Default::default()
+        },
+        Expr::Class(class) => Query {
+            classes: [class].into(),
+            ..Default::default()
+        },
+        Expr::Extension(ext) => Query {
+            extensions: [ext].into(),
+            ..Default::default()
+        },
 
-        Expr::Symbol(sym) => smallvec![Query {
+        Expr::Symbol(sym) => Query {
             target: Some(Target::Symbol(sym)),
             ..Default::default()
-        }],
-        Expr::Lang(lang) => smallvec![Query {
-            lang: Some(super::languages::parse_alias(lang)),
+        },
+        Expr::SymbolKind(kind) => Query {
+            symbol_kinds: [kind.to_lowercase().into()].into(),
+            ..Default::default()
+        },
+        Expr::Lang(lang) => Query {
+            langs: [super::languages::parse_alias(lang)].into(),
             ..Default::default()
-        }],
-        Expr::Content(lit) => smallvec![Query {
+        },
+        Expr::Content(lit) => Query {
             target: Some(Target::Content(lit)),
             ..Default::default()
-        }],
+        },
+        Expr::Ast(pattern) => Query {
+            target: Some(Target::Ast(pattern)),
+            ..Default::default()
+        },
 
-        Expr::CaseSensitive(case_sensitive) => smallvec![Query {
+        Expr::CaseSensitive(case_sensitive) => Query {
             case_sensitive: Some(case_sensitive),
             ..Default::default()
-        }],
-        Expr::Open(open) => smallvec![Query {
+        },
+        Expr::Open(open) => Query {
             open: Some(open),
             ..Default::default()
-        }],
-        Expr::GlobalRegex(flag) => smallvec![Query {
+        },
+        Expr::GlobalRegex(flag) => Query {
             global_regex: Some(flag),
             ..Default::default()
-        }],
-        Expr::GlobalMode(_) => smallvec![Query {
+        },
+        Expr::Word(flag) => Query {
+            word: Some(flag),
+            ..Default::default()
+        },
+        Expr::Fuzzy(flag) => Query {
+            fuzzy: Some(flag),
+            ..Default::default()
+        },
+        Expr::GlobalMode(_) => Query {
             // we don't propagate this flag down to the query level!
             ..Default::default()
-        }],
-
-        // Simple merge
-        Expr::Or(exprs) => {
-            let mut queries = smallvec![];
-            for e in exprs {
-                queries.extend(flatten(e));
+        },
+
+        Expr::Not(expr) => {
+            return match lower(*expr) {
+                Expression::Term(q) => Expression::Term(Query {
+                    exclude: vec![q],
+                    ..Default::default()
+                }),
+                expr => Expression::Not(Box::new(expr)),
             }
-            queries
         }
+        Expr::NotGroup(expr) => return Expression::Not(Box::new(lower(*expr))),
+
+        Expr::Or(exprs) => return Expression::or(exprs.into_iter().map(lower)),
 
-        // A more complex cross merge.
         Expr::And(els) => {
-            Query::default().cross(els.iter().cloned().map(flatten).map(SmallVec::into_iter))
+            let mut exprs = Vec::new();
+
+            for expr in els.into_iter().map(lower) {
+                match expr {
+                    Expression::Term(q) => QueryExpr::push_term(&mut exprs, q),
+                    Expression::And(children) => {
+                        for child in children {
+                            match child {
+                                Expression::Term(q) => QueryExpr::push_term(&mut exprs, q),
+                                child => exprs.push(child),
+                            }
+                        }
+                    }
+                    expr => exprs.push(expr),
+                }
+            }
+
+            return Expression::and(exprs);
         }
-    }
+    })
 }
 
 #[cfg(test)]
@@ -647,58 +1399,64 @@ mod tests {
     fn basic_parse() {
         assert_eq!(
             parse("ParseError").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         assert_eq!(
             parse("org:bloopai repo:enterprise-search branch:origin/main ParseError").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 repo: Some(Literal::Plain("enterprise-search".into())),
                 org: Some(Literal::Plain("bloopai".into())),
-                branch: Some(Literal::Plain("origin/main".into())),
+                branches: [Literal::Plain("origin/main".into())].into(),
                 target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         assert_eq!(
             parse("org:bloopai repo:enterprise-search ParseError").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 repo: Some(Literal::Plain("enterprise-search".into())),
                 org: Some(Literal::Plain("bloopai".into())),
                 target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         assert_eq!(
             parse("content:ParseError").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
-        // Here the last target operator takes precedence. Should we return an error instead?
+        // Different targets are kept as separate terms, both of which must match.
         assert_eq!(
             parse("path:foo.c create_foo symbol:bar").unwrap(),
-            vec![Query {
-                path: Some(Literal::Plain("foo.c".into())),
-                target: Some(Target::Symbol(Literal::Plain("bar".into()))),
-                ..Query::default()
-            }],
+            Expression::And(vec![
+                Expression::Term(Query {
+                    paths: [Literal::Plain("foo.c".into())].into(),
+                    target: Some(Target::Content(Literal::Plain("create_foo".into()))),
+                    ..Query::default()
+                }),
+                Expression::Term(Query {
+                    target: Some(Target::Symbol(Literal::Plain("bar".into()))),
+                    ..Query::default()
+                }),
+            ]),
         );
 
         assert_eq!(
             parse("case:ignore Parse").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 case_sensitive: Some(false),
                 target: Some(Target::Content(Literal::Plain("Parse".into()))),
                 ..Query::default()
-            }],
+            }),
         );
     }
 
@@ -706,47 +1464,47 @@ mod tests {
     fn test_force_parsing_mode_from_language() {
         assert_eq!(
             parse("repo:foo ParseError or repo:bar mode:grep").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("foo".into())),
                     target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("bar".into())),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
 
         assert_eq!(
             parse_nl("repo:foo ParseError or repo:bar mode:grep"),
-            Ok(ParsedQuery::Grep(vec![
-                Query {
+            Ok(ParsedQuery::Grep(Expression::Or(vec![
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("foo".into())),
                     target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("bar".into())),
                     ..Query::default()
-                },
-            ])),
+                }),
+            ]))),
         );
 
         assert_eq!(
             parse("repo:foo ParseError or repo:bar").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("foo".into())),
                     target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("bar".into())),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
 
         assert_eq!(
@@ -758,39 +1516,53 @@ mod tests {
             parse_nl("repo:bar or repo:foo ParseError mode:semantic mode:grep"),
             Err(ParseError::MultiMode)
         );
+
+        assert_eq!(
+            parse_nl("repo:bar ParseError mode:hybrid"),
+            Ok(ParsedQuery::Hybrid(SemanticQuery {
+                repos: [Literal::Plain("bar".into())].into(),
+                target: Some(Literal::Plain("ParseError".into())),
+                ..Default::default()
+            }))
+        );
+
+        assert_eq!(
+            parse_nl("ParseError mode:hybrid mode:grep"),
+            Err(ParseError::MultiMode)
+        );
     }
 
     #[test]
     fn intersection_parse() {
         assert_eq!(
             parse("repo:foo ParseError or repo:bar").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("foo".into())),
                     target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("bar".into())),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
 
         // Flip the intersection order.
         assert_eq!(
             parse("repo:bar or repo:foo ParseError").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("bar".into())),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("foo".into())),
                     target: Some(Target::Content(Literal::Plain("ParseError".into()))),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
     }
 
@@ -798,14 +1570,9 @@ mod tests {
     fn complex_nested_combinators_expr() {
         // (((repo:foo xyz) or repo:abc) (repo:fred or repo:grub) org:bloop)
         //
-        // -> 4 independent terms [
-        //    (org:bloop repo:fred xyz),
-        //    (org:bloop repo:grub xyz),
-        //    (org:bloop repo:fred),
-        //    (org:bloop repo:grub),
-        // ]
-
-        let terms = flatten(Expr::And(vec![
+        // -> groups are kept as they are written, without expanding them into every combination
+
+        let expr = lower(Expr::And(vec![
             Expr::Or(vec![
                 Expr::And(vec![
                     Expr::Repo(Literal::Plain("foo".into())),
@@ -821,31 +1588,34 @@ mod tests {
         ]));
 
         assert_eq!(
-            terms.into_vec(),
-            vec![
-                Query {
-                    repo: Some(Literal::Plain("fred".into())),
-                    org: Some(Literal::Plain("bloop".into())),
-                    target: Some(Target::Content(Literal::Plain("xyz".into()))),
-                    ..Query::default()
-                },
-                Query {
-                    repo: Some(Literal::Plain("grub".into())),
-                    org: Some(Literal::Plain("bloop".into())),
-                    target: Some(Target::Content(Literal::Plain("xyz".into()))),
-                    ..Query::default()
-                },
-                Query {
-                    repo: Some(Literal::Plain("fred".into())),
-                    org: Some(Literal::Plain("bloop".into())),
-                    ..Query::default()
-                },
-                Query {
-                    repo: Some(Literal::Plain("grub".into())),
+            expr,
+            Expression::And(vec![
+                Expression::Or(vec![
+                    Expression::Term(Query {
+                        repo: Some(Literal::Plain("foo".into())),
+                        target: Some(Target::Content(Literal::Plain("xyz".into()))),
+                        ..Query::default()
+                    }),
+                    Expression::Term(Query {
+                        repo: Some(Literal::Plain("abc".into())),
+                        ..Query::default()
+                    }),
+                ]),
+                Expression::Or(vec![
+                    Expression::Term(Query {
+                        repo: Some(Literal::Plain("fred".into())),
+                        ..Query::default()
+                    }),
+                    Expression::Term(Query {
+                        repo: Some(Literal::Plain("grub".into())),
+                        ..Query::default()
+                    }),
+                ]),
+                Expression::Term(Query {
                     org: Some(Literal::Plain("bloop".into())),
                     ..Query::default()
-                },
-            ]
+                }),
+            ])
         );
     }
 
@@ -853,30 +1623,20 @@ mod tests {
     fn complex_nested_combinators_parse() {
         assert_eq!(
             parse("(((repo:foo xyz) or repo:abc) (repo:fred or repo:grub) org:bloop)").unwrap(),
-            vec![
-                Query {
-                    repo: Some(Literal::Plain("fred".into())),
-                    org: Some(Literal::Plain("bloop".into())),
-                    target: Some(Target::Content(Literal::Plain("xyz".into()))),
-                    ..Query::default()
-                },
-                Query {
-                    repo: Some(Literal::Plain("grub".into())),
-                    org: Some(Literal::Plain("bloop".into())),
-                    target: Some(Target::Content(Literal::Plain("xyz".into()))),
-                    ..Query::default()
-                },
-                Query {
-                    repo: Some(Literal::Plain("fred".into())),
-                    org: Some(Literal::Plain("bloop".into())),
-                    ..Query::default()
-                },
-                Query {
-                    repo: Some(Literal::Plain("grub".into())),
-                    org: Some(Literal::Plain("bloop".into())),
-                    ..Query::default()
-                },
-            ],
+            lower(Expr::And(vec![
+                Expr::Or(vec![
+                    Expr::And(vec![
+                        Expr::Repo(Literal::Plain("foo".into())),
+                        Expr::Content(Literal::Plain("xyz".into())),
+                    ]),
+                    Expr::Repo(Literal::Plain("abc".into())),
+                ]),
+                Expr::Or(vec![
+                    Expr::Repo(Literal::Plain("fred".into())),
+                    Expr::Repo(Literal::Plain("grub".into())),
+                ]),
+                Expr::Org(Literal::Plain("bloop".into())),
+            ])),
         );
     }
 
@@ -884,43 +1644,107 @@ mod tests {
     fn complex_multiple_parse_types() {
         assert_eq!(
             parse("(repo:bloop or repo:google) Parser or repo:zoekt Parsing or (symbol:Compiler or (org:bloop repo:enterprise-search))").unwrap(),
-            vec![
-                Query {
-                    repo: Some(Literal::Plain("bloop".into())),
-                    target: Some(Target::Content(Literal::Plain("Parser".into()))),
-                    ..Query::default()
-                },
-                Query {
-                    repo: Some(Literal::Plain("google".into())),
-                    target: Some(Target::Content(Literal::Plain("Parser".into()))),
-                    ..Query::default()
-                },
-                Query {
+            Expression::Or(vec![
+                Expression::And(vec![
+                    Expression::Or(vec![
+                        Expression::Term(Query {
+                            repo: Some(Literal::Plain("bloop".into())),
+                            ..Query::default()
+                        }),
+                        Expression::Term(Query {
+                            repo: Some(Literal::Plain("google".into())),
+                            ..Query::default()
+                        }),
+                    ]),
+                    Expression::Term(Query {
+                        target: Some(Target::Content(Literal::Plain("Parser".into()))),
+                        ..Query::default()
+                    }),
+                ]),
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("zoekt".into())),
                     target: Some(Target::Content(Literal::Plain("Parsing".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     target: Some(Target::Symbol(Literal::Plain("Compiler".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     repo: Some(Literal::Plain("enterprise-search".into())),
                     org: Some(Literal::Plain("bloop".into())),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
+        );
+    }
+
+    #[test]
+    fn representative_and_select() {
+        let expr = parse("path:server (foo or lang:rust)").unwrap();
+        let content = |q: &Query<'_>| q.target.is_some();
+        let file = |q: &Query<'_>| q.target.is_none() && !q.langs.is_empty();
+
+        assert!(expr.matches(&content));
+        assert!(expr.matches(&file));
+        assert!(!expr.matches(&|q: &Query<'_>| q.repo.is_some()));
+
+        assert_eq!(
+            expr.select(&content),
+            Expression::And(vec![
+                Expression::Term(Query {
+                    paths: [Literal::Plain("server".into())].into(),
+                    ..Query::default()
+                }),
+                Expression::Term(Query {
+                    target: Some(Target::Content(Literal::Plain("foo".into()))),
+                    ..Query::default()
+                }),
+            ]),
+        );
+
+        assert_eq!(
+            expr.select(&file),
+            Expression::And(vec![
+                Expression::Term(Query {
+                    paths: [Literal::Plain("server".into())].into(),
+                    ..Query::default()
+                }),
+                Expression::Term(Query {
+                    langs: ["rust".into()].into(),
+                    ..Query::default()
+                }),
+            ]),
         );
+
+        // branches that are only filters are kept as a whole
+        let expr = parse("(repo:foo or repo:bar) baz").unwrap();
+        assert_eq!(expr.select(&content), expr);
+    }
+
+    #[test]
+    fn evaluate_three_valued() {
+        let expr = parse("(foo or bar) -(baz qux)").unwrap();
+        let eval = |known: &[&str]| {
+            expr.evaluate(&mut |q| match &q.target {
+                Some(Target::Content(lit)) => Some(known.contains(&lit.regex_str().as_ref())),
+                _ => None,
+            })
+        };
+
+        assert_eq!(eval(&["foo"]), Some(true));
+        assert_eq!(eval(&["bar", "baz\\s+qux"]), Some(false));
+        assert_eq!(eval(&[]), Some(false));
     }
 
     #[test]
     fn slash_in_path() {
         assert_eq!(
             parse("path:foo/bar.js").unwrap(),
-            vec![Query {
-                path: Some(Literal::Plain("foo/bar.js".into())),
+            Expression::Term(Query {
+                paths: [Literal::Plain("foo/bar.js".into())].into(),
                 ..Query::default()
-            }],
+            }),
         );
     }
 
@@ -941,11 +1765,39 @@ mod tests {
     fn lang_path_filter() {
         assert_eq!(
             parse("lang:Rust path:server").unwrap(),
-            vec![Query {
-                path: Some(Literal::Plain("server".into())),
-                lang: Some("rust".into()),
+            Expression::Term(Query {
+                paths: [Literal::Plain("server".into())].into(),
+                langs: ["rust".into()].into(),
+                ..Query::default()
+            }),
+        );
+    }
+
+    #[test]
+    fn multiple_filters() {
+        assert_eq!(
+            parse("lang:rust lang:go branch:main branch:dev path:server path:client foo").unwrap(),
+            Expression::Term(Query {
+                langs: ["rust".into(), "go".into()].into(),
+                branches: [Literal::Plain("main".into()), Literal::Plain("dev".into())].into(),
+                paths: [
+                    Literal::Plain("server".into()),
+                    Literal::Plain("client".into())
+                ]
+                .into(),
+                target: Some(Target::Content(Literal::Plain("foo".into()))),
                 ..Query::default()
-            }],
+            }),
+        );
+
+        // Duplicate filters are collapsed.
+        assert_eq!(
+            parse("lang:rs lang:rust foo").unwrap(),
+            Expression::Term(Query {
+                langs: ["rust".into()].into(),
+                target: Some(Target::Content(Literal::Plain("foo".into()))),
+                ..Query::default()
+            }),
         );
     }
 
@@ -953,29 +1805,29 @@ mod tests {
     fn enable_open() {
         assert_eq!(
             parse("open:true path:server/bleep/Cargo.toml").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 open: Some(true),
-                path: Some(Literal::Plain("server/bleep/Cargo.toml".into())),
+                paths: [Literal::Plain("server/bleep/Cargo.toml".into())].into(),
                 ..Query::default()
-            }],
+            }),
         );
 
         assert_eq!(
             parse("open:false path:server/bleep/Cargo.toml").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 open: Some(false),
-                path: Some(Literal::Plain("server/bleep/Cargo.toml".into())),
+                paths: [Literal::Plain("server/bleep/Cargo.toml".into())].into(),
                 ..Query::default()
-            }],
+            }),
         );
 
         assert_eq!(
             parse("path:server/bleep/Cargo.toml").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 open: None,
-                path: Some(Literal::Plain("server/bleep/Cargo.toml".into())),
+                paths: [Literal::Plain("server/bleep/Cargo.toml".into())].into(),
                 ..Query::default()
-            }],
+            }),
         );
     }
 
@@ -983,20 +1835,20 @@ mod tests {
     fn special_chars() {
         assert_eq!(
             parse("foo\\nbar\\tquux").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Plain("foo\\nbar\\tquux".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         assert_eq!(
             parse("/^\\b\\B\\w\\Wfoo\\d\\D$/").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Regex(
                     "^\\b\\B\\w\\Wfoo\\d\\D$".into()
                 ))),
                 ..Query::default()
-            }],
+            }),
         );
     }
 
@@ -1004,30 +1856,30 @@ mod tests {
     fn test_global_regex() {
         assert_eq!(
             parse("global_regex:true foo").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 global_regex: Some(true),
                 target: Some(Target::Content(Literal::Regex("foo".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         // Don't conflict with per-term regexes.
         assert_eq!(
             parse("global_regex:true /foo/").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 global_regex: Some(true),
                 target: Some(Target::Content(Literal::Regex("foo".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         // Lack of the flag should result in a `None` value.
         assert_eq!(
             parse("foo").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Plain("foo".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         // Can only apply this flag at the top-level, not inside groups.
@@ -1036,60 +1888,167 @@ mod tests {
         // Later uses at the top-level override previous uses.
         assert_eq!(
             parse("global_regex:false org:bloopai repo:bloop path:server foo or repo:google bar global_regex:true").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     global_regex: Some(true),
                     org: Some(Literal::Regex("bloopai".into())),
                     repo: Some(Literal::Regex("bloop".into())),
-                    path: Some(Literal::Regex("server".into())),
+                    paths: [Literal::Regex("server".into())].into(),
                     target: Some(Target::Content(Literal::Regex("foo".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     global_regex: Some(true),
                     repo: Some(Literal::Regex("google".into())),
                     target: Some(Target::Content(Literal::Regex("bar".into()))),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
 
         // Make sure that later values of `false` override previous values of `true`.
         assert_eq!(
             parse("global_regex:true foo or bar global_regex:false").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     global_regex: Some(false),
                     target: Some(Target::Content(Literal::Plain("foo".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     global_regex: Some(false),
                     target: Some(Target::Content(Literal::Plain("bar".into()))),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
     }
 
+    #[test]
+    fn test_word() {
+        assert_eq!(
+            parse("word:true repo:bloop id").unwrap(),
+            Expression::Term(Query {
+                word: Some(true),
+                repo: Some(Literal::Plain("bloop".into())),
+                target: Some(Target::Content(Literal::Plain("id".into()))),
+                ..Query::default()
+            }),
+        );
+
+        // Targets are kept as-is, and the flag reaches exclusions too.
+        assert_eq!(
+            parse("symbol:a.b or /fo+|bar/ -content:baz word:true").unwrap(),
+            Expression::Or(vec![
+                Expression::Term(Query {
+                    word: Some(true),
+                    target: Some(Target::Symbol(Literal::Plain("a.b".into()))),
+                    ..Query::default()
+                }),
+                Expression::Term(Query {
+                    word: Some(true),
+                    target: Some(Target::Content(Literal::Regex("fo+|bar".into()))),
+                    exclude: vec![Query {
+                        word: Some(true),
+                        target: Some(Target::Content(Literal::Plain("baz".into()))),
+                        ..Query::default()
+                    }],
+                    ..Query::default()
+                }),
+            ]),
+        );
+
+        assert_eq!(
+            parse("word:false id").unwrap(),
+            Expression::Term(Query {
+                word: Some(false),
+                target: Some(Target::Content(Literal::Plain("id".into()))),
+                ..Query::default()
+            }),
+        );
+
+        assert!(parse("(word:true id)").is_err());
+    }
+
+    #[test]
+    fn test_word_regex() {
+        let regex_str = |query| {
+            let Expression::Term(q) = parse(query).unwrap() else {
+                panic!("not a single term");
+            };
+            let (Some(Target::Content(lit)) | Some(Target::Symbol(lit))) = &q.target else {
+                panic!("no target");
+            };
+            q.target_regex_str(lit).into_owned()
+        };
+
+        assert_eq!(regex_str("word:true a.b"), "\\b(?:a\\.b)\\b");
+        assert_eq!(regex_str("word:false a.b"), "a\\.b");
+
+        // Boundaries are only added next to word characters.
+        assert_eq!(regex_str("word:true \"foo(\""), "\\b(?:foo\\()");
+        assert_eq!(regex_str("word:true \".bar\""), "(?:\\.bar)\\b");
+        assert_eq!(regex_str("word:true /fo+|bar/"), "\\b(?:fo+|bar)\\b");
+        assert_eq!(regex_str("word:true /fo+\\(/"), "\\b(?:fo+\\()");
+        assert_eq!(regex_str("word:true /\\w+/"), "\\b(?:\\w+)\\b");
+
+        // Fuzzy targets are still fuzzy.
+        assert_ne!(regex_str("word:true fuzzy:true parse"), "\\b(?:parse)\\b");
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        // Targets are kept as-is, and exclusions are never fuzzy.
+        assert_eq!(
+            parse("fuzzy:true symbol:prase or indxer -content:tets").unwrap(),
+            Expression::Or(vec![
+                Expression::Term(Query {
+                    fuzzy: Some(true),
+                    target: Some(Target::Symbol(Literal::Plain("prase".into()))),
+                    ..Query::default()
+                }),
+                Expression::Term(Query {
+                    fuzzy: Some(true),
+                    target: Some(Target::Content(Literal::Plain("indxer".into()))),
+                    exclude: vec![Query {
+                        target: Some(Target::Content(Literal::Plain("tets".into()))),
+                        ..Query::default()
+                    }],
+                    ..Query::default()
+                }),
+            ]),
+        );
+
+        assert_eq!(
+            parse("fuzzy:false prase").unwrap(),
+            Expression::Term(Query {
+                fuzzy: Some(false),
+                target: Some(Target::Content(Literal::Plain("prase".into()))),
+                ..Query::default()
+            }),
+        );
+
+        assert!(parse("(fuzzy:true prase)").is_err());
+    }
+
     #[test]
     fn case_ignore_affinity() {
         // `case:` is special, it binds globally to the entire query string.
 
         assert_eq!(
             parse("foo or bar case:ignore").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     case_sensitive: Some(false),
                     target: Some(Target::Content(Literal::Plain("foo".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     case_sensitive: Some(false),
                     target: Some(Target::Content(Literal::Plain("bar".into()))),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
 
         assert_eq!(
@@ -1107,24 +2066,24 @@ mod tests {
     fn or_prefix() {
         assert_eq!(
             parse("org").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Plain("org".into()))),
                 ..Query::default()
-            },],
+            }),
         );
 
         assert_eq!(
             parse("org or orange").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     target: Some(Target::Content(Literal::Plain("org".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     target: Some(Target::Content(Literal::Plain("orange".into()))),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
     }
 
@@ -1132,39 +2091,37 @@ mod tests {
     fn or_suffix() {
         assert_eq!(
             parse("for").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Plain("for".into()))),
                 ..Query::default()
-            },],
+            }),
         );
 
         assert_eq!(
             parse("for or error").unwrap(),
-            vec![
-                Query {
+            Expression::Or(vec![
+                Expression::Term(Query {
                     target: Some(Target::Content(Literal::Plain("for".into()))),
                     ..Query::default()
-                },
-                Query {
+                }),
+                Expression::Term(Query {
                     target: Some(Target::Content(Literal::Plain("error".into()))),
                     ..Query::default()
-                },
-            ],
+                }),
+            ]),
         );
     }
 
     #[test]
     fn test_complex_parse() {
-        let mut q = parse(r#"(?:[a-z0-9!#$%&'*+\/=?^_`{|}~-]+(?:\.[a-z0-9!#$%&'*+\/=?^_`{|}~-]+)*|"(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21\x23-\x5b\x5d-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])*")@(?:(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z0-9](?:[a-z0-9-]*[a-z0-9])?|\[(?:(?:(2(5[0-5]|[0-4][0-9])|1[0-9][0-9]|[1-9]?[0-9]))\.){3}(?:(2(5[0-5]|[0-4][0-9])|1[0-9][0-9]|[1-9]?[0-9])|[a-z0-9-]*[a-z0-9]:(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21-\x5a\x53-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])+)\])"#).unwrap();
+        let q = parse(r#"(?:[a-z0-9!#$%&'*+\/=?^_`{|}~-]+(?:\.[a-z0-9!#$%&'*+\/=?^_`{|}~-]+)*|"(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21\x23-\x5b\x5d-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])*")@(?:(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z0-9](?:[a-z0-9-]*[a-z0-9])?|\[(?:(?:(2(5[0-5]|[0-4][0-9])|1[0-9][0-9]|[1-9]?[0-9]))\.){3}(?:(2(5[0-5]|[0-4][0-9])|1[0-9][0-9]|[1-9]?[0-9])|[a-z0-9-]*[a-z0-9]:(?:[\x01-\x08\x0b\x0c\x0e-\x1f\x21-\x5a\x53-\x7f]|\\[\x01-\x09\x0b\x0c\x0e-\x7f])+)\])"#).unwrap();
 
         // Make sure that this regex successfully compiles.
-        q[0].target
-            .take()
-            .unwrap()
-            .content()
-            .unwrap()
-            .regex()
-            .unwrap();
+        for term in q.terms() {
+            if let Some(Target::Content(lit)) = &term.target {
+                lit.regex().unwrap();
+            }
+        }
     }
 
     #[test]
@@ -1176,7 +2133,8 @@ mod tests {
                 langs: ["tsx".into()].into(),
                 repos: [Literal::Plain("bloop".into())].into(),
                 paths: [].into(),
-                branch: [].into()
+                branch: [].into(),
+                ..Default::default()
             }),
         );
     }
@@ -1206,6 +2164,7 @@ mod tests {
                 ]
                 .into(),
                 paths: [Literal::Plain("server/bleep".into())].into(),
+                ..Default::default()
             })
         );
     }
@@ -1223,6 +2182,7 @@ mod tests {
                 repos: [Literal::Plain("bloop".into())].into(),
                 paths: [].into(),
                 branch: [].into(),
+                ..Default::default()
             })
         );
 
@@ -1251,30 +2211,336 @@ mod tests {
         }
     }
 
+    #[test]
+    fn negated_labels() {
+        assert_eq!(
+            parse("-path:test not lang:JavaScript ParseError").unwrap(),
+            Expression::Term(Query {
+                target: Some(Target::Content(Literal::Plain("ParseError".into()))),
+                exclude: vec![
+                    Query {
+                        paths: [Literal::Plain("test".into())].into(),
+                        ..Query::default()
+                    },
+                    Query {
+                        langs: ["javascript".into()].into(),
+                        ..Query::default()
+                    },
+                ],
+                ..Query::default()
+            }),
+        );
+
+        // Exclusions apply to the whole group.
+        assert_eq!(
+            parse("(foo or bar) -repo:vendor").unwrap(),
+            Expression::And(vec![
+                Expression::Or(vec![
+                    Expression::Term(Query {
+                        target: Some(Target::Content(Literal::Plain("foo".into()))),
+                        ..Query::default()
+                    }),
+                    Expression::Term(Query {
+                        target: Some(Target::Content(Literal::Plain("bar".into()))),
+                        ..Query::default()
+                    }),
+                ]),
+                Expression::Term(Query {
+                    exclude: vec![Query {
+                        repo: Some(Literal::Plain("vendor".into())),
+                        ..Query::default()
+                    }],
+                    ..Query::default()
+                }),
+            ]),
+        );
+
+        // Negated groups exclude results matching the whole group.
+        assert_eq!(
+            parse("foo not (repo:vendor path:test)").unwrap(),
+            Expression::And(vec![
+                Expression::Term(Query {
+                    target: Some(Target::Content(Literal::Plain("foo".into()))),
+                    ..Query::default()
+                }),
+                Expression::Not(Box::new(Expression::Term(Query {
+                    repo: Some(Literal::Plain("vendor".into())),
+                    paths: [Literal::Plain("test".into())].into(),
+                    ..Query::default()
+                }))),
+            ]),
+        );
+
+        // A leading dash on a plain literal is still a literal.
+        assert_eq!(
+            parse("-foo").unwrap(),
+            Expression::Term(Query {
+                target: Some(Target::Content(Literal::Plain("-foo".into()))),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("global_regex:true -symbol:foo bar").unwrap(),
+            Expression::Term(Query {
+                global_regex: Some(true),
+                target: Some(Target::Content(Literal::Regex("bar".into()))),
+                exclude: vec![Query {
+                    global_regex: Some(true),
+                    target: Some(Target::Symbol(Literal::Regex("foo".into()))),
+                    ..Query::default()
+                }],
+                ..Query::default()
+            }),
+        );
+    }
+
+    #[test]
+    fn spans_of_targets() {
+        let query = "repo:foo bar -baz content:'qux' symbol:function:quux path:corge";
+        let spans = target_spans(query)
+            .unwrap()
+            .into_iter()
+            .map(|span| &query[span])
+            .collect::<Vec<_>>();
+
+        assert_eq!(spans, ["bar", "qux", "quux"]);
+    }
+
+    #[test]
+    fn nl_parse_negated_labels() {
+        assert_eq!(
+            parse_nl("where is the server started? -path:test not lang:js -repo:bar").unwrap(),
+            ParsedQuery::Semantic(SemanticQuery {
+                target: Some(Literal::Plain("where is the server started?".into())),
+                excluded_paths: [Literal::Plain("test".into())].into(),
+                excluded_langs: ["javascript".into()].into(),
+                excluded_repos: [Literal::Plain("bar".into())].into(),
+                ..Default::default()
+            })
+        );
+
+        for query in ["-org:foo bar", "-symbol:foo bar", "bar -content:foo"] {
+            assert!(matches!(
+                parse_nl(query),
+                Err(ParseError::UnsupportedNegation(..))
+            ));
+        }
+    }
+
     #[test]
     fn escape_characters() {
         assert_eq!(
             parse("'foo\\'bar'").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Plain("foo'bar".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         assert_eq!(
             parse(r#""foo\"bar""#).unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Plain("foo\"bar".into()))),
                 ..Query::default()
-            }],
+            }),
         );
 
         assert_eq!(
             parse("/foo\\/bar/").unwrap(),
-            vec![Query {
+            Expression::Term(Query {
                 target: Some(Target::Content(Literal::Regex("foo/bar".into()))),
                 ..Query::default()
-            }],
+            }),
+        );
+    }
+
+    #[test]
+    fn commit_labels() {
+        const JAN_1: u64 = 1704067200;
+        const DAY: u64 = 24 * 60 * 60;
+
+        assert_eq!(
+            parse("author:alice modified:>2024-01-01 foo").unwrap(),
+            Expression::Term(Query {
+                author: Some(Literal::Plain("alice".into())),
+                modified: Some(JAN_1 + DAY..u64::MAX),
+                target: Some(Target::Content(Literal::Plain("foo".into()))),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("modified:>=2024-01-01 modified:<2024-01-03").unwrap(),
+            Expression::Term(Query {
+                modified: Some(JAN_1..JAN_1 + 2 * DAY),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("modified:2024-01-01").unwrap(),
+            Expression::Term(Query {
+                modified: Some(JAN_1..JAN_1 + DAY),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("-modified:<=2024-01-01").unwrap(),
+            Expression::Term(Query {
+                exclude: vec![Query {
+                    modified: Some(0..JAN_1 + DAY),
+                    ..Query::default()
+                }],
+                ..Query::default()
+            }),
+        );
+
+        assert!(parse("modified:>2024-13-45").is_err());
+    }
+
+    #[test]
+    fn file_predicates() {
+        assert_eq!(
+            parse("size:>100k lines:<=300 ext:.RS foo").unwrap(),
+            Expression::Term(Query {
+                size: Some(100 * 1024 + 1..u64::MAX),
+                lines: Some(0..301),
+                extensions: ["rs".into()].into(),
+                target: Some(Target::Content(Literal::Plain("foo".into()))),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("size:>=1kb size:<2MB").unwrap(),
+            Expression::Term(Query {
+                size: Some(1024..2 * 1024 * 1024),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("is:dir path:src or is:file ext:rs ext:go").unwrap(),
+            Expression::Or(vec![
+                Expression::Term(Query {
+                    is_dir: Some(true),
+                    paths: [Literal::Plain("src".into())].into(),
+                    ..Query::default()
+                }),
+                Expression::Term(Query {
+                    is_dir: Some(false),
+                    extensions: ["rs".into(), "go".into()].into(),
+                    ..Query::default()
+                }),
+            ]),
+        );
+
+        assert_eq!(
+            parse("-lines:>1000").unwrap(),
+            Expression::Term(Query {
+                exclude: vec![Query {
+                    lines: Some(1001..u64::MAX),
+                    ..Query::default()
+                }],
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("is:test is:docs -is:generated").unwrap(),
+            Expression::Term(Query {
+                classes: [FileClass::Test, FileClass::Documentation].into(),
+                exclude: vec![Query {
+                    classes: [FileClass::Generated].into(),
+                    ..Query::default()
+                }],
+                ..Query::default()
+            }),
+        );
+
+        assert!(parse("is:something").is_err());
+    }
+
+    #[test]
+    fn symbol_kinds() {
+        assert_eq!(
+            parse("symbol:function:parse").unwrap(),
+            Expression::Term(Query {
+                symbol_kinds: ["function".into()].into(),
+                target: Some(Target::Symbol(Literal::Plain("parse".into()))),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("kind:struct kind:Enum Error").unwrap(),
+            Expression::Term(Query {
+                symbol_kinds: ["struct".into(), "enum".into()].into(),
+                target: Some(Target::Content(Literal::Plain("Error".into()))),
+                ..Query::default()
+            }),
+        );
+
+        // `std` is not a kind of symbol
+        assert_eq!(
+            parse("symbol:std::io").unwrap(),
+            Expression::Term(Query {
+                target: Some(Target::Symbol(Literal::Plain("std::io".into()))),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("kind:Function").unwrap(),
+            Expression::Term(Query {
+                symbol_kinds: ["function".into()].into(),
+                ..Query::default()
+            }),
+        );
+
+        assert_eq!(
+            parse("kind:bogus foo"),
+            Err(ParseError::UnknownSymbolKind("bogus".into())),
+        );
+
+        assert_eq!(
+            parse("-kind:variable").unwrap(),
+            Expression::Term(Query {
+                exclude: vec![Query {
+                    symbol_kinds: ["variable".into()].into(),
+                    ..Query::default()
+                }],
+                ..Query::default()
+            }),
+        );
+    }
+
+    #[test]
+    fn ast_label() {
+        assert_eq!(
+            parse(
+                r#"lang:rust ast:'(call_expression function: (identifier) @f (#eq? @f "parse"))'"#
+            )
+            .unwrap(),
+            Expression::Term(Query {
+                langs: ["rust".into()].into(),
+                target: Some(Target::Ast(Literal::Plain(
+                    r#"(call_expression function: (identifier) @f (#eq? @f "parse"))"#.into()
+                ))),
+                ..Query::default()
+            }),
+        );
+
+        // patterns are never converted to regexes
+        assert_eq!(
+            parse("global_regex:true ast:'(function_item)'").unwrap(),
+            Expression::Term(Query {
+                global_regex: Some(true),
+                target: Some(Target::Ast(Literal::Plain("(function_item)".into()))),
+                ..Query::default()
+            }),
         );
     }
 }
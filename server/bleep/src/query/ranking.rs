@@ -1,33 +1,154 @@
-use std::{sync::Arc, time::SystemTime};
+use std::sync::Arc;
 
+use clap::Args;
+use serde::{Deserialize, Serialize};
 use tantivy::{
     collector::{ScoreSegmentTweaker, ScoreTweaker},
     fastfield::{BytesFastFieldReader, Column},
     DocId, Score,
 };
 
-use crate::indexes::file::File;
+use crate::{indexes::file::File, repo::iterator::FileClass};
+
+/// Weights of the signals used to rank content search results.
+///
+/// Penalties divide the score of a document, so a weight of `0` disables the signal.
+#[derive(Serialize, Deserialize, Args, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "snake_case")]
+pub struct RankingConfig {
+    /// Score multiplier for files written in a language we understand
+    #[clap(long = "ranking-lang-boost", default_value_t = default_lang_boost())]
+    pub lang_boost: f32,
+
+    /// Exponent of the penalty for files with long lines
+    #[clap(long = "ranking-line-length-weight", default_value_t = 1.0)]
+    pub line_length_weight: f32,
+
+    /// Exponent of the penalty for files that were not modified recently
+    #[clap(long = "ranking-recency-weight", default_value_t = 1.0)]
+    pub recency_weight: f32,
+
+    /// Penalty for every directory a file is nested in
+    #[clap(long = "ranking-depth-penalty", default_value_t = default_depth_penalty())]
+    pub depth_penalty: f32,
+
+    /// Penalty for test files
+    #[clap(long = "ranking-test-penalty", default_value_t = default_test_penalty())]
+    pub test_penalty: f32,
+
+    /// Penalty for vendored files
+    #[clap(long = "ranking-vendor-penalty", default_value_t = default_vendor_penalty())]
+    pub vendor_penalty: f32,
+
+    /// Penalty for generated files
+    #[clap(long = "ranking-generated-penalty", default_value_t = default_generated_penalty())]
+    pub generated_penalty: f32,
+
+    /// Penalty for documentation
+    #[clap(long = "ranking-docs-penalty", default_value_t = default_docs_penalty())]
+    pub docs_penalty: f32,
+
+    /// Score boost for files that define a symbol matching the query
+    #[clap(long = "ranking-symbol-boost", default_value_t = default_symbol_boost())]
+    pub symbol_boost: f32,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            lang_boost: default_lang_boost(),
+            line_length_weight: 1.0,
+            recency_weight: 1.0,
+            depth_penalty: default_depth_penalty(),
+            test_penalty: default_test_penalty(),
+            vendor_penalty: default_vendor_penalty(),
+            generated_penalty: default_generated_penalty(),
+            docs_penalty: default_docs_penalty(),
+            symbol_boost: default_symbol_boost(),
+        }
+    }
+}
+
+const fn default_lang_boost() -> f32 {
+    1000.0
+}
+
+const fn default_depth_penalty() -> f32 {
+    0.1
+}
+
+const fn default_test_penalty() -> f32 {
+    2.0
+}
+
+const fn default_vendor_penalty() -> f32 {
+    5.0
+}
+
+const fn default_generated_penalty() -> f32 {
+    5.0
+}
+
+const fn default_docs_penalty() -> f32 {
+    1.5
+}
+
+const fn default_symbol_boost() -> f32 {
+    10.0
+}
+
+/// Ranks content search results by the signals in `RankingConfig`.
+///
+/// Recency is ranked against `now`, in unix seconds, which is fixed for every page of a search.
+pub struct DocumentTweaker {
+    pub schema: File,
+    pub now: u64,
+}
 
-pub struct DocumentTweaker(pub File);
 pub struct SegmentScorer {
+    config: RankingConfig,
+    now: u64,
     line_length: Arc<dyn Column<f64>>,
     lang: BytesFastFieldReader,
     last_commit: Arc<dyn Column<u64>>,
+    relative_path: BytesFastFieldReader,
+    file_class: Arc<dyn Column<u64>>,
 }
 
 impl ScoreSegmentTweaker<Score> for SegmentScorer {
     fn score(&mut self, doc: DocId, mut score: Score) -> Score {
-        // * 1000 if it's a language we understand
-        score *= 1.0 + self.lang.num_bytes(doc).min(1) as f32 * 999.0;
+        let config = &self.config;
+
+        // Boost files in a language we understand
+        if self.lang.num_bytes(doc) > 0 {
+            score *= config.lang_boost.max(1.0);
+        }
 
         // Penalty for lines that are too long
-        score /= self.line_length.get_val(doc).clamp(20.0, 1000.0) as f32;
-        score /= SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
+        score /= (self.line_length.get_val(doc).clamp(20.0, 1000.0) as f32)
+            .powf(config.line_length_weight);
+
+        // Penalty for files that were last modified a long time ago
+        score /= (self
+            .now
             .saturating_sub(self.last_commit.get_val(doc))
-            .min(5_000_000) as f32;
+            .clamp(1, 5_000_000) as f32)
+            .powf(config.recency_weight);
+
+        // Penalty for deeply nested files
+        let path = self.relative_path.get_bytes(doc);
+        let depth = path.iter().filter(|&&b| b == b'/').count();
+        score /= 1.0 + depth as f32 * config.depth_penalty;
+
+        // Penalty for tests, generated and third-party code, and documentation
+        let penalty = match FileClass::from_u64(self.file_class.get_val(doc)) {
+            FileClass::Source => 1.0,
+            FileClass::Test => config.test_penalty,
+            FileClass::Generated => config.generated_penalty,
+            FileClass::Vendored => config.vendor_penalty,
+            FileClass::Documentation => config.docs_penalty,
+        };
+        score /= penalty.max(1.0);
 
         score
     }
@@ -40,13 +161,19 @@ impl ScoreTweaker<Score> for DocumentTweaker {
         &self,
         segment_reader: &tantivy::SegmentReader,
     ) -> tantivy::Result<Self::Child> {
-        let Self(schema) = self;
+        let Self { schema, now } = self;
         Ok(SegmentScorer {
+            config: schema.ranking.clone(),
+            now: *now,
             line_length: segment_reader.fast_fields().f64(schema.avg_line_length)?,
             lang: segment_reader.fast_fields().bytes(schema.lang)?,
             last_commit: segment_reader
                 .fast_fields()
                 .u64(schema.last_commit_unix_seconds)?,
+            relative_path: segment_reader
+                .fast_fields()
+                .bytes(schema.raw_relative_path)?,
+            file_class: segment_reader.fast_fields().u64(schema.file_class)?,
         })
     }
 }
@@ -0,0 +1,554 @@
+//! Preview a regex search-and-replace across repositories.
+//!
+//! Replacements are rendered as unified diffs against the indexed content, and nothing is ever
+//! written back to the repositories on disk.
+
+use std::{ops::Range, sync::Arc};
+
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    execute::{ApiQuery, ExecuteQuery, PagingMetadata, QueryResult},
+    parser::{self, Target},
+};
+use crate::{
+    indexes::{reader::ContentReader, Indexes},
+    repo::RepoRef,
+};
+
+const fn default_diff_context() -> usize {
+    3
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplaceParams {
+    /// The text to substitute for every match of the content regex.
+    ///
+    /// Capture groups are referenced as `$1` or `${name}`, and `$$` is a literal `$`.
+    pub replace: String,
+
+    /// The number of unchanged lines around each hunk
+    #[serde(default = "default_diff_context")]
+    pub diff_context: usize,
+
+    /// Whether to also return the diffs of each repository as a single patch
+    #[serde(default)]
+    pub patch: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReplaceResponse {
+    /// Number of changed files in this response
+    count: usize,
+
+    /// Paging metadata of the underlying content search
+    metadata: PagingMetadata,
+
+    data: Vec<FileDiff>,
+
+    /// One patch per repository, that can be applied with `git apply` at its root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    patches: Option<Vec<RepoPatch>>,
+}
+
+impl crate::webserver::ApiResponse for ReplaceResponse {}
+
+#[derive(Serialize, Debug)]
+pub struct FileDiff {
+    repo_name: String,
+    repo_ref: String,
+    relative_path: String,
+    lang: Option<String>,
+
+    /// Number of matches that were replaced
+    replacements: usize,
+
+    /// The change, as a unified diff
+    diff: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RepoPatch {
+    repo_name: String,
+    repo_ref: String,
+    patch: String,
+}
+
+impl ApiQuery {
+    pub async fn replace(
+        self: Arc<Self>,
+        indexes: Arc<Indexes>,
+        params: ReplaceParams,
+    ) -> Result<ReplaceResponse> {
+        let expr = parser::parse(&self.q)?;
+        let regex = content_regex(&expr)?;
+        let branch = branch(&expr);
+
+        let response = ContentReader.execute(&indexes.file, &expr, &self).await?;
+
+        let mut data = Vec::new();
+        for result in response.data {
+            let QueryResult::Snippets(file) = result else {
+                continue;
+            };
+
+            let repo_ref = file
+                .repo_ref
+                .parse::<RepoRef>()
+                .context("invalid repo ref in index")?;
+
+            let Some(doc) = indexes
+                .file
+                .by_path(&repo_ref, &file.relative_path, branch.as_deref())
+                .await?
+            else {
+                continue;
+            };
+
+            let Some((diff, replacements)) = unified_diff(
+                &doc.relative_path,
+                &doc.content,
+                &regex,
+                &params.replace,
+                params.diff_context,
+            ) else {
+                continue;
+            };
+
+            data.push(FileDiff {
+                repo_name: doc.repo_name,
+                repo_ref: doc.repo_ref,
+                relative_path: doc.relative_path,
+                lang: doc.lang,
+                replacements,
+                diff,
+            });
+        }
+
+        let patches = params.patch.then(|| bundle(&data));
+
+        Ok(ReplaceResponse {
+            count: data.len(),
+            metadata: response.metadata,
+            data,
+            patches,
+        })
+    }
+}
+
+/// A query that can't be replaced over, which is the caller's fault.
+#[derive(Debug, thiserror::Error)]
+pub enum ReplaceError {
+    #[error("a replacement needs a query with a content target, such as `/fn (\\w+)/`")]
+    NoContentTarget,
+    #[error("a replacement needs a query with a single content target")]
+    ManyContentTargets,
+}
+
+/// The regex to replace matches of, built from the only content target in the query.
+///
+/// This matches what the search highlights, including the typos of fuzzy queries and the word
+/// boundaries of whole word queries.
+fn content_regex(expr: &parser::QueryExpr<'_>) -> Result<Regex> {
+    let mut targets = expr.positive_terms().filter_map(|q| match &q.target {
+        Some(Target::Content(lit)) => Some((q, lit)),
+        _ => None,
+    });
+
+    let Some((q, lit)) = targets.next() else {
+        return Err(ReplaceError::NoContentTarget.into());
+    };
+
+    if targets.next().is_some() {
+        return Err(ReplaceError::ManyContentTargets.into());
+    }
+
+    RegexBuilder::new(&q.target_regex_str(lit))
+        .multi_line(true)
+        .case_insensitive(!q.is_case_sensitive())
+        .build()
+        .context("invalid content regex")
+}
+
+/// The branch that files are read from, if the query searches a single one.
+fn branch(expr: &parser::QueryExpr<'_>) -> Option<String> {
+    let mut branches = expr.positive_terms().flat_map(|q| &q.branches);
+    match (branches.next(), branches.next()) {
+        (Some(branch), None) => Some(branch.as_plain()?.into_owned()),
+        _ => None,
+    }
+}
+
+/// Concatenate the diffs of each repository, in the order the repositories first appear.
+fn bundle(diffs: &[FileDiff]) -> Vec<RepoPatch> {
+    let mut patches: Vec<RepoPatch> = Vec::new();
+
+    for diff in diffs {
+        match patches.iter_mut().find(|p| p.repo_ref == diff.repo_ref) {
+            Some(patch) => patch.patch.push_str(&diff.diff),
+            None => patches.push(RepoPatch {
+                repo_name: diff.repo_name.clone(),
+                repo_ref: diff.repo_ref.clone(),
+                patch: diff.diff.clone(),
+            }),
+        }
+    }
+
+    patches
+}
+
+/// A set of adjacent lines that are rewritten by one or more matches.
+struct Change {
+    /// First and last line, inclusive
+    first: usize,
+    last: usize,
+
+    /// The byte range and replacement of every match in these lines
+    edits: Vec<(Range<usize>, String)>,
+}
+
+/// Replace every match of `regex` in `content`, and render the result as a unified diff.
+///
+/// Returns the diff and the number of replaced matches, or `None` if nothing changed.
+fn unified_diff(
+    path: &str,
+    content: &str,
+    regex: &Regex,
+    replacement: &str,
+    context: usize,
+) -> Option<(String, usize)> {
+    let lines = content.split_inclusive('\n').collect::<Vec<_>>();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let starts = lines
+        .iter()
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some(start)
+        })
+        .collect::<Vec<_>>();
+
+    let line_of = |offset: usize| starts.partition_point(|&s| s <= offset) - 1;
+    let line_end = |line: usize| starts[line] + lines[line].len();
+
+    let mut changes: Vec<Change> = Vec::new();
+    let mut replacements = 0;
+
+    for caps in regex.captures_iter(content) {
+        let m = caps.get(0).unwrap();
+        let mut replaced = String::new();
+        caps.expand(replacement, &mut replaced);
+
+        if replaced == m.as_str() {
+            continue;
+        }
+
+        replacements += 1;
+
+        let first = line_of(m.start());
+        let last = if m.is_empty() {
+            first
+        } else {
+            line_of(m.end() - 1)
+        };
+
+        match changes.last_mut() {
+            Some(change) if first <= change.last => {
+                change.last = change.last.max(last);
+                change.edits.push((m.range(), replaced));
+            }
+            _ => changes.push(Change {
+                first,
+                last,
+                edits: vec![(m.range(), replaced)],
+            }),
+        }
+    }
+
+    if changes.is_empty() {
+        return None;
+    }
+
+    let render = |change: &Change| {
+        let mut out = String::new();
+        let mut cursor = starts[change.first];
+        for (range, replaced) in &change.edits {
+            out.push_str(&content[cursor..range.start]);
+            out.push_str(replaced);
+            cursor = range.end;
+        }
+        out.push_str(&content[cursor..line_end(change.last)]);
+        out
+    };
+
+    // a replacement that removes the last line break of a change joins the next line onto it, so
+    // that line has to be part of the change as well
+    let mut rendered = Vec::with_capacity(changes.len());
+    let mut i = 0;
+    while i < changes.len() {
+        let new = render(&changes[i]);
+        if !new.is_empty() && !new.ends_with('\n') && changes[i].last + 1 < lines.len() {
+            changes[i].last += 1;
+            if changes
+                .get(i + 1)
+                .map_or(false, |next| next.first <= changes[i].last)
+            {
+                let next = changes.remove(i + 1);
+                changes[i].last = changes[i].last.max(next.last);
+                changes[i].edits.extend(next.edits);
+            }
+            continue;
+        }
+
+        rendered.push((changes[i].first, changes[i].last, new));
+        i += 1;
+    }
+
+    let mut out = format!("--- a/{path}\n+++ b/{path}\n");
+    let mut delta = 0isize;
+    let mut k = 0;
+
+    while k < rendered.len() {
+        // changes that are close enough to share context lines go into the same hunk
+        let mut j = k + 1;
+        while j < rendered.len() && rendered[j].0 <= rendered[j - 1].1 + 1 + 2 * context {
+            j += 1;
+        }
+
+        let hunk_start = rendered[k].0.saturating_sub(context);
+        let hunk_end = (rendered[j - 1].1 + 1 + context).min(lines.len());
+        let new_start = (hunk_start as isize + delta) as usize;
+
+        let mut body = String::new();
+        let mut new_len = hunk_end - hunk_start;
+        let mut cursor = hunk_start;
+
+        for (first, last, new) in &rendered[k..j] {
+            for line in &lines[cursor..*first] {
+                push_line(&mut body, ' ', line);
+            }
+            for line in &lines[*first..=*last] {
+                push_line(&mut body, '-', line);
+            }
+
+            let added = new.split_inclusive('\n').collect::<Vec<_>>();
+            for line in &added {
+                push_line(&mut body, '+', line);
+            }
+
+            let removed = last - first + 1;
+            new_len = new_len - removed + added.len();
+            delta += added.len() as isize - removed as isize;
+            cursor = last + 1;
+        }
+
+        for line in &lines[cursor..hunk_end] {
+            push_line(&mut body, ' ', line);
+        }
+
+        out += &format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(hunk_start, hunk_end - hunk_start),
+            hunk_range(new_start, new_len)
+        );
+        out += &body;
+
+        k = j;
+    }
+
+    Some((out, replacements))
+}
+
+/// Format the line range of a hunk, given the 0-based index of its first line.
+///
+/// Empty ranges point at the line before them, as in GNU diff.
+fn hunk_range(start: usize, len: usize) -> String {
+    if len == 0 {
+        format!("{start},0")
+    } else {
+        format!("{},{len}", start + 1)
+    }
+}
+
+fn push_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(content: &str, regex: &str, replacement: &str, context: usize) -> Option<String> {
+        let regex = Regex::new(regex).unwrap();
+        unified_diff("a.rs", content, &regex, replacement, context).map(|(diff, _)| diff)
+    }
+
+    #[test]
+    fn single_hunk_with_capture_groups() {
+        let content = "one\ntwo\nfn foo() {}\nthree\nfour\nfive\n";
+        let (out, count) = unified_diff(
+            "a.rs",
+            content,
+            &Regex::new(r"fn (\w+)").unwrap(),
+            "fn ${1}_renamed",
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(
+            out,
+            "--- a/a.rs\n\
+             +++ b/a.rs\n\
+             @@ -2,3 +2,3 @@\n \
+             two\n\
+             -fn foo() {}\n\
+             +fn foo_renamed() {}\n \
+             three\n"
+        );
+    }
+
+    #[test]
+    fn nearby_changes_share_a_hunk() {
+        let content = "a\nx\nb\nx\nc\nd\ne\nf\nx\n";
+        let out = diff(content, "x", "y", 1).unwrap();
+
+        assert_eq!(
+            out,
+            "--- a/a.rs\n\
+             +++ b/a.rs\n\
+             @@ -1,5 +1,5 @@\n \
+             a\n\
+             -x\n\
+             +y\n \
+             b\n\
+             -x\n\
+             +y\n \
+             c\n\
+             @@ -8,2 +8,2 @@\n \
+             f\n\
+             -x\n\
+             +y\n"
+        );
+    }
+
+    #[test]
+    fn line_counts_follow_inserted_and_removed_lines() {
+        let content = "a\nb\nc\nd\ne\nf\ng\n";
+        let out = diff(content, "(?m)^([bf])$", "$1\n$1", 0).unwrap();
+
+        assert_eq!(
+            out,
+            "--- a/a.rs\n\
+             +++ b/a.rs\n\
+             @@ -2,1 +2,2 @@\n\
+             -b\n\
+             +b\n\
+             +b\n\
+             @@ -6,1 +7,2 @@\n\
+             -f\n\
+             +f\n\
+             +f\n"
+        );
+
+        let out = diff(content, "c\n", "", 0).unwrap();
+        assert_eq!(
+            out,
+            "--- a/a.rs\n\
+             +++ b/a.rs\n\
+             @@ -3,1 +2,0 @@\n\
+             -c\n"
+        );
+    }
+
+    #[test]
+    fn joined_lines() {
+        let out = diff("a\nfoo\nbar\nb\n", "foo\n", "foo ", 0).unwrap();
+
+        assert_eq!(
+            out,
+            "--- a/a.rs\n\
+             +++ b/a.rs\n\
+             @@ -2,2 +2,1 @@\n\
+             -foo\n\
+             -bar\n\
+             +foo bar\n"
+        );
+    }
+
+    #[test]
+    fn missing_newline_at_end_of_file() {
+        let out = diff("a\nfoo", "foo", "bar", 1).unwrap();
+
+        assert_eq!(
+            out,
+            "--- a/a.rs\n\
+             +++ b/a.rs\n\
+             @@ -1,2 +1,2 @@\n \
+             a\n\
+             -foo\n\
+             \\ No newline at end of file\n\
+             +bar\n\
+             \\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn unchanged_content() {
+        assert_eq!(diff("foo\nbar\n", "baz", "qux", 3), None);
+        assert_eq!(diff("foo\nbar\n", "(foo)", "$1", 3), None);
+        assert_eq!(diff("", "foo", "bar", 3), None);
+    }
+
+    #[test]
+    fn requires_a_single_content_target() {
+        let expr = parser::parse("repo:bloop /fn (\\w+)/").unwrap();
+        assert_eq!(content_regex(&expr).unwrap().as_str(), "fn (\\w+)");
+
+        let expr = parser::parse("repo:bloop").unwrap();
+        let err = content_regex(&expr).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ReplaceError::NoContentTarget)
+        ));
+
+        let expr = parser::parse("foo or bar").unwrap();
+        let err = content_regex(&expr).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(ReplaceError::ManyContentTargets)
+        ));
+    }
+
+    #[test]
+    fn matches_like_the_search() {
+        let regex = content_regex(&parser::parse("word:true id").unwrap()).unwrap();
+        assert!(regex.is_match("let id = 1;"));
+        assert!(!regex.is_match("let idle = 1;"));
+
+        let regex = content_regex(&parser::parse("word:true \"foo(\"").unwrap()).unwrap();
+        assert!(regex.is_match("foo(x)"));
+        assert!(!regex.is_match("afoo(x)"));
+
+        let regex = content_regex(&parser::parse("fuzzy:true parse").unwrap()).unwrap();
+        assert!(regex.is_match("fn parze() {}"));
+    }
+
+    #[test]
+    fn single_branch() {
+        let expr = parser::parse("branch:main foo").unwrap();
+        assert_eq!(branch(&expr).as_deref(), Some("main"));
+
+        let expr = parser::parse("branch:main branch:dev foo").unwrap();
+        assert_eq!(branch(&expr), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! "Did you mean" suggestions for searches that return nothing.
+//!
+//! A typo in an identifier silently returns zero hits, so we look for indexed symbols that share
+//! trigrams with the searched word, and suggest the ones that are a few edits away from it.
+
+use std::{borrow::Cow, cmp::Reverse, collections::HashMap};
+
+use anyhow::Result;
+use serde::Serialize;
+use tantivy::{
+    collector::TopDocs,
+    query::{BooleanQuery, Occur, Query, TermQuery},
+    schema::IndexRecordOption,
+    Term,
+};
+
+use super::{
+    compiler::{case_permutations, trigrams},
+    fuzzy::levenshtein,
+    parser::{self, Literal, QueryExpr, Target},
+};
+use crate::indexes::{reader::ContentReader, DocumentRead, File, Indexer};
+
+/// Words shorter than this share too few trigrams with their corrections
+const MIN_WORD_LEN: usize = 3;
+
+/// Number of files whose symbols are considered as corrections
+const CANDIDATE_FILES: usize = 100;
+
+/// Maximum number of suggestions returned for a query
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Corrections can be at most this many edits away from the searched word
+const MAX_DISTANCE: usize = 3;
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Suggestion {
+    /// The name of an indexed symbol that is close to the searched word
+    pub text: String,
+
+    /// The original query, with the searched word replaced by `text`
+    pub query: String,
+}
+
+/// The single plain word a query searches for, if any.
+///
+/// Queries with several targets, regexes or multiple words are not corrected, as it's unclear which
+/// part of the query is misspelled.
+pub fn searched_word<'a>(expr: &'a QueryExpr<'a>) -> Option<Cow<'a, str>> {
+    let mut targets = expr.positive_terms().filter_map(|q| q.target.as_ref());
+
+    let word = match (targets.next()?, targets.next()) {
+        (Target::Content(Literal::Plain(word)) | Target::Symbol(Literal::Plain(word)), None) => {
+            word
+        }
+        _ => return None,
+    };
+
+    (!word.contains(char::is_whitespace)).then(|| word.clone())
+}
+
+/// Suggest corrections for `word` in the query string `q`, from the names of indexed symbols.
+///
+/// Symbols are only taken from the files that `expr` searches, as in the repositories and
+/// languages it is restricted to.
+pub async fn did_you_mean(
+    indexer: &Indexer<File>,
+    q: &str,
+    expr: &QueryExpr<'_>,
+    word: &str,
+) -> Result<Vec<Suggestion>> {
+    if word.chars().count() < MIN_WORD_LEN {
+        return Ok(vec![]);
+    }
+
+    // the word is replaced where it is written, rather than wherever the same text first appears,
+    // as in `path:indxer indxer`
+    let Some(span) = parser::target_spans(q)?
+        .into_iter()
+        .find(|span| &q[span.clone()] == word)
+    else {
+        return Ok(vec![]);
+    };
+
+    // the files searched by the query, regardless of what is searched for in them
+    let filters = expr.map(&mut |term| parser::Query {
+        target: None,
+        ..term.clone()
+    });
+    let filter = ContentReader.compile(&indexer.source, &filters, &indexer.index)?;
+
+    // files defining symbols that share the most trigrams with the word, regardless of case
+    let field = indexer.source.symbols;
+    let trigrams = BooleanQuery::union(
+        trigrams(word)
+            .map(|trigram| {
+                let terms = case_permutations(&trigram)
+                    .map(|t| {
+                        let term = Term::from_field_text(field, &t);
+                        Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs))
+                            as Box<dyn Query>
+                    })
+                    .collect();
+
+                Box::new(BooleanQuery::union(terms)) as Box<dyn Query>
+            })
+            .collect(),
+    );
+    let query = BooleanQuery::new(vec![
+        (Occur::Must, Box::new(trigrams) as Box<dyn Query>),
+        (Occur::Must, filter),
+    ]);
+
+    let searcher = indexer.reader.read().await.searcher();
+    let top_docs = searcher.search(&query, &TopDocs::with_limit(CANDIDATE_FILES))?;
+
+    let mut frequencies = HashMap::<String, usize>::new();
+    for (_, addr) in top_docs {
+        let doc = ContentReader.read_document(&indexer.source, searcher.doc(addr)?);
+
+        for symbol in doc.symbol_locations.list() {
+            if let Some(name) = doc
+                .content
+                .get(symbol.range.start.byte..symbol.range.end.byte)
+            {
+                *frequencies.entry(name.to_owned()).or_default() += 1;
+            }
+        }
+    }
+
+    Ok(closest(word, frequencies)
+        .into_iter()
+        .map(|text| Suggestion {
+            query: format!("{}{text}{}", &q[..span.start], &q[span.end..]),
+            text,
+        })
+        .collect())
+}
+
+/// Pick the names closest to `word`, breaking ties in favour of the most frequent names.
+fn closest(word: &str, frequencies: HashMap<String, usize>) -> Vec<String> {
+    let word = word.to_lowercase();
+    let max_distance = ((word.chars().count() + 3) / 4).clamp(1, MAX_DISTANCE);
+
+    let mut candidates = frequencies
+        .into_iter()
+        .filter_map(|(name, frequency)| {
+            let distance = levenshtein(&word, &name.to_lowercase());
+            (distance > 0 && distance <= max_distance).then_some((
+                distance,
+                Reverse(frequency),
+                name,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort();
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, _, name)| name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parser;
+
+    #[test]
+    fn closest_names() {
+        let frequencies = [
+            ("Indexer", 3),
+            ("Indexes", 10),
+            ("indexer", 1),
+            ("Index", 7),
+            ("Snipper", 20),
+        ]
+        .into_iter()
+        .map(|(name, freq)| (name.to_owned(), freq))
+        .collect();
+
+        assert_eq!(
+            closest("Indxer", frequencies),
+            ["Indexer", "indexer", "Indexes", "Index"]
+        );
+    }
+
+    #[test]
+    fn searched_words() {
+        let word = |q: &str| searched_word(&parser::parse(q).unwrap()).map(Cow::into_owned);
+
+        assert_eq!(word("Indxer repo:bloop"), Some("Indxer".to_owned()));
+        assert_eq!(word("symbol:Indxer"), Some("Indxer".to_owned()));
+        assert_eq!(word("/Ind.xer/"), None);
+        assert_eq!(word("Indxer or Snipr"), None);
+        assert_eq!(word("path:src"), None);
+    }
+}
@@ -14,7 +14,7 @@ use tracing::debug;
 use crate::state::get_relative_path;
 
 pub(crate) mod iterator;
-use iterator::language;
+use iterator::{classify, language};
 
 // Types of repo
 #[derive(Serialize, Deserialize, Hash, PartialEq, Eq, Clone, Debug)]
@@ -286,10 +286,12 @@ impl Repository {
             .ok();
 
         let langs = Default::default();
+        let attributes = classify::Attributes::load(&self.disk_path);
 
         RepoMetadata {
             last_commit_unix_secs,
             langs,
+            attributes,
         }
         .into()
     }
@@ -337,6 +339,9 @@ fn get_unix_time(time: SystemTime) -> u64 {
 pub struct RepoMetadata {
     pub last_commit_unix_secs: Option<u64>,
     pub langs: language::LanguageInfo,
+
+    /// Overrides for the classification of files, from `.gitattributes`
+    pub attributes: classify::Attributes,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Hash)]
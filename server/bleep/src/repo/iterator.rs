@@ -2,13 +2,16 @@ use std::{collections::HashMap, path::Path};
 
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Serialize;
 use smallvec::SmallVec;
 use tracing::warn;
 
+pub mod classify;
 mod fs;
 mod git;
 pub(super) mod language;
 
+pub use classify::FileClass;
 pub use fs::FileWalker;
 pub use git::{BranchFilter, GitWalker};
 
@@ -54,17 +57,38 @@ impl RepoDirEntry {
             RepoDirEntry::Other => None,
         }
     }
+
+    pub fn last_commit(&self) -> Option<&LastCommit> {
+        match self {
+            RepoDirEntry::Dir(d) => d.last_commit.as_ref(),
+            RepoDirEntry::File(f) => f.last_commit.as_ref(),
+            RepoDirEntry::Other => None,
+        }
+    }
 }
 
 pub struct RepoDir {
     pub path: String,
     pub branches: Vec<String>,
+    /// The last commit that modified this directory, if known
+    pub last_commit: Option<LastCommit>,
 }
 
 pub struct RepoFile {
     pub path: String,
     pub buffer: String,
     pub branches: Vec<String>,
+    /// The last commit that modified this file, if known
+    pub last_commit: Option<LastCommit>,
+}
+
+/// Metadata of the last commit that touched a file or directory.
+#[derive(Serialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct LastCommit {
+    pub hash: String,
+    /// Author of the commit, formatted as `Name <email>`
+    pub author: String,
+    pub unix_secs: u64,
 }
 
 #[derive(Hash, Eq, PartialEq)]
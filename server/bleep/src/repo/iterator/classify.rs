@@ -0,0 +1,374 @@
+//! Classify files as tests, generated code, vendored code or documentation.
+//!
+//! Classes are guessed from the path of a file and the first lines of its content, and can be
+//! overridden with the `linguist-generated`, `linguist-vendored` and `linguist-documentation`
+//! attributes in the `.gitattributes` file at the root of a repository.
+
+use std::path::Path;
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use serde::Serialize;
+use tracing::warn;
+
+/// Number of bytes at the start of a file that are searched for a generated code marker
+const HEADER_LEN: usize = 1024;
+
+/// What a file is used for, when it isn't regular source code.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FileClass {
+    #[default]
+    Source = 0,
+    Test = 1,
+    Generated = 2,
+    Vendored = 3,
+    Documentation = 4,
+}
+
+impl FileClass {
+    /// Guess the class of a file, given its path relative to the repository root.
+    pub fn classify(path: &Path, content: &str, attributes: &Attributes) -> Self {
+        let path_str = path.to_string_lossy();
+        let path_bytes = path_str.as_bytes();
+
+        if attributes
+            .vendored(path)
+            .unwrap_or_else(|| is_vendor_path(path_bytes))
+        {
+            Self::Vendored
+        } else if attributes
+            .generated(path)
+            .unwrap_or_else(|| is_generated_path(path_bytes) || has_generated_header(content))
+        {
+            Self::Generated
+        } else if attributes.documentation(path) == Some(true) {
+            Self::Documentation
+        } else if is_test_path(path_bytes) {
+            Self::Test
+        } else if attributes.documentation(path).is_none() && is_docs_path(path_bytes) {
+            Self::Documentation
+        } else {
+            Self::Source
+        }
+    }
+
+    /// Parse the name of a class, as used in `is:test` or `-is:generated`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "source" => Some(Self::Source),
+            "test" => Some(Self::Test),
+            "generated" => Some(Self::Generated),
+            "vendored" | "vendor" => Some(Self::Vendored),
+            "docs" | "documentation" => Some(Self::Documentation),
+            _ => None,
+        }
+    }
+
+    /// The name of this class, as serialized.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Source => "source",
+            Self::Test => "test",
+            Self::Generated => "generated",
+            Self::Vendored => "vendored",
+            Self::Documentation => "documentation",
+        }
+    }
+
+    /// The value of this class in the `file_class` fast field.
+    pub fn as_u64(self) -> u64 {
+        self as u64
+    }
+
+    pub fn from_u64(value: u64) -> Self {
+        match value {
+            1 => Self::Test,
+            2 => Self::Generated,
+            3 => Self::Vendored,
+            4 => Self::Documentation,
+            _ => Self::Source,
+        }
+    }
+}
+
+/// The `linguist-*` attributes set in a repository's `.gitattributes`.
+#[derive(Default, Debug)]
+pub struct Attributes {
+    generated: Option<Gitignore>,
+    vendored: Option<Gitignore>,
+    documentation: Option<Gitignore>,
+}
+
+impl Attributes {
+    /// Load the attributes in the `.gitattributes` file at the root of a repository, if any.
+    pub fn load(repo_root: &Path) -> Self {
+        match std::fs::read_to_string(repo_root.join(".gitattributes")) {
+            Ok(text) => Self::parse(repo_root, &text),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn parse(repo_root: &Path, text: &str) -> Self {
+        let mut generated = GitignoreBuilder::new(repo_root);
+        let mut vendored = GitignoreBuilder::new(repo_root);
+        let mut documentation = GitignoreBuilder::new(repo_root);
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let Some(pattern) = parts.next() else {
+                continue;
+            };
+
+            // negative patterns are forbidden in `.gitattributes`, so the only use of `!` here is
+            // to mark an attribute as unset for a path
+            if pattern.starts_with('!') || pattern.starts_with('"') {
+                continue;
+            }
+
+            for attr in parts {
+                let (name, set) = match attr.split_once('=') {
+                    Some((name, value)) => (name, value != "false"),
+                    None => match attr.strip_prefix(['-', '!']) {
+                        Some(name) => (name, false),
+                        None => (attr, true),
+                    },
+                };
+
+                let builder = match name {
+                    "linguist-generated" => &mut generated,
+                    "linguist-vendored" => &mut vendored,
+                    "linguist-documentation" => &mut documentation,
+                    _ => continue,
+                };
+
+                let glob = if set {
+                    pattern.to_owned()
+                } else {
+                    format!("!{pattern}")
+                };
+
+                if let Err(err) = builder.add_line(None, &glob) {
+                    warn!(%err, pattern, "invalid pattern in .gitattributes");
+                }
+            }
+        }
+
+        let build = |builder: GitignoreBuilder| builder.build().ok().filter(|g| !g.is_empty());
+        Self {
+            generated: build(generated),
+            vendored: build(vendored),
+            documentation: build(documentation),
+        }
+    }
+
+    fn generated(&self, path: &Path) -> Option<bool> {
+        lookup(self.generated.as_ref(), path)
+    }
+
+    fn vendored(&self, path: &Path) -> Option<bool> {
+        lookup(self.vendored.as_ref(), path)
+    }
+
+    fn documentation(&self, path: &Path) -> Option<bool> {
+        lookup(self.documentation.as_ref(), path)
+    }
+}
+
+/// Whether an attribute is set or unset for a path, or `None` if it isn't mentioned.
+fn lookup(attribute: Option<&Gitignore>, path: &Path) -> Option<bool> {
+    match attribute?.matched_path_or_any_parents(path, false) {
+        Match::Ignore(_) => Some(true),
+        Match::Whitelist(_) => Some(false),
+        Match::None => None,
+    }
+}
+
+/// Whether a path looks like a test file, or is inside a test directory.
+fn is_test_path(path: &[u8]) -> bool {
+    const TEST_DIRS: &[&[u8]] = &[
+        b"test",
+        b"tests",
+        b"spec",
+        b"specs",
+        b"__tests__",
+        b"testdata",
+    ];
+    const TEST_MARKERS: &[&[u8]] = &[b"_test.", b".test.", b"_spec.", b".spec."];
+
+    let mut components = path.split(|&b| b == b'/').rev();
+    let file_name = components.next().unwrap_or_default();
+
+    components.any(|c| TEST_DIRS.contains(&c))
+        || file_name.starts_with(b"test_")
+        || TEST_MARKERS
+            .iter()
+            .any(|m| file_name.windows(m.len()).any(|w| w == *m))
+}
+
+/// Whether a path is inside a directory of third-party code.
+fn is_vendor_path(path: &[u8]) -> bool {
+    const VENDOR_DIRS: &[&[u8]] = &[
+        b"vendor",
+        b"node_modules",
+        b"third_party",
+        b"third-party",
+        b"bower_components",
+    ];
+
+    let mut components = path.split(|&b| b == b'/').rev().skip(1);
+    components.any(|c| VENDOR_DIRS.contains(&c))
+}
+
+/// Whether a file name is one that tools commonly generate, such as protobuf bindings or lock
+/// files.
+fn is_generated_path(path: &[u8]) -> bool {
+    const GENERATED_NAMES: &[&[u8]] = &[
+        b"package-lock.json",
+        b"yarn.lock",
+        b"pnpm-lock.yaml",
+        b"Cargo.lock",
+        b"poetry.lock",
+        b"go.sum",
+    ];
+    const GENERATED_SUFFIXES: &[&[u8]] = &[b".pb.go", b"_pb2.py", b".pb.cc", b".pb.h", b".min.js"];
+
+    let file_name = path.rsplit(|&b| b == b'/').next().unwrap_or_default();
+
+    GENERATED_NAMES.contains(&file_name)
+        || GENERATED_SUFFIXES.iter().any(|s| file_name.ends_with(s))
+}
+
+/// Whether the start of a file carries a marker left by a code generator, such as `@generated` or
+/// Go's `Code generated ... DO NOT EDIT.`
+fn has_generated_header(content: &str) -> bool {
+    let mut end = content.len().min(HEADER_LEN);
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    content[..end].lines().any(|line| {
+        line.contains("@generated")
+            || line.contains("<auto-generated")
+            || (line.contains("generated") && line.contains("DO NOT EDIT"))
+    })
+}
+
+/// Whether a path is documentation, such as a readme or a file in a `docs` directory.
+fn is_docs_path(path: &[u8]) -> bool {
+    const DOCS_DIRS: &[&[u8]] = &[b"doc", b"docs", b"documentation"];
+    const DOCS_NAMES: &[&[u8]] = &[b"README", b"CHANGELOG", b"CONTRIBUTING", b"LICENSE"];
+    const DOCS_EXTENSIONS: &[&[u8]] = &[b".md", b".mdx", b".rst", b".adoc"];
+
+    let mut components = path.split(|&b| b == b'/').rev();
+    let file_name = components.next().unwrap_or_default();
+
+    components.any(|c| DOCS_DIRS.contains(&c))
+        || DOCS_NAMES.iter().any(|n| file_name.starts_with(n))
+        || DOCS_EXTENSIONS.iter().any(|e| file_name.ends_with(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        let tests = [
+            ("src/main.rs", false),
+            ("src/test_utils.rs", true),
+            ("tests/integration.rs", true),
+            ("src/__tests__/App.tsx", true),
+            ("pkg/server/handler_test.go", true),
+            ("client/app.spec.ts", true),
+            ("src/contest.rs", false),
+            ("test", false),
+        ];
+
+        for (path, expected) in tests {
+            assert_eq!(is_test_path(path.as_bytes()), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn vendor_paths() {
+        let tests = [
+            ("src/main.rs", false),
+            ("vendor/foo.rs", true),
+            ("web/node_modules/react/index.js", true),
+            ("src/vendor", false),
+            ("third_party/protobuf/BUILD", true),
+        ];
+
+        for (path, expected) in tests {
+            assert_eq!(is_vendor_path(path.as_bytes()), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn classify() {
+        let attributes = Attributes::default();
+        let classify =
+            |path: &str, content: &str| FileClass::classify(Path::new(path), content, &attributes);
+
+        assert_eq!(classify("src/main.rs", "fn main() {}\n"), FileClass::Source);
+        assert_eq!(classify("tests/parse.rs", "#[test]\n"), FileClass::Test);
+        assert_eq!(classify("api/api.pb.go", ""), FileClass::Generated);
+        assert_eq!(classify("Cargo.lock", ""), FileClass::Generated);
+        assert_eq!(
+            classify("src/schema.rs", "// @generated by diesel\nuse diesel;\n"),
+            FileClass::Generated
+        );
+        assert_eq!(
+            classify("db/models.go", "// Code generated by sqlc. DO NOT EDIT.\n"),
+            FileClass::Generated
+        );
+        assert_eq!(classify("vendor/lib/test_foo.c", ""), FileClass::Vendored);
+        assert_eq!(classify("README.md", "# bloop\n"), FileClass::Documentation);
+        assert_eq!(
+            classify("docs/guide/intro.txt", ""),
+            FileClass::Documentation
+        );
+    }
+
+    #[test]
+    fn gitattributes() {
+        let attributes = Attributes::parse(
+            Path::new("/repo"),
+            "# overrides\n\
+             *.gen.ts linguist-generated\n\
+             assets/** linguist-vendored=true\n\
+             vendor/ours/** -linguist-vendored\n\
+             notes/*.txt linguist-documentation\n\
+             README.md linguist-documentation=false\n",
+        );
+
+        let classify = |path: &str| FileClass::classify(Path::new(path), "", &attributes);
+
+        assert_eq!(classify("src/api.gen.ts"), FileClass::Generated);
+        assert_eq!(classify("assets/js/app.js"), FileClass::Vendored);
+        assert_eq!(classify("vendor/ours/lib.rs"), FileClass::Source);
+        assert_eq!(classify("vendor/theirs/lib.rs"), FileClass::Vendored);
+        assert_eq!(classify("notes/todo.txt"), FileClass::Documentation);
+        assert_eq!(classify("README.md"), FileClass::Source);
+    }
+
+    #[test]
+    fn fast_field_values() {
+        for class in [
+            FileClass::Source,
+            FileClass::Test,
+            FileClass::Generated,
+            FileClass::Vendored,
+            FileClass::Documentation,
+        ] {
+            assert_eq!(FileClass::from_u64(class.as_u64()), class);
+        }
+    }
+}
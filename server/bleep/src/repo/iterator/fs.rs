@@ -58,11 +58,13 @@ impl FileSource for FileWalker {
                         buffer,
                         path: entry_disk_path.to_string_lossy().to_string(),
                         branches: vec![HEAD.into()],
+                        last_commit: None,
                     }))
                 } else if entry_disk_path.is_dir() {
                     Some(RepoDirEntry::Dir(RepoDir {
                         path: entry_disk_path.to_string_lossy().to_string(),
                         branches: vec![HEAD.into()],
+                        last_commit: None,
                     }))
                 } else {
                     Some(RepoDirEntry::Other)
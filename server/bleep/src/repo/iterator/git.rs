@@ -5,10 +5,10 @@ use super::*;
 use anyhow::Result;
 use gix::ThreadSafeRepository;
 use regex::RegexSet;
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeSet, HashMap, HashSet},
     path::Path,
 };
 
@@ -34,6 +34,10 @@ impl Default for BranchFilter {
     }
 }
 
+/// The maximum number of commits to walk through when looking for the last commit that modified
+/// each file.
+const MAX_HISTORY_DEPTH: usize = 1000;
+
 fn human_readable_branch_name(r: &gix::Reference<'_>) -> String {
     use gix::bstr::ByteSlice;
     r.name().shorten().to_str_lossy().to_string()
@@ -42,6 +46,7 @@ fn human_readable_branch_name(r: &gix::Reference<'_>) -> String {
 pub struct GitWalker {
     git: ThreadSafeRepository,
     entries: HashMap<(String, FileType, gix::ObjectId), BTreeSet<String>>,
+    last_commits: HashMap<String, LastCommit>,
 }
 
 impl GitWalker {
@@ -169,8 +174,87 @@ impl GitWalker {
                 },
             );
 
-        Ok(Self { git, entries })
+        let last_commits = last_commits(&local_git, root_dir).unwrap_or_else(|err| {
+            warn!(%err, "failed to read commit history");
+            HashMap::new()
+        });
+
+        Ok(Self {
+            git,
+            entries,
+            last_commits,
+        })
+    }
+}
+
+/// Find the last commit that modified each file of the tree at HEAD.
+///
+/// This follows the first parent of each commit, diffing its tree against that of its parent,
+/// and stops as soon as every file has been attributed. Files that were not modified in the last
+/// `MAX_HISTORY_DEPTH` commits are left out, as their last commit is unknown.
+fn last_commits(git: &gix::Repository, root_dir: &Path) -> Result<HashMap<String, LastCommit>> {
+    use gix::object::tree::diff::Action;
+
+    let mut commit = git.head()?.peel_to_commit_in_place()?;
+    let mut pending = commit
+        .tree()?
+        .traverse()
+        .breadthfirst
+        .files()?
+        .into_iter()
+        .filter(|entry| !entry.mode.is_tree())
+        .map(|entry| entry.filepath)
+        .collect::<HashSet<_>>();
+    let mut commits = HashMap::new();
+
+    for _ in 0..MAX_HISTORY_DEPTH {
+        let author = commit.author()?;
+        let last = LastCommit {
+            hash: commit.id().to_string(),
+            author: format!("{} <{}>", author.name, author.email),
+            unix_secs: commit.time()?.seconds,
+        };
+
+        let parent = match commit.parent_ids().next() {
+            Some(id) => Some(id.object()?.try_into_commit()?),
+            None => None,
+        };
+
+        let parent_tree = match &parent {
+            Some(parent) => parent.tree()?,
+            None => git.empty_tree(),
+        };
+
+        // anything that differs from the parent commit was last modified here
+        commit
+            .tree()?
+            .changes()?
+            .track_path()
+            .track_rewrites(None)
+            .for_each_to_obtain_tree(&parent_tree, |change| {
+                if !change.event.entry_mode().is_tree() {
+                    if let Some(path) = pending.take(change.location) {
+                        commits.insert(path, last.clone());
+                    }
+                }
+
+                Ok::<_, std::convert::Infallible>(Action::Continue)
+            })?;
+
+        match parent {
+            Some(parent) if !pending.is_empty() => commit = parent,
+            _ => break,
+        }
     }
+
+    Ok(commits
+        .into_iter()
+        .map(|(path, commit)| {
+            let strpath = String::from_utf8_lossy(path.as_ref());
+            let full_path = root_dir.join(strpath.as_ref());
+            (full_path.to_string_lossy().to_string(), commit)
+        })
+        .collect())
 }
 
 impl FileSource for GitWalker {
@@ -194,6 +278,12 @@ impl FileSource for GitWalker {
                     return None;
                 }
 
+                // history is only walked from HEAD, so versions of a file that only exist on other
+                // branches were last modified by some other commit
+                let last_commit = branches
+                    .contains("HEAD")
+                    .then(|| self.last_commits.get(&path).cloned())
+                    .flatten();
                 let entry = match kind {
                     FileType::File => {
                         let buffer = String::from_utf8_lossy(&object.data).to_string();
@@ -201,11 +291,13 @@ impl FileSource for GitWalker {
                             path,
                             branches: branches.into_iter().collect(),
                             buffer,
+                            last_commit,
                         })
                     }
                     FileType::Dir => RepoDirEntry::Dir(RepoDir {
                         path,
                         branches: branches.into_iter().collect(),
+                        last_commit,
                     }),
                     FileType::Other => return None,
                 };
@@ -25,6 +25,7 @@ use tracing::{debug, info, trace, warn};
 
 pub mod chunk;
 pub mod execute;
+pub mod hybrid;
 mod schema;
 
 pub use schema::{Embedding, Payload};
@@ -331,10 +332,7 @@ impl Semantic {
                 with_payload: Some(WithPayloadSelector {
                     selector_options: Some(with_payload_selector::SelectorOptions::Enable(true)),
                 }),
-                filter: Some(Filter {
-                    must: build_conditions(parsed_query),
-                    ..Default::default()
-                }),
+                filter: Some(build_conditions(parsed_query)),
                 with_vectors: Some(WithVectorsSelector {
                     selector_options: Some(with_vectors_selector::SelectorOptions::Enable(true)),
                 }),
@@ -366,7 +364,7 @@ impl Semantic {
 
         // Queries should contain the same filters, so we get the first one
         let parsed_query = parsed_queries.first().unwrap();
-        let filters = &build_conditions(parsed_query);
+        let filter = &build_conditions(parsed_query);
 
         let responses = stream::iter(vectors.into_iter())
             .map(|vector| async move {
@@ -381,10 +379,7 @@ impl Semantic {
                             true,
                         )),
                     }),
-                    filter: Some(Filter {
-                        must: filters.clone(),
-                        ..Default::default()
-                    }),
+                    filter: Some(filter.clone()),
                     with_vectors: Some(WithVectorsSelector {
                         selector_options: Some(with_vectors_selector::SelectorOptions::Enable(
                             true,
@@ -615,18 +610,19 @@ fn make_kv_text_filter(key: &str, value: &str) -> FieldCondition {
     }
 }
 
-fn build_conditions(query: &SemanticQuery<'_>) -> Vec<qdrant_client::qdrant::Condition> {
+fn repo_name_filter(repo: &str) -> FieldCondition {
+    if repo.contains('/') && !repo.starts_with("github.com/") {
+        make_kv_keyword_filter("repo_name", &format!("github.com/{repo}"))
+    } else {
+        make_kv_keyword_filter("repo_name", repo)
+    }
+}
+
+fn build_conditions(query: &SemanticQuery<'_>) -> Filter {
     let repo_filter = {
         let conditions = query
             .repos()
-            .map(|r| {
-                if r.contains('/') && !r.starts_with("github.com/") {
-                    format!("github.com/{r}")
-                } else {
-                    r.to_string()
-                }
-            })
-            .map(|r| make_kv_keyword_filter("repo_name", r.as_ref()).into())
+            .map(|r| repo_name_filter(r.as_ref()).into())
             .collect::<Vec<_>>();
         // one of the above repos should match
         if conditions.is_empty() {
@@ -686,13 +682,39 @@ fn build_conditions(query: &SemanticQuery<'_>) -> Vec<qdrant_client::qdrant::Con
         }
     };
 
-    let filters: Vec<_> = [repo_filter, path_filter, lang_filter, branch_filter]
+    let must = [repo_filter, path_filter, lang_filter, branch_filter]
         .into_iter()
         .flatten()
         .map(Into::into)
         .collect();
 
-    filters
+    // a point matching any of the excluded filters is dropped
+    let must_not = query
+        .excluded_repos()
+        .map(|r| repo_name_filter(r.as_ref()))
+        .chain(
+            query
+                .excluded_paths()
+                .map(|p| make_kv_text_filter("relative_path", p.as_ref())),
+        )
+        .chain(
+            query
+                .excluded_langs()
+                .map(|l| make_kv_keyword_filter("lang", l.as_ref())),
+        )
+        .chain(
+            query
+                .excluded_branches()
+                .map(|b| make_kv_keyword_filter("branches", b.as_ref())),
+        )
+        .map(Into::into)
+        .collect();
+
+    Filter {
+        must,
+        must_not,
+        ..Default::default()
+    }
 }
 
 fn dot(a: &[f32], b: &[f32]) -> f32 {
@@ -61,6 +61,7 @@ pub async fn execute(
         count: data.len(),
         metadata: PagingMetadata::new(params.page, params.page_size, None),
         stats: ResultStats::default(),
+        suggestions: vec![],
         data,
     })
 }
@@ -0,0 +1,371 @@
+//! Hybrid search, fusing the results of a lexical and a semantic search for the same target.
+//!
+//! Both searches rank files independently, and their rankings are combined with reciprocal rank
+//! fusion, so that a file ranked highly by either search ends up near the top of the results.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
+
+use anyhow::Result;
+
+use super::{schema::Payload, Semantic};
+use crate::{
+    indexes::Indexes,
+    query::{
+        execute::{ApiQuery, PagingMetadata, QueryResponse, QueryResult, ResultStats},
+        languages,
+        parser::{Expression, Literal, Query, QueryExpr, SemanticQuery, Target},
+    },
+    snippet::{SnippedFile, Snippet},
+};
+
+/// Constant added to every rank before fusing, dampening the weight of the top few results.
+///
+/// 60 is the value used in the original paper on reciprocal rank fusion, and works well across a
+/// wide range of result lists.
+const RRF_K: f32 = 60.0;
+
+/// Number of results fetched from each search before fusing them.
+///
+/// The fused ranking depends on how deep both lists go, so every page is cut from the same fused
+/// list, rather than fetching deeper lists for later pages, which would reorder earlier pages.
+const FUSION_DEPTH: usize = 200;
+
+/// Words shorter than this are too common to narrow down a lexical search.
+const MIN_WORD_LEN: usize = 3;
+
+/// English words that are long enough to pass `MIN_WORD_LEN`, but would still match nearly every
+/// file in a lexical search.
+const STOPWORDS: &[&str] = &[
+    "about", "after", "all", "also", "and", "any", "are", "been", "before", "being", "but", "can",
+    "could", "did", "does", "doing", "each", "for", "from", "had", "has", "have", "her", "here",
+    "his", "how", "into", "its", "more", "most", "not", "only", "other", "our", "out", "over",
+    "should", "some", "such", "than", "that", "the", "their", "them", "then", "there", "these",
+    "they", "this", "those", "through", "under", "very", "was", "were", "what", "when", "where",
+    "which", "while", "who", "whom", "why", "will", "with", "would", "you", "your",
+];
+
+pub async fn execute(
+    semantic: Semantic,
+    indexes: Arc<Indexes>,
+    query: SemanticQuery<'_>,
+    params: ApiQuery,
+) -> Result<QueryResponse> {
+    // pages are cut from a fused list of a fixed depth, which holds at least a single page
+    let depth = FUSION_DEPTH.max(params.page_size);
+
+    let lexical = async {
+        let Some(expr) = lexical_query(&query) else {
+            return Ok((vec![], ResultStats::default()));
+        };
+
+        let response = Arc::new(params.first(depth))
+            .query_with(indexes, expr)
+            .await?;
+        let files = response
+            .data
+            .into_iter()
+            .filter_map(|result| match result {
+                QueryResult::Snippets(file) => Some(file),
+                _ => None,
+            })
+            .collect();
+
+        Ok::<_, anyhow::Error>((files, response.stats))
+    };
+
+    let semantic = async { semantic.search(&query, depth as u64, 0, 0.0, false).await };
+
+    let ((lexical, mut stats), semantic) = futures::try_join!(lexical, semantic)?;
+    let semantic = group_by_file(semantic);
+
+    // the lexical stats cover every file it matched, so only the files that the semantic search
+    // found on its own are left to add
+    let lexical_files = lexical
+        .iter()
+        .map(|f| (&f.repo_ref, &f.relative_path))
+        .collect::<HashSet<_>>();
+    for file in semantic
+        .iter()
+        .filter(|f| !lexical_files.contains(&(&f.repo_ref, &f.relative_path)))
+    {
+        *stats.repo.entry(file.repo_name.clone()).or_default() += 1;
+        if let Some(lang) = &file.lang {
+            let lang = languages::proper_case(lang.into()).into_owned();
+            *stats.lang.entry(lang).or_default() += 1;
+        }
+    }
+
+    // only the fused files can be paged through
+    let fused = fuse(lexical, semantic);
+    let total_count = fused.len();
+
+    let data = fused
+        .into_iter()
+        .skip(params.page * params.page_size)
+        .take(params.page_size)
+        .map(QueryResult::Snippets)
+        .collect::<Vec<_>>();
+
+    Ok(QueryResponse {
+        count: data.len(),
+        metadata: PagingMetadata::new(params.page, params.page_size, Some(total_count)),
+        stats,
+        suggestions: vec![],
+        data,
+    })
+}
+
+/// Build a lexical query out of a semantic query, matching files that contain any of the words in
+/// its target, with the same repository, path, language and branch filters.
+///
+/// Returns `None` if the target has no words that are worth searching for.
+fn lexical_query<'a>(query: &SemanticQuery<'a>) -> Option<QueryExpr<'a>> {
+    let target = query.target()?;
+
+    let mut words = target
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '_'))
+        .filter(|w| w.chars().count() >= MIN_WORD_LEN)
+        .filter(|w| !STOPWORDS.contains(&w.to_lowercase().as_str()))
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    words.sort();
+    words.dedup();
+
+    if words.is_empty() {
+        return None;
+    }
+
+    let exclude = query
+        .excluded_repos
+        .iter()
+        .map(|r| Query {
+            repo: Some(r.clone()),
+            ..Default::default()
+        })
+        .chain(query.excluded_paths.iter().map(|p| Query {
+            paths: [p.clone()].into(),
+            ..Default::default()
+        }))
+        .chain(query.excluded_langs.iter().map(|l| Query {
+            langs: [l.clone()].into(),
+            ..Default::default()
+        }))
+        .chain(query.excluded_branches.iter().map(|b| Query {
+            branches: [b.clone()].into(),
+            ..Default::default()
+        }))
+        .collect::<Vec<_>>();
+
+    let filters = Query {
+        paths: query.paths.clone(),
+        langs: query.langs.clone(),
+        branches: query.branch.clone(),
+        exclude,
+        ..Default::default()
+    };
+
+    // a query can only name a single repository, so searching in several of them takes one term
+    // per repository
+    let repos = if query.repos.is_empty() {
+        vec![None]
+    } else {
+        query.repos.iter().cloned().map(Some).collect()
+    };
+
+    let terms = words
+        .iter()
+        .flat_map(|word| {
+            repos.iter().map(|repo| {
+                Expression::Term(Query {
+                    repo: repo.clone(),
+                    target: Some(Target::Content(Literal::Plain(Cow::Owned(word.clone())))),
+                    ..filters.clone()
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Some(Expression::Or(terms))
+}
+
+/// Group semantic search results by file, in the order each file first appears.
+fn group_by_file(payloads: Vec<Payload>) -> Vec<SnippedFile> {
+    let mut positions = HashMap::new();
+    let mut files: Vec<SnippedFile> = Vec::new();
+
+    for payload in payloads {
+        let key = (payload.repo_ref.clone(), payload.relative_path.clone());
+        let snippet = Snippet {
+            data: payload.text,
+            line_range: payload.start_line as usize..payload.end_line as usize,
+            highlights: vec![],
+            symbols: vec![],
+        };
+
+        match positions.get(&key) {
+            Some(&i) => files[i].snippets.push(snippet),
+            None => {
+                positions.insert(key, files.len());
+                files.push(SnippedFile {
+                    relative_path: payload.relative_path,
+                    repo_name: payload.repo_name,
+                    repo_ref: payload.repo_ref,
+                    lang: Some(payload.lang),
+                    snippets: vec![snippet],
+                });
+            }
+        }
+    }
+
+    files
+}
+
+/// Fuse two ranked lists of files with reciprocal rank fusion.
+///
+/// Files found by both searches keep their lexical snippets, which are highlighted, plus any
+/// semantic snippets that cover different lines. Ties are broken in favour of lexical results.
+fn fuse(lexical: Vec<SnippedFile>, semantic: Vec<SnippedFile>) -> Vec<SnippedFile> {
+    let mut positions = HashMap::new();
+    let mut fused: Vec<(f32, SnippedFile)> = Vec::new();
+
+    for list in [lexical, semantic] {
+        for (rank, file) in list.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f32 + 1.0);
+            let key = (file.repo_ref.clone(), file.relative_path.clone());
+
+            match positions.get(&key) {
+                Some(&i) => {
+                    let (total, existing) = &mut fused[i];
+                    *total += score;
+
+                    for snippet in file.snippets {
+                        if !existing
+                            .snippets
+                            .iter()
+                            .any(|s| overlaps(&s.line_range, &snippet.line_range))
+                        {
+                            existing.snippets.push(snippet);
+                        }
+                    }
+                }
+                None => {
+                    positions.insert(key, fused.len());
+                    fused.push((score, file));
+                }
+            }
+        }
+    }
+
+    // a stable sort keeps lexical results first when scores are equal
+    fused.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    fused.into_iter().map(|(_, file)| file).collect()
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parser::{self, ParsedQuery};
+
+    fn file(path: &str, lines: &[Range<usize>]) -> SnippedFile {
+        SnippedFile {
+            relative_path: path.to_owned(),
+            repo_name: "bloop".to_owned(),
+            repo_ref: "github.com/bloopai/bloop".to_owned(),
+            lang: Some("Rust".to_owned()),
+            snippets: lines
+                .iter()
+                .map(|line_range| Snippet {
+                    data: String::new(),
+                    line_range: line_range.clone(),
+                    highlights: vec![],
+                    symbols: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    fn paths(files: &[SnippedFile]) -> Vec<&str> {
+        files.iter().map(|f| f.relative_path.as_str()).collect()
+    }
+
+    #[test]
+    fn fuse_rankings() {
+        let lexical = vec![
+            file("parser.rs", &[10..12]),
+            file("compiler.rs", &[1..3]),
+            file("lexer.rs", &[5..6]),
+        ];
+        let semantic = vec![
+            file("grammar.pest", &[0..20]),
+            file("compiler.rs", &[2..4, 40..60]),
+            file("parser.rs", &[100..120]),
+        ];
+
+        let fused = fuse(lexical, semantic);
+
+        // found by both searches, so they outrank results found by only one of them
+        assert_eq!(
+            paths(&fused),
+            ["parser.rs", "compiler.rs", "grammar.pest", "lexer.rs"]
+        );
+
+        // semantic snippets that overlap a lexical one are dropped
+        assert_eq!(
+            fused[1]
+                .snippets
+                .iter()
+                .map(|s| s.line_range.clone())
+                .collect::<Vec<_>>(),
+            [1..3, 40..60]
+        );
+        assert_eq!(fused[0].snippets.len(), 2);
+    }
+
+    #[test]
+    fn lexical_query_from_semantic() {
+        let ParsedQuery::Hybrid(query) =
+            parser::parse_nl("where is the parse_nl fn? repo:bloop -path:tests mode:hybrid")
+                .unwrap()
+        else {
+            panic!("not a hybrid query");
+        };
+
+        let filters = Query {
+            repo: Some(Literal::Plain("bloop".into())),
+            exclude: vec![Query {
+                paths: [Literal::Plain("tests".into())].into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let term = |word: &str| {
+            Expression::Term(Query {
+                target: Some(Target::Content(Literal::Plain(word.to_owned().into()))),
+                ..filters.clone()
+            })
+        };
+
+        assert_eq!(
+            lexical_query(&query).unwrap(),
+            Expression::Or(vec![term("parse_nl")]),
+        );
+
+        let ParsedQuery::Hybrid(query) =
+            parser::parse_nl("Where are those that did it? mode:hybrid").unwrap()
+        else {
+            panic!("not a hybrid query");
+        };
+        assert_eq!(lexical_query(&query), None);
+    }
+}
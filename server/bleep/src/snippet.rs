@@ -4,7 +4,7 @@ use serde::Serialize;
 use smallvec::{smallvec, SmallVec};
 
 use crate::{indexes, symbol::Symbol};
-use std::ops::Range;
+use std::{borrow::Cow, collections::HashSet, ops::Range};
 
 #[derive(Serialize, Debug, PartialEq, Eq)]
 pub struct SnippedFile {
@@ -54,9 +54,11 @@ impl Location {
             return Err(rhs);
         }
 
+        // Highlights can be nested, for example when highlighting syntax nodes, so the joined
+        // location must still cover the entirety of `self`.
         let offset = rhs.byte_range.start - self.byte_range.start;
-        self.line_range.end = rhs.line_range.end;
-        self.byte_range.end = rhs.byte_range.end;
+        self.line_range.end = self.line_range.end.max(rhs.line_range.end);
+        self.byte_range.end = self.byte_range.end.max(rhs.byte_range.end);
         self.highlights
             .extend(rhs.highlights.into_iter().map(|mut h| {
                 h.start += offset;
@@ -151,15 +153,62 @@ impl Snipper {
             .build()?;
 
         let snippets = if self.find_symbols {
-            // a symbol search should perform an intersection of
-            // search results with the symbol list present in a document.
-            //
-            let mut symbols = doc.symbol_locations.list();
-            let symbol_ranges = symbols
-                .iter()
-                .map(|sym| sym.range.into())
-                .collect::<Vec<Range<usize>>>();
+            self.symbol_snippets(Some(&query), &HashSet::new(), doc)
+        } else {
+            let highlights = query.find_iter(&doc.content).map(|m| m.range());
+            self.expand_many(highlights.into_iter(), &doc.content, &doc.line_end_indices)
+                .map(|loc| loc.reify(&doc.content, &[]))
+                .collect::<Vec<_>>()
+        };
+
+        Ok(Self::snipped_file(doc, snippets))
+    }
+
+    /// Create snippets for the symbols in a document that are of one of the given kinds.
+    ///
+    /// If a regex is supplied, only the parts of symbols matching it are highlighted. Otherwise,
+    /// every symbol of these kinds is highlighted in its entirety.
+    pub fn symbols_for_doc(
+        &self,
+        regex: Option<&str>,
+        kinds: &HashSet<Cow<'_, str>>,
+        doc: &indexes::reader::ContentDocument,
+    ) -> Result<Option<SnippedFile>> {
+        let query = regex
+            .map(|regex| {
+                RegexBuilder::new(regex)
+                    .multi_line(true)
+                    .case_insensitive(!self.case_sensitive)
+                    .build()
+            })
+            .transpose()?;
 
+        let snippets = self.symbol_snippets(query.as_ref(), kinds, doc);
+        Ok(Self::snipped_file(doc, snippets))
+    }
+
+    fn symbol_snippets(
+        &self,
+        query: Option<&Regex>,
+        kinds: &HashSet<Cow<'_, str>>,
+        doc: &indexes::reader::ContentDocument,
+    ) -> Vec<Snippet> {
+        // a symbol search should perform an intersection of
+        // search results with the symbol list present in a document.
+        //
+        let mut symbols = doc.symbol_locations.list();
+
+        // searches like `symbol:function:parse` only consider symbols of that kind
+        if !kinds.is_empty() {
+            symbols.retain(|sym| kinds.contains(sym.kind.as_str()));
+        }
+
+        let mut symbol_ranges = symbols
+            .iter()
+            .map(|sym| sym.range.into())
+            .collect::<Vec<Range<usize>>>();
+
+        let highlights = match query {
             // limit highlights to only symbols
             //
             // for a search query of `symbol:n` on this text:
@@ -175,7 +224,7 @@ impl Snipper {
             //    const cool_beans = beans();
             //      ^           ^       ^-- incorrect
             //
-            let highlights = query
+            Some(query) => query
                 .find_iter(&doc.content)
                 .map(|m| m.range())
                 .filter(|hl_range| {
@@ -183,34 +232,56 @@ impl Snipper {
                         hl_range.start >= sym_range.start && hl_range.end <= sym_range.end
                     })
                 })
-                .collect::<Vec<Range<usize>>>();
+                .collect::<Vec<Range<usize>>>(),
 
-            // limit symbols to only those in our highlight list
-            //
-            // for a search query of `symbol:loud` on this text:
-            //
-            //    const (loud, clear) = audio();
-            //
-            // the symbols returned should be just `loud`, even though `clear`
-            // is also a symbol present in the same snippet.
-            symbols.retain(|sym_range| {
-                highlights.iter().any(|hl_range| {
-                    hl_range.start >= sym_range.range.start.byte
-                        && hl_range.end <= sym_range.range.end.byte
-                })
-            });
-
-            self.expand_many(highlights.into_iter(), &doc.content, &doc.line_end_indices)
-                .map(|loc| loc.reify(&doc.content, &symbols))
-                .collect::<Vec<_>>()
-        } else {
-            let highlights = query.find_iter(&doc.content).map(|m| m.range());
-            self.expand_many(highlights.into_iter(), &doc.content, &doc.line_end_indices)
-                .map(|loc| loc.reify(&doc.content, &[]))
-                .collect::<Vec<_>>()
+            // with only a kind to look for, as in `kind:struct`, highlight every symbol
+            None => {
+                symbol_ranges.sort_by_key(|r| (r.start, r.end));
+                symbol_ranges
+            }
         };
 
-        Ok(if snippets.is_empty() {
+        // limit symbols to only those in our highlight list
+        //
+        // for a search query of `symbol:loud` on this text:
+        //
+        //    const (loud, clear) = audio();
+        //
+        // the symbols returned should be just `loud`, even though `clear`
+        // is also a symbol present in the same snippet.
+        symbols.retain(|sym_range| {
+            highlights.iter().any(|hl_range| {
+                hl_range.start >= sym_range.range.start.byte
+                    && hl_range.end <= sym_range.range.end.byte
+            })
+        });
+
+        self.expand_many(highlights.into_iter(), &doc.content, &doc.line_end_indices)
+            .map(|loc| loc.reify(&doc.content, &symbols))
+            .collect::<Vec<_>>()
+    }
+
+    /// Create snippets for a list of highlighted byte ranges in a document.
+    ///
+    /// Highlights must be sorted by their start position, but they may overlap.
+    pub fn all_for_ranges(
+        &self,
+        highlights: Vec<Range<usize>>,
+        doc: &indexes::reader::ContentDocument,
+    ) -> Option<SnippedFile> {
+        let snippets = self
+            .expand_many(highlights.into_iter(), &doc.content, &doc.line_end_indices)
+            .map(|loc| loc.reify(&doc.content, &[]))
+            .collect::<Vec<_>>();
+
+        Self::snipped_file(doc, snippets)
+    }
+
+    fn snipped_file(
+        doc: &indexes::reader::ContentDocument,
+        snippets: Vec<Snippet>,
+    ) -> Option<SnippedFile> {
+        if snippets.is_empty() {
             None
         } else {
             Some(SnippedFile {
@@ -220,7 +291,7 @@ impl Snipper {
                 lang: doc.lang.clone(),
                 snippets,
             })
-        })
+        }
     }
 
     fn expand_many<'a>(
@@ -267,10 +338,18 @@ impl Snipper {
             .map(|(i, _)| i + 1)
             .unwrap_or(0);
 
-        let end = text[highlight.end..]
+        // Multi-line matches can end in a line break, as in `foo\n`, in which case the snippet
+        // ends on that line rather than the next one.
+        let last = if highlight.end > highlight.start && text[..highlight.end].ends_with('\n') {
+            highlight.end - 1
+        } else {
+            highlight.end
+        };
+
+        let end = text[last..]
             .match_indices('\n')
             .nth(self.context_after)
-            .map(|(i, _)| i + highlight.end)
+            .map(|(i, _)| i + last)
             .unwrap_or(text.len());
 
         let line_end = line_ends
@@ -288,7 +367,7 @@ impl Snipper {
         Location {
             byte_range: start..end,
             line_range: line_start..line_end,
-            highlights: smallvec![(highlight.start - start)..(highlight.end - start)],
+            highlights: smallvec![(highlight.start - start)..(highlight.end.min(end) - start)],
         }
     }
 }
@@ -611,6 +690,126 @@ mod tests {
             .is_some());
     }
 
+    #[test]
+    fn multiline_regex() {
+        let (text, line_end_indices) =
+            with_line_ends("struct Foo;\nfn parse(\n    self,\n) {}\nfn main() {}\n");
+        let doc = indexes::reader::ContentDocument {
+            content: text.into(),
+            line_end_indices,
+            ..Default::default()
+        };
+
+        let observed = Snipper::default()
+            .all_for_doc(r"fn\s+\w+\(\s*\n\s*self", &doc)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            observed.snippets,
+            vec![Snippet {
+                data: "fn parse(\n    self,".into(),
+                line_range: 1..2,
+                highlights: vec![0..18],
+                symbols: vec![],
+            }]
+        );
+
+        // a match ending in a line break doesn't pull in the next line
+        let observed = Snipper::default()
+            .all_for_doc(r"\(\n", &doc)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            observed.snippets,
+            vec![Snippet {
+                data: "fn parse(".into(),
+                line_range: 1..1,
+                highlights: vec![8..9],
+                symbols: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn whole_words() {
+        let (text, line_end_indices) = with_line_ends("let valid = id;\n");
+        let doc = indexes::reader::ContentDocument {
+            content: text.into(),
+            line_end_indices,
+            ..Default::default()
+        };
+
+        let observed = Snipper::default()
+            .all_for_doc(r"\b(?:id)\b", &doc)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(observed.snippets[0].highlights, vec![12..14]);
+    }
+
+    #[test]
+    fn nested_highlights() {
+        let (text, line_end_indices) = with_line_ends("fn foo() {\n    bar();\n}\nfn baz() {}\n");
+        let doc = indexes::reader::ContentDocument {
+            content: text.into(),
+            line_end_indices,
+            ..Default::default()
+        };
+
+        // a function node, and its name nested inside
+        let observed = Snipper::default()
+            .all_for_ranges(vec![0..23, 3..6], &doc)
+            .unwrap();
+
+        assert_eq!(
+            observed.snippets,
+            vec![Snippet {
+                data: "fn foo() {\n    bar();\n}".into(),
+                line_range: 0..2,
+                highlights: vec![0..23, 3..6],
+                symbols: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn symbol_kinds() {
+        use crate::{intelligence::TreeSitterFile, symbol::SymbolLocations};
+
+        let (text, line_end_indices) = with_line_ends("fn parse() {}\nstruct Parser;\n");
+        let graph = TreeSitterFile::try_build(text.as_bytes(), "Rust")
+            .and_then(TreeSitterFile::scope_graph)
+            .unwrap();
+        let doc = indexes::reader::ContentDocument {
+            content: text.into(),
+            line_end_indices,
+            symbol_locations: SymbolLocations::TreeSitter(graph),
+            ..Default::default()
+        };
+
+        let observed = Snipper::default()
+            .symbols_for_doc(Some("Pars"), &["struct".into()].into(), &doc)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(observed.snippets.len(), 1);
+        assert_eq!(observed.snippets[0].data, "struct Parser;");
+        assert_eq!(observed.snippets[0].highlights, vec![7..11]);
+        assert_eq!(observed.snippets[0].symbols[0].kind, "struct");
+
+        // without a regex, every symbol of the kind is highlighted
+        let observed = Snipper::default()
+            .symbols_for_doc(None, &["function".into()].into(), &doc)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(observed.snippets.len(), 1);
+        assert_eq!(observed.snippets[0].data, "fn parse() {}");
+        assert_eq!(observed.snippets[0].highlights, vec![3..8]);
+    }
+
     #[test]
     fn test_highlighted_string() {
         let mut s = HighlightedString::new("foo bar quux");
@@ -1,6 +1,11 @@
-use crate::{env::Feature, Application};
+use crate::{
+    env::Feature,
+    query::{cursor::CursorError, facets::UnknownFacet, parser::ParseError, replace::ReplaceError},
+    Application,
+};
 
 use axum::{
+    extract::DefaultBodyLimit,
     http::StatusCode,
     response::IntoResponse,
     routing::{get, post},
@@ -20,7 +25,7 @@ mod file;
 mod github;
 mod hoverable;
 mod index;
-mod intelligence;
+pub(crate) mod intelligence;
 pub mod middleware;
 mod query;
 pub mod repos;
@@ -44,6 +49,10 @@ pub async fn start(app: Application) -> anyhow::Result<()> {
         .route("/config", get(config::get).put(config::put))
         // querying
         .route("/q", get(query::handle))
+        .route("/q/stream", get(query::stream))
+        .route("/q/explain", get(query::explain))
+        .route("/q/replace", get(query::replace))
+        .route("/q/export", get(query::export))
         // autocomplete
         .route("/autocomplete", get(autocomplete::handle))
         // indexing
@@ -53,6 +62,12 @@ pub async fn start(app: Application) -> anyhow::Result<()> {
         // intelligence
         .route("/hoverable", get(hoverable::handle))
         .route("/token-info", get(intelligence::handle))
+        // SCIP indexes of large repositories run into hundreds of megabytes
+        .route(
+            "/scip",
+            post(intelligence::upload_scip).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/scip/export", get(intelligence::export_scip))
         // misc
         .route("/search", get(semantic::complex_search))
         .route("/file", get(file::handle))
@@ -194,6 +209,16 @@ impl Error {
 
 impl From<anyhow::Error> for Error {
     fn from(value: anyhow::Error) -> Self {
+        // queries that don't parse, such as invalid structural patterns, cursors or facets that
+        // can't be searched by, and queries that can't be replaced over are the caller's fault
+        if value.downcast_ref::<ParseError>().is_some()
+            || value.downcast_ref::<CursorError>().is_some()
+            || value.downcast_ref::<UnknownFacet>().is_some()
+            || value.downcast_ref::<ReplaceError>().is_some()
+        {
+            return Error::user(value);
+        }
+
         Error::internal(value.to_string())
     }
 }
@@ -1,39 +1,89 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use super::prelude::*;
 use crate::{
     indexes::{
         reader::{ContentReader, FileReader, RepoReader},
-        Indexes,
+        Indexer, Indexes,
     },
     query::{
         execute::{ApiQuery, ExecuteQuery, QueryResult},
         parser,
-        parser::{Literal, Target},
+        parser::{Expression, Literal, Target},
     },
+    repo::BranchFilter,
+    Application,
 };
 
-use axum::{extract::Query, response::IntoResponse as IntoAxumResponse, Extension};
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse as IntoAxumResponse,
+    Extension,
+};
 use futures::{stream, StreamExt, TryStreamExt};
 use serde::Serialize;
+use tantivy::{
+    collector::{MultiCollector, TopDocs},
+    schema::Field,
+};
+
+type RepoFilter = dyn Fn(&str) -> bool + Sync;
+
+/// Maximum number of values suggested for a label
+const MAX_VALUES: usize = 10;
+
+/// Number of files whose symbols are searched for `symbol:` completions
+const SYMBOL_FILES: usize = 50;
+
+#[derive(Deserialize)]
+pub(super) struct AutocompleteParams {
+    /// Position of the cursor in the query, in characters. Defaults to the end of the query.
+    ///
+    /// This is not the paging `cursor` of `ApiQuery`, which autocompletion never uses.
+    cursor_pos: Option<usize>,
+}
 
 pub(super) async fn handle(
     Query(mut api_params): Query<ApiQuery>,
+    Query(params): Query<AutocompleteParams>,
     Extension(indexes): Extension<Arc<Indexes>>,
+    State(app): State<Application>,
 ) -> Result<impl IntoAxumResponse> {
     // Override page_size and set to low value
     api_params.page = 0;
     api_params.page_size = 3;
+    api_params.cursor = None;
 
-    let queries = parser::parse(&api_params.q).map_err(Error::user)?;
     let mut autocomplete_results = vec![];
 
+    // Complete the value of the label under the cursor, bypassing the parser, as the query is
+    // usually incomplete at this point.
+    //
+    //      `repo:bl| api` -> repositories starting with or resembling `bl`
+    //      `lang:p| api` -> languages starting with `p`
+    if let Some((label, prefix)) = value_under_cursor(&api_params.q, params.cursor_pos) {
+        autocomplete_results = complete_value(label, prefix, &api_params.q, &indexes, &app)
+            .await
+            .map_err(Error::internal)?;
+    }
+
+    if !autocomplete_results.is_empty() {
+        return Ok(json(AutocompleteResponse {
+            count: autocomplete_results.len(),
+            data: autocomplete_results,
+        }));
+    }
+
+    let expr = parser::parse(&api_params.q).map_err(Error::user)?;
+
     // Only execute prefix search on flag names if there is a non-regex content target.
-    // Always matches against the last query.
+    // Always matches against the last term.
     //
     //      `la repo:bloop or sy` -> search with prefix `sy`
     //      `repo:bloop re path:src` -> search with prefix `re`
-    if let Some(Target::Content(Literal::Plain(q))) = queries.last().unwrap().target.clone() {
+    if let Some(Target::Content(Literal::Plain(q))) =
+        expr.terms().last().and_then(|q| q.target.clone())
+    {
         autocomplete_results.append(
             &mut complete_flag(&q)
                 .map(|f| QueryResult::Flag(f.to_string()))
@@ -41,26 +91,11 @@ pub(super) async fn handle(
         );
     }
 
-    // Bypass the parser and execute a prefix search using the last whitespace-split token
-    // in the query string.
-    //
-    // This should be revisited when we implement cursor-aware autocomplete.
-    //
-    //      `api lang:p` -> search lang list with prefix `p`
-    //      `lang:p api` -> lang prefix search not triggered
-    if let Some(matched_langs) = complete_lang(&api_params.q) {
-        autocomplete_results.append(
-            &mut matched_langs
-                .map(|l| QueryResult::Lang(l.to_string()))
-                .collect(),
-        );
-    }
-
     // If no flags completion, run a search with full query
     if autocomplete_results.is_empty() {
-        let contents = ContentReader.execute(&indexes.file, &queries, &api_params);
-        let repos = RepoReader.execute(&indexes.repo, &queries, &api_params);
-        let files = FileReader.execute(&indexes.file, &queries, &api_params);
+        let contents = ContentReader.execute(&indexes.file, &expr, &api_params);
+        let repos = RepoReader.execute(&indexes.repo, &expr, &api_params);
+        let files = FileReader.execute(&indexes.file, &expr, &api_params);
 
         autocomplete_results = stream::iter([contents, repos, files])
             // Buffer several readers at the same time. The exact number is not important; this is
@@ -88,16 +123,225 @@ fn complete_flag(q: &str) -> impl Iterator<Item = &str> + '_ {
         .copied()
 }
 
-fn complete_lang(q: &str) -> Option<impl Iterator<Item = &str> + '_> {
-    match q.split_whitespace().last() {
-        Some(last) => last.strip_prefix("lang:").map(|prefix| {
-            COMMON_LANGUAGES
-                .iter()
-                .filter(move |l| l.starts_with(prefix))
-                .copied()
-        }),
-        _ => None,
+/// A label whose values can be completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueLabel {
+    Repo,
+    Path,
+    Branch,
+    Symbol,
+    Lang,
+}
+
+/// Find the label under the cursor, and the part of its value that comes before the cursor.
+///
+/// The cursor is counted in characters, and defaults to the end of the query.
+fn value_under_cursor(q: &str, cursor: Option<usize>) -> Option<(ValueLabel, &str)> {
+    let end = match cursor {
+        Some(cursor) => q.char_indices().nth(cursor).map_or(q.len(), |(i, _)| i),
+        None => q.len(),
+    };
+
+    let start = q[..end].rfind([' ', '(']).map_or(0, |i| i + 1);
+    let token = q[start..end].trim_start_matches('-');
+    let (label, value) = token.split_once(':')?;
+
+    let label = match label {
+        "repo" => ValueLabel::Repo,
+        "path" => ValueLabel::Path,
+        "branch" => ValueLabel::Branch,
+        "symbol" => ValueLabel::Symbol,
+        "lang" => ValueLabel::Lang,
+        _ => return None,
+    };
+
+    // in `symbol:function:pa`, only the name of the symbol is completed
+    let value = match label {
+        ValueLabel::Symbol => value.rsplit(':').next().unwrap_or(value),
+        _ => value,
+    };
+
+    Some((label, value.trim_start_matches('"')))
+}
+
+async fn complete_value(
+    label: ValueLabel,
+    prefix: &str,
+    q: &str,
+    indexes: &Indexes,
+    app: &Application,
+) -> anyhow::Result<Vec<QueryResult>> {
+    // completions are narrowed down to the repositories named elsewhere in the query
+    let repos = q
+        .split_whitespace()
+        .filter_map(|t| t.strip_prefix("repo:"))
+        .filter(|r| !r.is_empty() && *r != prefix)
+        .collect::<Vec<_>>();
+    let in_repo: &RepoFilter =
+        &|name: &str| repos.is_empty() || repos.iter().any(|r| name.contains(r));
+
+    let values = match label {
+        ValueLabel::Repo => {
+            let source = &indexes.repo.source;
+            fast_field_values(&indexes.repo, source.raw_name, None, prefix).await?
+        }
+        ValueLabel::Path => {
+            let source = &indexes.file.source;
+            let repo_filter = (!repos.is_empty()).then_some((source.raw_repo_name, in_repo));
+            fast_field_values(&indexes.file, source.raw_relative_path, repo_filter, prefix).await?
+        }
+        ValueLabel::Branch => {
+            let mut branches = HashMap::new();
+            app.repo_pool.scan(|reporef, repo| {
+                if !in_repo(&reporef.indexed_name()) {
+                    return;
+                }
+
+                if let Some(BranchFilter::Select(selected)) = &repo.branch_filter {
+                    for branch in selected {
+                        if let Some(rank) = rank(branch, prefix) {
+                            branches.insert(branch.clone(), rank);
+                        }
+                    }
+                }
+            });
+            branches
+        }
+        ValueLabel::Symbol => symbols(indexes, repos.first().copied(), prefix).await?,
+        ValueLabel::Lang => COMMON_LANGUAGES
+            .iter()
+            .filter(|l| l.starts_with(prefix))
+            .map(|l| (l.to_string(), (0, l.len())))
+            .collect(),
+    };
+
+    let mut values = values.into_iter().collect::<Vec<_>>();
+    values.sort_by(|(a, a_rank), (b, b_rank)| a_rank.cmp(b_rank).then_with(|| a.cmp(b)));
+
+    Ok(values
+        .into_iter()
+        .take(MAX_VALUES)
+        .map(|(value, _)| match label {
+            ValueLabel::Repo => QueryResult::Repo(value),
+            ValueLabel::Path => QueryResult::Path(value),
+            ValueLabel::Branch => QueryResult::Branch(value),
+            ValueLabel::Symbol => QueryResult::Symbol(value),
+            ValueLabel::Lang => QueryResult::Lang(value),
+        })
+        .collect())
+}
+
+/// Collect the distinct values of a bytes fast field that complete `prefix`, optionally keeping
+/// only documents whose `filter` field is accepted.
+///
+/// Scanning stops as soon as `MAX_VALUES` values start with `prefix`, which outrank any other
+/// match, so that a short or empty prefix doesn't read every document of the index. Shorter
+/// values further along the index are missed in that case.
+async fn fast_field_values<T>(
+    indexer: &Indexer<T>,
+    field: Field,
+    filter: Option<(Field, &RepoFilter)>,
+    prefix: &str,
+) -> anyhow::Result<HashMap<String, (u8, usize)>> {
+    let searcher = indexer.reader.read().await.searcher();
+    let mut values = HashMap::new();
+    let mut prefix_matches = 0;
+
+    for segment_reader in searcher.segment_readers() {
+        let reader = segment_reader.fast_fields().bytes(field)?;
+        let filter = filter
+            .map(|(field, accept)| anyhow::Ok((segment_reader.fast_fields().bytes(field)?, accept)))
+            .transpose()?;
+
+        for doc in segment_reader.doc_ids_alive() {
+            let value = String::from_utf8_lossy(reader.get_bytes(doc));
+            if values.contains_key(value.as_ref()) {
+                continue;
+            }
+
+            if let Some((filter, accept)) = &filter {
+                if !accept(&String::from_utf8_lossy(filter.get_bytes(doc))) {
+                    continue;
+                }
+            }
+
+            if let Some(rank) = rank(&value, prefix) {
+                values.insert(value.into_owned(), rank);
+
+                if rank.0 == 0 {
+                    prefix_matches += 1;
+                    if prefix_matches == MAX_VALUES {
+                        return Ok(values);
+                    }
+                }
+            }
+        }
     }
+
+    Ok(values)
+}
+
+/// Collect the names of symbols that complete `prefix`, from the files that define the best
+/// matching symbols.
+async fn symbols(
+    indexes: &Indexes,
+    repo: Option<&str>,
+    prefix: &str,
+) -> anyhow::Result<HashMap<String, (u8, usize)>> {
+    if prefix.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let expr = Expression::Term(parser::Query {
+        repo: repo.map(|r| Literal::Plain(r.into())),
+        target: Some(Target::Symbol(Literal::Plain(prefix.into()))),
+        ..Default::default()
+    });
+
+    let collector = (TopDocs::with_limit(SYMBOL_FILES), MultiCollector::new());
+    let results = indexes.file.query(&expr, &ContentReader, collector).await?;
+
+    let mut values = HashMap::new();
+    for doc in results.docs {
+        for symbol in doc.symbol_locations.list() {
+            let Some(name) = doc
+                .content
+                .get(symbol.range.start.byte..symbol.range.end.byte)
+            else {
+                continue;
+            };
+
+            if let Some(rank) = rank(name, prefix) {
+                values.insert(name.to_owned(), rank);
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+/// How well `value` completes `prefix`, lower is better.
+///
+/// Prefix matches rank above substring matches, which rank above fuzzy matches where the
+/// characters of `prefix` merely appear in order. Shorter values rank above longer ones.
+fn rank(value: &str, prefix: &str) -> Option<(u8, usize)> {
+    let value_lower = value.to_lowercase();
+    let prefix = prefix.to_lowercase();
+
+    let tier = if value_lower.starts_with(&prefix) {
+        0
+    } else if value_lower.contains(&prefix) {
+        1
+    } else {
+        let mut chars = value_lower.chars();
+        if !prefix.chars().all(|p| chars.any(|c| c == p)) {
+            return None;
+        }
+
+        2
+    };
+
+    Some((tier, value.len()))
 }
 
 #[derive(Serialize)]
@@ -108,8 +352,9 @@ pub(super) struct AutocompleteResponse {
 
 impl super::ApiResponse for AutocompleteResponse {}
 
-const QUERY_FLAGS: &[&str; 8] = &[
-    "repo", "path", "content", "symbol", "lang", "case", "or", "open",
+const QUERY_FLAGS: &[&str; 16] = &[
+    "repo", "path", "content", "symbol", "lang", "case", "or", "open", "author", "modified", "ast",
+    "kind", "size", "lines", "is", "ext",
 ];
 
 // List of common languages
@@ -181,3 +426,47 @@ const COMMON_LANGUAGES: &[&str] = &[
     "objective-c++",
     "actionscript",
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_label() {
+        assert_eq!(
+            value_under_cursor("api repo:bl", None),
+            Some((ValueLabel::Repo, "bl"))
+        );
+        assert_eq!(
+            value_under_cursor("repo:bloop path:src/que parse", Some(23)),
+            Some((ValueLabel::Path, "src/que"))
+        );
+        assert_eq!(
+            value_under_cursor("repo:bloop path:src/que parse", Some(18)),
+            Some((ValueLabel::Path, "sr"))
+        );
+        assert_eq!(
+            value_under_cursor("(-branch:ma or foo)", Some(11)),
+            Some((ValueLabel::Branch, "ma"))
+        );
+        assert_eq!(
+            value_under_cursor("symbol:function:pa", None),
+            Some((ValueLabel::Symbol, "pa"))
+        );
+        assert_eq!(
+            value_under_cursor("lang:ü foo", Some(6)),
+            Some((ValueLabel::Lang, "ü"))
+        );
+        assert_eq!(value_under_cursor("repo:bloop parse", None), None);
+        assert_eq!(value_under_cursor("content:foo", None), None);
+    }
+
+    #[test]
+    fn rank_values() {
+        assert_eq!(rank("src/query/parser.rs", "src/q"), Some((0, 19)));
+        assert_eq!(rank("src/query/parser.rs", "PARSER"), Some((1, 19)));
+        assert_eq!(rank("src/query/parser.rs", "qparse"), Some((2, 19)));
+        assert_eq!(rank("src/query/parser.rs", "rsq"), None);
+        assert_eq!(rank("main", ""), Some((0, 4)));
+    }
+}
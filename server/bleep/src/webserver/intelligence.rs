@@ -1,19 +1,28 @@
-use std::{ops::Not, sync::Arc};
+use std::{collections::BTreeMap, ops::Not, sync::Arc};
 
 use super::prelude::*;
 use crate::{
+    db::SymbolTable,
     indexes::{reader::ContentDocument, Indexes},
     intelligence::{
         code_navigation::{CodeNavigationContext, FileSymbols, Occurrence, OccurrenceKind, Token},
-        Language, NodeKind, TSLanguage,
+        scip, Language, NodeKind, TSLanguage,
     },
     repo::RepoRef,
     snippet::Snipper,
     text_range::TextRange,
+    Application,
 };
 
-use axum::{extract::Query, response::IntoResponse, Extension};
+use axum::{
+    body::{Bytes, Full},
+    extract::Query,
+    http::header,
+    response::{IntoResponse, Response},
+    Extension,
+};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 /// The request made to the `local-intel` endpoint.
 #[derive(Debug, Deserialize)]
@@ -36,18 +45,29 @@ pub(super) struct TokenInfoRequest {
 #[derive(Serialize, Debug)]
 pub(super) struct TokenInfoResponse {
     data: Vec<FileSymbols>,
+
+    /// Definitions from other indexed repositories, when none could be found in this one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    external: Vec<RepoFileSymbols>,
 }
 
-impl TokenInfoResponse {
-    fn new(data: Vec<FileSymbols>) -> Self {
-        Self { data }
-    }
+/// Symbols in a file that belongs to another repository.
+#[derive(Serialize, Debug)]
+pub(super) struct RepoFileSymbols {
+    repo_ref: String,
+
+    #[serde(flatten)]
+    symbols: FileSymbols,
 }
 
 impl super::ApiResponse for TokenInfoResponse {}
 
+/// The maximum number of files in other repositories to return definitions from.
+const MAX_EXTERNAL_FILES: usize = 20;
+
 pub(super) async fn handle(
     Query(payload): Query<TokenInfoRequest>,
+    Extension(app): Extension<Application>,
     Extension(indexes): Extension<Arc<Indexes>>,
 ) -> Result<impl IntoResponse> {
     let repo_ref = payload.repo_ref.parse::<RepoRef>().map_err(Error::user)?;
@@ -64,50 +84,311 @@ pub(super) async fn handle(
         .await
         .map_err(Error::user)?
         .ok_or_else(|| Error::user("path not found").with_status(StatusCode::NOT_FOUND))?;
-    let lang = source_document.lang.as_deref();
-    let all_docs = {
-        let associated_langs = match lang.map(TSLanguage::from_id) {
-            Some(Language::Supported(config)) => config.language_ids,
-            _ => &[],
-        };
-        indexes
-            .file
-            .by_repo(
+    let associated_langs = match source_document.lang.as_deref().map(TSLanguage::from_id) {
+        Some(Language::Supported(config)) => config.language_ids,
+        _ => &[],
+    };
+    let token_text = source_document
+        .content
+        .get(payload.start..payload.end)
+        .unwrap_or_default()
+        .to_owned();
+
+    let symbols = SymbolTable::new(&app.sql);
+
+    // precise navigation from a SCIP index takes precedence over scope graphs
+    let scip = scip::token_info(
+        &app,
+        &repo_ref,
+        &source_document,
+        payload.start..payload.end,
+        payload.branch.as_deref(),
+    )
+    .await;
+
+    let (data, is_definition) = match scip {
+        Some(info) => (info.data, info.is_definition),
+        None => {
+            let all_docs = match symbol_table_docs(
+                &indexes,
+                &symbols,
                 &repo_ref,
-                associated_langs.iter(),
+                &token_text,
+                associated_langs,
                 payload.branch.as_deref(),
+                &source_document,
             )
             .await
-    };
+            {
+                Some(docs) => docs,
+                // this file has not been added to the symbol table yet, load everything
+                None => {
+                    indexes
+                        .file
+                        .by_repo(
+                            &repo_ref,
+                            associated_langs.iter(),
+                            payload.branch.as_deref(),
+                        )
+                        .await
+                }
+            };
+
+            let source_document_idx = all_docs
+                .iter()
+                .position(|doc| doc.relative_path == payload.relative_path)
+                .ok_or(Error::internal("invalid language"))?;
+
+            let ctx = CodeNavigationContext {
+                repo_ref: repo_ref.clone(),
+                token,
+                all_docs,
+                source_document_idx,
+            };
+
+            let is_definition = source_document
+                .symbol_locations
+                .scope_graph()
+                .and_then(|graph| {
+                    graph
+                        .node_by_range(payload.start, payload.end)
+                        .map(|idx| matches!(graph.graph[idx], NodeKind::Def(_)))
+                })
+                .unwrap_or_default();
 
-    let source_document_idx = all_docs
-        .iter()
-        .position(|doc| doc.relative_path == payload.relative_path)
-        .ok_or(Error::internal("invalid language"))?;
+            (ctx.token_info(), is_definition)
+        }
+    };
 
-    let ctx = CodeNavigationContext {
-        repo_ref: repo_ref.clone(),
-        token,
-        all_docs,
-        source_document_idx,
+    // only look for definitions in other repositories if this is a reference that we couldn't
+    // resolve within its own repository
+    let external = if is_definition
+        || data
+            .iter()
+            .flat_map(|file| &file.data)
+            .any(Occurrence::is_definition)
+    {
+        Vec::new()
+    } else {
+        external_definitions(&indexes, &symbols, &repo_ref, &token_text, associated_langs).await
     };
 
-    let data = ctx.token_info();
-    if data.is_empty() {
+    let data = if data.is_empty() {
         search_nav(
             Arc::clone(&indexes),
             &repo_ref,
-            ctx.active_token_text(),
-            ctx.active_token_range(),
+            &token_text,
+            payload.start..payload.end,
             payload.branch.as_deref(),
             &source_document,
         )
-        .await
-        .map(TokenInfoResponse::new)
-        .map(json)
+        .await?
     } else {
-        Ok(json(TokenInfoResponse { data }))
+        data
+    };
+
+    Ok(json(TokenInfoResponse { data, external }))
+}
+
+/// The request made to the `scip` endpoint, with the protobuf encoded index as its body.
+#[derive(Debug, Deserialize)]
+pub(super) struct ScipUploadRequest {
+    /// The repo_ref of the indexed repository
+    repo_ref: String,
+
+    /// The commit that was indexed. Indexes uploaded without one are used with any checkout
+    commit: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub(super) struct ScipUploadResponse {
+    /// The number of occurrences in the index
+    occurrences: usize,
+}
+
+impl super::ApiResponse for ScipUploadResponse {}
+
+/// Store the SCIP index of a repository, for precise code navigation.
+pub(super) async fn upload_scip(
+    Query(params): Query<ScipUploadRequest>,
+    Extension(app): Extension<Application>,
+    body: Bytes,
+) -> Result<impl IntoResponse> {
+    let repo_ref = params.repo_ref.parse::<RepoRef>().map_err(Error::user)?;
+    if !app.repo_pool.contains(&repo_ref) {
+        return Err(Error::user("repo not found").with_status(StatusCode::NOT_FOUND));
     }
+
+    let index = scip::decode(&body).map_err(Error::user)?;
+    let occurrences = index.occurrences.len();
+    index.store(&app, &repo_ref, params.commit, None).await?;
+
+    Ok(json(ScipUploadResponse { occurrences }))
+}
+
+#[derive(Deserialize)]
+pub(super) struct ScipExportRequest {
+    /// The repo_ref of the repository to export
+    repo_ref: String,
+
+    /// The branch to export, `HEAD` if none is given
+    branch: Option<String>,
+}
+
+/// Download the scope graphs of every file in a repository as a SCIP index.
+pub(super) async fn export_scip(
+    Query(params): Query<ScipExportRequest>,
+    Extension(app): Extension<Application>,
+    Extension(indexes): Extension<Arc<Indexes>>,
+) -> Result<Response<Full<Bytes>>> {
+    let repo_ref = params.repo_ref.parse::<RepoRef>().map_err(Error::user)?;
+    let disk_path = app
+        .repo_pool
+        .read(&repo_ref, |_, repo| repo.disk_path.clone())
+        .ok_or_else(|| Error::user("repo not found").with_status(StatusCode::NOT_FOUND))?;
+
+    let index = scip::export(
+        &indexes.file,
+        &repo_ref,
+        &disk_path,
+        params.branch.as_deref(),
+    )
+    .await?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"index.scip\"",
+        )
+        .body(index.into())
+        .map_err(Error::internal)
+}
+
+/// Load only the documents that can take part in navigating `name`, according to the symbol
+/// table, starting with the source document.
+///
+/// Returns `None` if the source document is missing from the symbol table.
+pub(crate) async fn symbol_table_docs(
+    indexes: &Indexes,
+    symbols: &SymbolTable<'_>,
+    repo_ref: &RepoRef,
+    name: &str,
+    langs: &[&str],
+    branch: Option<&str>,
+    source_document: &ContentDocument,
+) -> Option<Vec<ContentDocument>> {
+    let repo_str = repo_ref.to_string();
+    let indexed = symbols
+        .contains_file(&repo_str, &source_document.relative_path)
+        .await
+        .map_err(|err| warn!(%err, "failed to read symbol table"))
+        .ok()?;
+
+    if !indexed {
+        return None;
+    }
+
+    let paths = symbols
+        .files_declaring(&repo_str, name)
+        .await
+        .map_err(|err| warn!(%err, "failed to read symbol table"))
+        .ok()?;
+
+    let mut docs = vec![source_document.clone()];
+    for path in paths {
+        if path == source_document.relative_path {
+            continue;
+        }
+
+        if let Ok(Some(doc)) = indexes.file.by_path(repo_ref, &path, branch).await {
+            if is_associated(&doc, langs) {
+                docs.push(doc);
+            }
+        }
+    }
+
+    Some(docs)
+}
+
+/// Find top-level definitions of `name` in other repositories, using the symbol table.
+async fn external_definitions(
+    indexes: &Indexes,
+    symbols: &SymbolTable<'_>,
+    repo_ref: &RepoRef,
+    name: &str,
+    langs: &[&str],
+) -> Vec<RepoFileSymbols> {
+    let repo_str = repo_ref.to_string();
+    let definitions = match symbols.definitions(name).await {
+        Ok(definitions) => definitions,
+        Err(err) => {
+            warn!(%err, "failed to read symbol table");
+            return Vec::new();
+        }
+    };
+
+    let mut files = BTreeMap::<_, Vec<_>>::new();
+    for def in definitions {
+        if def.repo_ref != repo_str && langs.iter().any(|l| l.eq_ignore_ascii_case(&def.lang)) {
+            files
+                .entry((def.repo_ref, def.relative_path))
+                .or_default()
+                .push(def.entry.bytes);
+        }
+    }
+
+    let mut external = Vec::new();
+    for ((repo_ref, relative_path), ranges) in files.into_iter().take(MAX_EXTERNAL_FILES) {
+        let Ok(other_repo) = repo_ref.parse::<RepoRef>() else {
+            continue;
+        };
+
+        let Ok(Some(doc)) = indexes
+            .file
+            .by_path(&other_repo, &relative_path, None)
+            .await
+        else {
+            continue;
+        };
+
+        let mut data = ranges
+            .into_iter()
+            .filter(|range| range.end <= doc.content.len())
+            .map(|range| {
+                let snippet = Snipper::default()
+                    .expand(range.clone(), &doc.content, &doc.line_end_indices)
+                    .reify(&doc.content, &[]);
+
+                Occurrence {
+                    kind: OccurrenceKind::Definition,
+                    range: TextRange::from_byte_range(range, &doc.line_end_indices),
+                    snippet,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        data.sort_by_key(|occurrence| occurrence.range.start.byte);
+
+        if !data.is_empty() {
+            external.push(RepoFileSymbols {
+                repo_ref,
+                symbols: FileSymbols {
+                    file: relative_path,
+                    data,
+                },
+            });
+        }
+    }
+
+    external
+}
+
+fn is_associated(doc: &ContentDocument, langs: &[&str]) -> bool {
+    doc.lang
+        .as_deref()
+        .map(|lang| langs.iter().any(|l| l.eq_ignore_ascii_case(lang)))
+        .unwrap_or_default()
 }
 
 async fn search_nav(
@@ -365,6 +646,7 @@ mod tests {
                     }],
                 },
             ],
+            external: vec![],
         })
         .unwrap();
 
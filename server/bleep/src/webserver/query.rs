@@ -1,7 +1,17 @@
-use axum::extract::State;
+use axum::{
+    body::StreamBody,
+    extract::State,
+    http::header,
+    response::{sse, Response},
+};
+use futures::{Stream, StreamExt};
 
 use super::prelude::*;
-use crate::{db::QueryLog, query::execute::ApiQuery, Application};
+use crate::{
+    db::QueryLog,
+    query::{execute::ApiQuery, export::ExportParams, replace::ReplaceParams},
+    Application,
+};
 
 pub(super) async fn handle(
     Query(api_params): Query<ApiQuery>,
@@ -16,3 +26,78 @@ pub(super) async fn handle(
         .map(json)
         .map_err(super::Error::from)
 }
+
+/// Explain how a query is parsed, planned and executed, without returning any results.
+pub(super) async fn explain(
+    Query(api_params): Query<ApiQuery>,
+    Extension(indexes): Extension<Arc<Indexes>>,
+) -> impl IntoResponse {
+    Arc::new(api_params)
+        .explain(indexes)
+        .await
+        .map(json)
+        .map_err(super::Error::from)
+}
+
+/// Preview a regex search-and-replace over the files matched by a query, as unified diffs.
+///
+/// Takes the same parameters as `handle`, plus the replacement. Repositories are never modified.
+pub(super) async fn replace(
+    Query(api_params): Query<ApiQuery>,
+    Query(replace_params): Query<ReplaceParams>,
+    Extension(indexes): Extension<Arc<Indexes>>,
+) -> impl IntoResponse {
+    Arc::new(api_params)
+        .replace(indexes, replace_params)
+        .await
+        .map(json)
+        .map_err(super::Error::from)
+}
+
+/// Download every match of a content search as JSON Lines, CSV or SARIF, instead of a single
+/// page of results.
+///
+/// Takes the same parameters as `handle`, plus the `format`. Paging parameters are ignored.
+pub(super) async fn export(
+    Query(api_params): Query<ApiQuery>,
+    Query(export_params): Query<ExportParams>,
+    Extension(indexes): Extension<Arc<Indexes>>,
+) -> Result<Response<StreamBody<impl Stream<Item = anyhow::Result<String>>>>> {
+    let format = export_params.format;
+    let results = Arc::new(api_params)
+        .export(indexes, format)
+        .map_err(Error::user)?;
+
+    let disposition = format!(
+        "attachment; filename=\"results.{}\"",
+        format.file_extension()
+    );
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, format.content_type())
+        .header(header::CONTENT_DISPOSITION, disposition)
+        .body(StreamBody::new(results))
+        .map_err(Error::internal)
+}
+
+/// Like `handle`, but sends results over an SSE stream as each index finishes searching.
+///
+/// Every event holds a single `QueryResult`, and the last result is a summary with stats and
+/// paging metadata for the whole query. The stream is terminated by a `[DONE]` event.
+pub(super) async fn stream(
+    Query(api_params): Query<ApiQuery>,
+    Extension(indexes): Extension<Arc<Indexes>>,
+    State(app): State<Application>,
+) -> Result<sse::Sse<impl Stream<Item = anyhow::Result<sse::Event>>>> {
+    QueryLog::new(&app.sql).insert(&api_params.q).await?;
+
+    let results = Arc::new(api_params).query_stream(indexes).map(|result| {
+        sse::Event::default()
+            .json_data(result.map_err(|e| e.to_string()))
+            .map_err(anyhow::Error::new)
+    });
+
+    let done = futures::stream::once(async { Ok(sse::Event::default().data("[DONE]")) });
+
+    Ok(sse::Sse::new(results.chain(done)))
+}
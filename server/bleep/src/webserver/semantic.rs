@@ -25,14 +25,18 @@ pub(super) async fn complex_search(
             .await
             .map(json)
             .map_err(super::Error::from),
+        Ok(ParsedQuery::Hybrid(q)) => semantic::hybrid::execute(semantic, indexes, q, args)
+            .await
+            .map(json)
+            .map_err(super::Error::from),
         Ok(ParsedQuery::Grep(q)) => Arc::new(args)
             .query_with(indexes, q)
             .await
             .map(json)
             .map_err(super::Error::from),
         Err(err) => {
-            error!(?err, "qdrant query failed");
-            Err(Error::new(ErrorKind::UpstreamService, "error"))
+            error!(?err, "failed to parse semantic query");
+            Err(Error::user(err))
         }
     }
 }